@@ -0,0 +1,135 @@
+// Optional compressed mode: instead of one UserVault PDA per depositor, user balances are committed
+// as leaves of an spl-account-compression concurrent Merkle tree owned by a per-vault PDA authority.
+use solana_program::{
+  instruction::{AccountMeta, Instruction},          // For building the CPI instructions
+  keccak,                                          // Leaf hashing, matches what the compression program expects
+  pubkey::Pubkey,                                  // For identifying accounts and programs
+};
+
+// Program id of spl-account-compression (the concurrent Merkle tree program)
+pub const ACCOUNT_COMPRESSION_ID: Pubkey = solana_program::pubkey!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+
+// Program id of the SPL Noop program the compression program logs changelogs through
+pub const NOOP_ID: Pubkey = solana_program::pubkey!("noopb9bkMVfRPU8AsbpTUg8AWkHHKwYDrHv5QyftLQv");
+
+// Seed prefix of the PDA that owns (is the authority of) a vault's Merkle tree
+pub const TREE_AUTHORITY_SEED: &[u8] = b"tree_authority";
+
+// Anchor instruction discriminators: first 8 bytes of sha256("global:<instruction name>")
+const INIT_EMPTY_MERKLE_TREE_DISCRIMINATOR: [u8; 8] = [191, 11, 119, 7, 180, 107, 220, 110];
+const REPLACE_LEAF_DISCRIMINATOR: [u8; 8] = [204, 165, 76, 100, 73, 147, 0, 128];
+
+// Derive the tree authority PDA for a given vault state account
+pub fn find_tree_authority(vault: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[TREE_AUTHORITY_SEED, vault.as_ref()], program_id)
+}
+
+// Hash a user's balance into a tree leaf. Binding the vault and user keys into the leaf means a proof for
+// one user's leaf can never be replayed to move another user's balance, and binding the index keeps a user's
+// leaf from being copied into a second slot.
+pub fn leaf_hash(vault: &Pubkey, user: &Pubkey, index: u32, balance: u64) -> [u8; 32] {
+  keccak::hashv(&[vault.as_ref(), user.as_ref(), &index.to_le_bytes(), &balance.to_le_bytes()]).to_bytes()
+}
+
+// Build the CPI that initializes an empty concurrent Merkle tree
+// Accounts: merkle_tree [writable], authority [signer], noop program []
+pub fn init_empty_merkle_tree(
+  merkle_tree: &Pubkey,
+  authority: &Pubkey,
+  max_depth: u32,
+  max_buffer_size: u32,
+) -> Instruction {
+  let mut data = Vec::with_capacity(8 + 4 + 4);
+  data.extend_from_slice(&INIT_EMPTY_MERKLE_TREE_DISCRIMINATOR);
+  data.extend_from_slice(&max_depth.to_le_bytes());                   // Borsh encodes u32 as 4 little-endian bytes
+  data.extend_from_slice(&max_buffer_size.to_le_bytes());
+
+  Instruction {
+    program_id: ACCOUNT_COMPRESSION_ID,
+    accounts: vec![
+      AccountMeta::new(*merkle_tree, false),
+      AccountMeta::new_readonly(*authority, true),
+      AccountMeta::new_readonly(NOOP_ID, false),
+    ],
+    data,
+  }
+}
+
+// Build the CPI that swaps `previous_leaf` for `new_leaf` at `index`, verified against `root`
+// Accounts: merkle_tree [writable], authority [signer], noop program [], followed by the proof nodes []
+pub fn replace_leaf(
+  merkle_tree: &Pubkey,
+  authority: &Pubkey,
+  root: [u8; 32],
+  previous_leaf: [u8; 32],
+  new_leaf: [u8; 32],
+  index: u32,
+  proof: &[Pubkey],
+) -> Instruction {
+  let mut data = Vec::with_capacity(8 + 32 + 32 + 32 + 4);
+  data.extend_from_slice(&REPLACE_LEAF_DISCRIMINATOR);
+  data.extend_from_slice(&root);
+  data.extend_from_slice(&previous_leaf);
+  data.extend_from_slice(&new_leaf);
+  data.extend_from_slice(&index.to_le_bytes());
+
+  let mut accounts = vec![
+    AccountMeta::new(*merkle_tree, false),
+    AccountMeta::new_readonly(*authority, true),
+    AccountMeta::new_readonly(NOOP_ID, false),
+  ];
+  // Proof nodes are passed as read-only accounts, the same way the compression program's clients do it
+  accounts.extend(proof.iter().map(|node| AccountMeta::new_readonly(*node, false)));
+
+  Instruction {
+    program_id: ACCOUNT_COMPRESSION_ID,
+    accounts,
+    data,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use solana_program::hash::hash;
+
+  #[test]
+  fn discriminators_are_the_anchor_sighashes() {
+    assert_eq!(hash(b"global:init_empty_merkle_tree").to_bytes()[..8], INIT_EMPTY_MERKLE_TREE_DISCRIMINATOR);
+    assert_eq!(hash(b"global:replace_leaf").to_bytes()[..8], REPLACE_LEAF_DISCRIMINATOR);
+  }
+
+  #[test]
+  fn leaf_hash_binds_vault_user_and_index() {
+    let (vault, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+    assert_ne!(leaf_hash(&vault, &user, 0, 5), leaf_hash(&user, &vault, 0, 5));
+    assert_ne!(leaf_hash(&vault, &user, 0, 5), leaf_hash(&vault, &user, 0, 6));
+    assert_ne!(leaf_hash(&vault, &user, 0, 5), leaf_hash(&vault, &user, 1, 5));
+  }
+
+  // Root of a tree over `leaves` (a power of two), hashing each pair the way the compression program does
+  fn root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+      level = level.chunks(2).map(|pair| keccak::hashv(&[&pair[0], &pair[1]]).to_bytes()).collect();
+    }
+    level[0]
+  }
+
+  #[test]
+  fn updating_a_leaf_changes_the_root() {
+    let vault = Pubkey::new_unique();
+    let users: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+    let mut leaves: Vec<[u8; 32]> = users.iter().zip(0u32..).map(|(user, index)| leaf_hash(&vault, user, index, 100)).collect();
+    let before = root(&leaves);
+
+    // A deposit rewrites only the depositor's leaf, which is enough to move the root
+    leaves[2] = leaf_hash(&vault, &users[2], 2, 150);
+    let after = root(&leaves);
+    assert_ne!(before, after);
+
+    // Writing the old balance back restores the old root, so the root commits to the balances and nothing else
+    leaves[2] = leaf_hash(&vault, &users[2], 2, 100);
+    assert_eq!(root(&leaves), before);
+  }
+}
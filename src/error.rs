@@ -0,0 +1,100 @@
+// Custom program errors, so clients get stable codes instead of overloaded generic ProgramError variants
+use num_derive::FromPrimitive;                                            // Lets clients map a custom code back to a variant
+use solana_program::{
+  decode_error::DecodeError,                                              // Ties the enum to ProgramError::Custom decoding
+  program_error::ProgramError,                                            // Standard error type every handler returns
+};
+use thiserror::Error;                                                     // Derives Display/Error from the messages below
+
+// Errors returned by the vault program. Variants are encoded as ProgramError::Custom(variant as u32),
+// so new variants must only ever be appended to keep existing codes stable.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum VaultError {
+  #[error("Arithmetic overflow")]
+  Overflow,                                     // 0: A checked add/sub on a balance or total overflowed
+  #[error("Account does not match the expected program derived address")]
+  InvalidPda,                                   // 1: A PDA the client passed in doesn't match its seeds
+  #[error("Vault is paused")]
+  VaultPaused,                                  // 2: The vault is not accepting this operation right now
+  #[error("Signer is not authorized for this operation")]
+  Unauthorized,                                 // 3: Signer isn't the vault owner, position user, or arbiter required
+  #[error("Amount must be greater than zero")]
+  AmountZero,                                   // 4: Zero-amount deposits, withdrawals and requests are rejected
+  #[error("Withdrawals from this position must be released by its arbiter")]
+  ArbiterRequired,                              // 5: Plain withdraw attempted on an arbiter-held position
+  #[error("No withdrawal request is pending")]
+  NoPendingWithdrawal,                          // 6: Arbiter release attempted without a prior user request
+  #[error("Instruction is disabled for this vault")]
+  InstructionDisabled,                          // 7: The vault owner cleared this instruction's enabled bit
+  #[error("Destination token account is not initialized")]
+  DestinationNotInitialized,                    // 8: Withdraw destination hasn't been initialized by the token program
+  #[error("Token account mint does not match the vault's mint")]
+  MintMismatch,                                 // 9: A token account passed in holds a different mint than the vault
+  #[error("Vault has no deposits to distribute rewards over")]
+  NoDepositsToReward,                           // 10: DistributeRewards on an empty vault
+  #[error("Rewards were already distributed this epoch")]
+  RewardsAlreadyDistributed,                    // 11: A second DistributeRewards within the same epoch
+  #[error("Instruction data is empty")]
+  EmptyInstruction,                             // 12: No tag byte at all
+  #[error("Unknown instruction tag")]
+  UnknownInstruction,                           // 13: Tag byte doesn't match any VaultInstruction
+  #[error("Instruction data is too short for its fields")]
+  TruncatedInstruction,                         // 14: A fixed-width field (e.g. the u64 amount) is cut off
+  #[error("Withdrawal is below the vault's minimum")]
+  WithdrawBelowMinimum,                         // 15: Partial withdrawal smaller than Vault::min_withdraw
+  #[error("Deposit exceeds the vault's single-deposit limit")]
+  SingleDepositTooLarge,                        // 16: Deposit larger than Vault::max_single_deposit
+  #[error("New owner is the same as the current owner")]
+  OwnerUnchanged,                               // 17: TransferOwnership to the current owner
+  #[error("The same account was passed for two distinct signer roles")]
+  DuplicateSigner,                              // 18: e.g. the user also standing in as the current arbiter
+  #[error("Deposit would exceed the vault's deposit cap")]
+  CapExceeded,                                  // 19: total_deposits + amount is over Vault::deposit_cap
+  #[error("Withdrawal fee is above the allowed maximum")]
+  FeeTooHigh,                                   // 20: InitVault with withdrawal_fee_bps over MAX_WITHDRAWAL_FEE_BPS
+  #[error("Vault token account already holds tokens")]
+  PreFundedAccount,                             // 21: InitVault with a non-empty vault token account
+  #[error("More accounts were passed than the instruction uses")]
+  TooManyAccounts,                              // 22: Only with the strict-accounts feature
+  #[error("Vault-wide withdrawal limit for this window reached")]
+  VaultRateLimited,                             // 23: Withdrawal over what's left of max_vault_withdraw_per_window
+  #[error("Withdrawal is locked until the position vests and the lock after the latest deposit has passed")]
+  Locked,                                       // 24: Before UserVault::locked_until, or sooner than Vault::lock_duration after the last deposit
+  #[error("Instruction data has bytes past its last field")]
+  TrailingInstructionData,                      // 25: Buffer longer than the variant's encoding
+  #[error("Vault token account holds less than the withdrawal amount")]
+  InsufficientVaultLiquidity,                   // 26: Position covers the withdrawal but the vault's token balance doesn't
+  #[error("Not enough admins signed")]
+  ThresholdNotMet,                              // 27: Owner-gated instruction with fewer admin signers than Vault::admin_threshold
+  #[error("Account must be writable")]
+  AccountNotWritable,                           // 28: Passed read-only where instruction::account_flags marks it writable
+  #[error("Vault configuration is frozen")]
+  ConfigFrozen,                                 // 29: Config-changing instruction on a vault after FreezeConfig
+  #[error("Position still holds a balance")]
+  PositionNotEmpty,                             // 30: CloseUserVault on a position with a deposit or unclaimed rewards
+}
+
+impl From<VaultError> for ProgramError {
+  fn from(e: VaultError) -> Self {
+    ProgramError::Custom(e as u32)
+  }
+}
+
+impl<T> DecodeError<T> for VaultError {
+  fn type_of() -> &'static str {
+    "VaultError"
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use num_traits::FromPrimitive;
+
+  #[test]
+  fn codes_are_stable_and_decode_back() {
+    assert_eq!(ProgramError::from(VaultError::Overflow), ProgramError::Custom(0));
+    assert_eq!(ProgramError::from(VaultError::PositionNotEmpty), ProgramError::Custom(30));
+    assert_eq!(VaultError::from_u32(3), Some(VaultError::Unauthorized));
+  }
+}
@@ -0,0 +1,134 @@
+// Structured events for indexers. Each event is a one-byte type tag followed by its fixed-width fields,
+// little-endian, in declaration order; decode_event is the reference parser for that format.
+use solana_program::{
+  log::sol_log_data,                                 // Logs raw bytes as a base64 "Program data:" line
+  pubkey::Pubkey,                                    // For identifying accounts
+};
+use std::convert::TryInto;                           // Converts the field slices into fixed-size arrays
+
+// A user's deposit into a vault
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepositEvent {
+  pub user: Pubkey,                          // The depositor
+  pub vault: Pubkey,                         // The vault state account
+  pub amount: u64,                           // Tokens deposited
+  pub new_total: u64,                        // Vault::total_deposits after the deposit
+}
+
+// A user's withdrawal from a vault
+#[derive(Clone, Debug, PartialEq)]
+pub struct WithdrawEvent {
+  pub user: Pubkey,                          // The withdrawing user
+  pub vault: Pubkey,                         // The vault state account
+  pub amount: u64,                           // Tokens taken out of the position, fee included
+  pub fee: u64,                              // Part of `amount` paid to the fee treasury
+  pub new_total: u64,                        // Vault::total_deposits after the withdrawal
+}
+
+// Every event the program emits, keyed by its leading type byte
+#[derive(Clone, Debug, PartialEq)]
+pub enum VaultEvent {
+  Deposit(DepositEvent),                     // 0
+  Withdraw(WithdrawEvent),                   // 1
+}
+
+impl VaultEvent {
+  //The type byte this event is encoded with
+  pub fn tag(&self) -> u8 {
+    match self {
+      VaultEvent::Deposit(_) => 0,
+      VaultEvent::Withdraw(_) => 1,
+    }
+  }
+
+  //Pack the event into the bytes decode_event reads: the type byte followed by the event's fields
+  pub fn pack(&self) -> Vec<u8> {
+    let mut buf = vec![self.tag()];
+    match self {
+      VaultEvent::Deposit(event) => {
+        buf.extend_from_slice(event.user.as_ref());
+        buf.extend_from_slice(event.vault.as_ref());
+        buf.extend_from_slice(&event.amount.to_le_bytes());
+        buf.extend_from_slice(&event.new_total.to_le_bytes());
+      }
+      VaultEvent::Withdraw(event) => {
+        buf.extend_from_slice(event.user.as_ref());
+        buf.extend_from_slice(event.vault.as_ref());
+        buf.extend_from_slice(&event.amount.to_le_bytes());
+        buf.extend_from_slice(&event.fee.to_le_bytes());
+        buf.extend_from_slice(&event.new_total.to_le_bytes());
+      }
+    }
+    buf
+  }
+
+  //Log the packed event with sol_log_data, where indexers pick it up as a "Program data:" line
+  pub fn emit(&self) {
+    sol_log_data(&[&self.pack()]);
+  }
+}
+
+// Parse an event payload. Unknown type bytes and truncated payloads yield None, so indexers can skip
+// log lines from other programs or newer event types without failing.
+pub fn decode_event(bytes: &[u8]) -> Option<VaultEvent> {
+  let (&tag, rest) = bytes.split_first()?;
+  let pubkey_at = |offset: usize| rest.get(offset..offset + 32).and_then(|slice| slice.try_into().ok()).map(Pubkey::new_from_array);
+  let u64_at = |offset: usize| rest.get(offset..offset + 8).and_then(|slice| slice.try_into().ok()).map(u64::from_le_bytes);
+
+  Some(match tag {
+    0 => VaultEvent::Deposit(DepositEvent {
+      user: pubkey_at(0)?,
+      vault: pubkey_at(32)?,
+      amount: u64_at(64)?,
+      new_total: u64_at(72)?,
+    }),
+    1 => VaultEvent::Withdraw(WithdrawEvent {
+      user: pubkey_at(0)?,
+      vault: pubkey_at(32)?,
+      amount: u64_at(64)?,
+      fee: u64_at(72)?,
+      new_total: u64_at(80)?,
+    }),
+    _ => return None,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn events_round_trip_through_decode_event() {
+    let deposit = VaultEvent::Deposit(DepositEvent {
+      user: Pubkey::new_unique(),
+      vault: Pubkey::new_unique(),
+      amount: 10,
+      new_total: 110,
+    });
+    let withdraw = VaultEvent::Withdraw(WithdrawEvent {
+      user: Pubkey::new_unique(),
+      vault: Pubkey::new_unique(),
+      amount: 10,
+      fee: 1,
+      new_total: 100,
+    });
+
+    assert_eq!(decode_event(&deposit.pack()), Some(deposit));
+    assert_eq!(decode_event(&withdraw.pack()), Some(withdraw));
+  }
+
+  #[test]
+  fn unknown_or_truncated_payloads_decode_to_none() {
+    assert_eq!(decode_event(&[]), None);
+    assert_eq!(decode_event(&[9; 81]), None);
+
+    let packed = VaultEvent::Deposit(DepositEvent {
+      user: Pubkey::new_unique(),
+      vault: Pubkey::new_unique(),
+      amount: 1,
+      new_total: 1,
+    })
+    .pack();
+    assert_eq!(decode_event(&packed[..packed.len() - 1]), None);
+  }
+}
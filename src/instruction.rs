@@ -1,64 +1,1843 @@
 use solana_program::{
   instruction::{AccountMeta, Instruction},          // For building instructions to send to the blockchain
+  program_error::ProgramError,                     // Error type returned when instruction data can't be decoded
   pubkey::Pubkey,                                  // For identifying accounts and programs
+  system_program,                                  // System program id, passed to instructions that create accounts
+  sysvar,                                          // Sysvar ids (rent)
 };
+use crate::error::VaultError;                     // Distinct decode errors for empty, unknown and truncated instructions
+use crate::pda;                                   // PDA derivations the builders fill in
+use crate::state::{unpack_admins, FeeTier, ADMINS_LEN, ADMIN_COUNT, FEE_TIERS_LEN, FEE_TIER_COUNT}; // Layouts shared with the Vault account
 use std::convert::TryInto;                        // Trait from the std lib used to safely convert between types especially when dealing with raw bytes
 
 //Vault Instructions
+#[derive(Clone, Debug, PartialEq)]
 pub enum VaultInstruction {
   //initialize a new vault
   //Accounts:
-  //0. [signer] The vault creator (owner)
-  //1. [writable] The vault account (PDA)
+  //0. [signer, writable] The vault creator (owner), pays for the vault state account
+  //1. [writable] The vault account (PDA of ["vault_state", owner, mint])
   //2. [] The token Mint
   //3. [writable] The vault token account (PDA SPL token account)
   //4. [] Rent sysvar
   //5. [] Token program
   //6. [] System program
-  InitVault,
+  //Data: 32-byte vault name, UTF-8 padded with trailing zero bytes, the u64 deposit cap (0 for unlimited),
+  //the u16 withdrawal fee in basis points, the 32-byte fee treasury token account and the i64 number of seconds
+  //a user has to wait after a deposit before withdrawing (0 for no lock)
+  //Returns the 32-byte vault authority PDA followed by its bump via return data
+  InitVault { name: [u8; 32], deposit_cap: u64, withdrawal_fee_bps: u16, fee_treasury: Pubkey, lock_duration: i64 },
 
   //Deposit tokens into the vault
+  //The depositor may be a PDA of another program: that program signs for it with invoke_signed when it CPIs into this
+  //instruction, and the signature carries through to the token transfer. A PDA that holds data can't pay for account
+  //creation, so its user vault has to be set up first with EnsureUserVault and a separate payer
+  //Accounts:
+  //0. [signer, writable] The depositor, pays for the user vault account on first deposit
+  //1. [writable] Source user token account
+  //2. [writable] Vault token account (PDA)
+  //3. [writable] Vault state account
+  //4. [writable] User vault account (PDA of ["user_vault", depositor, vault state])
+  //5. [] Token program
+  //6. [] System program
+  //7. [] Token mint (for transfer_checked)
+  //8. [] Depositor's whitelist entry (PDA of ["whitelist", vault state, depositor]), only for vaults with require_whitelisted
+  //9. [writable] Instruction fee treasury, only for vaults with an instruction fee
+  //10. [] Memo program (only required when a memo is attached; optional accounts before it that don't apply are left out)
+  //Data: amount, optionally followed by a u16 little-endian length and that many UTF-8 memo bytes
+  Deposit { amount: u64, memo: Option<String> },
+
+  //Withdraw tokens from vault
+  //Accounts:
+  //0. [signer] The user withdrawing
+  //1. [writable] Vault token account
+  //2. [writable] Destination token account
+  //3. [writable] Vault state account
+  //4. [writable] User vault account (PDA of ["user_vault", user, vault state])
+  //5. [] Token Program
+  //6. [] Token mint (for transfer_checked)
+  //7. [] Vault authority (PDA of ["vault"]), signs the transfer out of the vault token account
+  //8. [writable] Fee treasury token account (only required when the vault charges a withdrawal fee; the owner pays none)
+  //9. [writable] Instruction fee treasury, only for vaults with an instruction fee (the user must then be writable;
+  //   index 8 when no withdrawal fee applies)
+  //10. [] System program, with the instruction fee treasury
+  //Data: amount, optionally followed by one byte holding the vault authority bump (saves a find_program_address)
+  Withdraw { amount: u64, authority_bump: Option<u8> },
+
+  //Switch the vault to compressed mode, committing user balances to a concurrent Merkle tree
   //Accounts:
   //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  //2. [writable] Merkle tree account (pre-allocated, owned by the account compression program)
+  //3. [] Tree authority (PDA of ["tree_authority", vault state])
+  //4. [] Noop program
+  //5. [] Account compression program
+  InitCompressedTree { max_depth: u32, max_buffer_size: u32 },
+
+  //Deposit tokens into a compressed vault, replacing the user's balance leaf in the Merkle tree. Unavailable on every
+  //vault for now (state::UNAVAILABLE_INSTRUCTIONS): nothing can withdraw a compressed balance yet
+  //Accounts:
+  //0. [signer] The depositor
   //1. [writable] Source user token account
   //2. [writable] Vault token account (PDA)
   //3. [] Vault state account
+  //4. [writable] Merkle tree account
+  //5. [] Tree authority (PDA of ["tree_authority", vault state])
+  //6. [] Noop program
+  //7. [] Account compression program
+  //8. [] Token program
+  //9. [] Token mint (for transfer_checked)
+  //10. [] Depositor's whitelist entry (PDA of ["whitelist", vault state, depositor]), only for vaults with require_whitelisted
+  //10.. [] Proof nodes for the user's leaf, after the whitelist entry when there is one
+  CompressedDeposit { amount: u64, previous_balance: u64, index: u32, root: [u8; 32] },
+
+  //Set (or clear with Pubkey::default()) the arbiter that must release this user's withdrawals
+  //Accounts:
+  //0. [signer] The user
+  //1. [writable] User vault account
+  //2. [] Vault state account
+  //3. [signer] The current arbiter (only required when one is already set)
+  SetArbiter { arbiter: Pubkey },
+
+  //Record a withdrawal request that the arbiter can later release
+  //Accounts:
+  //0. [signer] The user
+  //1. [writable] User vault account
+  //2. [] Vault state account
+  RequestConditionalWithdraw { amount: u64 },
+
+  //Release the user's pending withdrawal request, signed by the arbiter
+  //Accounts:
+  //0. [signer] The arbiter
+  //1. [writable] Vault token account
+  //2. [writable] Destination token account (must be owned by the user)
+  //3. [writable] Vault state account
+  //4. [writable] User vault account
+  //5. [] Token program
+  //6. [] Token mint (for transfer_checked)
+  //7. [] Vault authority (PDA of ["vault"])
+  //8. [writable] Fee treasury token account (only required when the vault charges a withdrawal fee, same as Withdraw)
+  ConditionalWithdraw,
+
+  //Compute how much a Withdraw would pay the user right now, net of the withdrawal fee, and return it as a little-endian
+  //u64 via return data. Zero while the vault is paused or has Withdraw disabled
+  //Accounts:
+  //0. [] The user
+  //1. [] Vault state account
+  //2. [] User vault account
+  //3. [] Vault token account
+  GetWithdrawable,
+
+  //Replace the vault's enabled-instruction bitmask (bit n enables the instruction with tag n). Bits of unavailable
+  //instructions are dropped
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetEnabledInstructions { enabled_instructions: u64 },
+
+  //Create the user's vault PDA if it doesn't exist yet; a no-op when it already does
+  //Accounts:
+  //0. [signer, writable] The payer funding the account
+  //1. [] The user the position belongs to
+  //2. [writable] User vault account (PDA of ["user_vault", user, vault state])
+  //3. [writable] Vault state account (its user_count grows when the account is created)
+  //4. [] System program
+  EnsureUserVault,
+
+  //Fund a reward round; every depositor's balance grows in proportion to their share (owner only, once per epoch)
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Owner's source token account
+  //2. [writable] Vault token account
+  //3. [writable] Vault state account
   //4. [] Token program
-  Deposit { amount: u64 },
+  //5. [] Token mint (for transfer_checked)
+  DistributeRewards { total_reward: u64 },
 
-  //Withdraw tokens from vault
+  //Set the smallest amount a single withdrawal may take out (owner only, 0 disables the minimum)
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetMinWithdraw { min_withdraw: u64 },
+
+  //Return the vault's configured limits as a packed VaultLimits struct via return data
   //Accounts:
-  //0. [signer] Vault owner
+  //0. [] Vault state account
+  GetLimits,
+
+  //Freeze or unfreeze deposits and withdrawals (owner only)
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetPaused { paused: bool },
+
+  //Close an empty vault and send the state account's rent lamports to a destination (owner only). Refused while:
+  //- the vault still records deposits
+  //- any user vault record is open. Anyone can open one with EnsureUserVault or TransferPosition; the owner can close
+  //  an empty, arbiter-free record with CloseUserVault, but one holding a balance or an arbiter needs its user
+  //- the vault is compressed: compressed balances have no withdraw path, so a compressed vault can never be closed
+  //Extra assets and their positions aren't tracked by the vault and have no close instruction, so their rent is never
+  //reclaimed and anything still deposited in an asset is stranded once the vault is gone; drain every asset first
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  //2. [writable] Destination for the reclaimed lamports
+  CloseVault,
+
+  //Set the largest amount a single deposit may carry (owner only, 0 for unlimited)
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetMaxSingleDeposit { max_single_deposit: u64 },
+
+  //Hand vault ownership to a new authority (owner only)
+  //Accounts:
+  //0. [signer] The current vault owner
+  //1. [writable] Vault state account
+  //2. [] The new owner
+  TransferOwnership,
+
+  //Set the account allowed to bypass the withdrawal cooldown (owner only, Pubkey::default() clears it)
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetCooldownExempt { exempt: Pubkey },
+
+  //Rename the vault (owner only); the name is UTF-8 padded with trailing zero bytes
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetName { name: [u8; 32] },
+
+  //Move part or all of the caller's deposited balance to another user's position, without any token movement
+  //Accounts:
+  //0. [signer, writable] The user giving up the balance, pays for the recipient's user vault if it doesn't exist yet
+  //1. [writable] User vault account (PDA of ["user_vault", user, vault state])
+  //2. [writable] Recipient user vault account (PDA of ["user_vault", to, vault state])
+  //3. [writable] Vault state account (its user_count grows when the recipient's account is created)
+  //4. [] System program
+  //5. [] Recipient's whitelist entry (PDA of ["whitelist", vault state, to]), only for vaults with require_whitelisted
+  TransferPosition { amount: u64, to: Pubkey },
+
+  //Withdraw the caller's entire position, rewards included, reading the balance on-chain
+  //Accounts:
+  //0. [signer] The user withdrawing
   //1. [writable] Vault token account
   //2. [writable] Destination token account
+  //3. [writable] Vault state account
+  //4. [writable] User vault account (PDA of ["user_vault", user, vault state])
+  //5. [] Token Program
+  //6. [] Token mint (for transfer_checked)
+  //7. [] Vault authority (PDA of ["vault"]), signs the transfer out of the vault token account
+  //8. [writable] Fee treasury token account (only required when the vault charges a withdrawal fee; the owner pays none)
+  //9. [writable] Instruction fee treasury, only for vaults with an instruction fee (the user must then be writable;
+  //   index 8 when no withdrawal fee applies)
+  //10. [] System program, with the instruction fee treasury
+  WithdrawAll,
+
+  //Set the vault-wide withdrawal allowance per window (owner only, 0 for unlimited)
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetMaxVaultWithdrawPerWindow { max_vault_withdraw_per_window: u64 },
+
+  //Run the gating checks of a hypothetical deposit (op 0) or withdraw (op 1) of `amount` without changing anything,
+  //returning a little-endian u32 bitmask of the PREFLIGHT_* checks that would fail (0 if it would go through) via return data
+  //Accounts:
+  //0. [] The user
+  //1. [] Vault state account
+  //2. [] User vault account (PDA of ["user_vault", user, vault state], may not exist yet)
+  //3. [] Vault token account
+  //4. [] The user's whitelist entry (PDA of ["whitelist", vault state, user]), only for deposits into vaults with
+  //   require_whitelisted; leaving it out reports the user as not whitelisted
+  PreflightCheck { op: u8, amount: u64 },
+
+  //Initialize a vault and make its first deposit in one instruction, creating the owner's user vault as well
+  //Accounts:
+  //0. [signer, writable] The vault creator (owner), depositor and payer for both new accounts
+  //1. [writable] The vault account (PDA of ["vault_state", owner, mint])
+  //2. [] The token Mint
+  //3. [writable] The vault token account (PDA SPL token account)
+  //4. [] Rent sysvar
+  //5. [] Token program
+  //6. [] System program
+  //7. [writable] The owner's source token account
+  //8. [writable] The owner's user vault account (PDA of ["user_vault", owner, vault state])
+  //Data: the InitVault fields followed by the u64 amount to deposit
+  InitVaultAndDeposit { name: [u8; 32], deposit_cap: u64, withdrawal_fee_bps: u16, fee_treasury: Pubkey, lock_duration: i64, amount: u64 },
+
+  //Return the vault's user_count, the number of user vault records currently open under it (opened minus closed with
+  //CloseUserVault), as a little-endian u64 via return data
+  //Accounts:
+  //0. [] Vault state account
+  GetUserCount,
+
+  //Grow a legacy-layout vault state account to Vault::LEN and rewrite it in the current layout (owner only).
+  //A vault already on the current layout is left as is
+  //Accounts:
+  //0. [signer, writable] The vault owner, pays the rent for the added space
+  //1. [writable] Vault state account
+  //2. [] System program
+  Migrate,
+
+  //Replace the vault's size-based withdrawal fee tiers (owner only). A position pays the fee of the highest tier threshold
+  //it reaches, or the base withdrawal fee below every tier
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  //Data: FEE_TIER_COUNT tiers of u64 threshold and u16 bps, in increasing threshold order, unused tiers zeroed at the end
+  SetFeeTiers { tiers: [FeeTier; FEE_TIER_COUNT] },
+
+  //Let a user deposit into a permissioned vault by creating their whitelist entry (owner only, a no-op if it exists)
+  //Accounts:
+  //0. [signer, writable] The vault owner, pays for the whitelist entry
+  //1. [] Vault state account
+  //2. [] The user being whitelisted
+  //3. [writable] Whitelist entry (PDA of ["whitelist", vault state, user])
+  //4. [] System program
+  AddToWhitelist,
+
+  //Take a user off a permissioned vault's whitelist by closing their entry, refunding its rent to the owner (owner only).
+  //Existing positions are kept and can still be withdrawn
+  //Accounts:
+  //0. [signer, writable] The vault owner, receives the entry's lamports
+  //1. [] Vault state account
+  //2. [] The user being removed
+  //3. [writable] Whitelist entry (PDA of ["whitelist", vault state, user])
+  RemoveFromWhitelist,
+
+  //Turn the deposit whitelist on or off for the vault (owner only)
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  SetRequireWhitelisted { require_whitelisted: bool },
+
+  //Withdraw from a wrapped-SOL vault as native SOL: the tokens are paid into a wrapped-SOL account the user owns, which
+  //is then closed into the user's wallet. Every lamport in it arrives, including its rent reserve and any wrapped SOL it
+  //already held, so a temporary account created for the withdrawal is the intended destination
+  //Accounts:
+  //0. [signer, writable] The user withdrawing, receives the unwrapped lamports
+  //1. [writable] Vault token account
+  //2. [writable] The user's wrapped-SOL token account, closed by this instruction
+  //3. [writable] Vault state account
+  //4. [writable] User vault account
+  //5. [] Token Program
+  //6. [] Token mint (the native mint)
+  //7. [] Vault authority (PDA of ["vault"]), signs the transfer out of the vault token account
+  //8. [writable] Fee treasury token account (only required when the vault charges a withdrawal fee; the owner pays none)
+  //9. [writable] Instruction fee treasury, only for vaults with an instruction fee (the user must then be writable;
+  //   index 8 when no withdrawal fee applies)
+  //10. [] System program, with the instruction fee treasury
+  //Data: amount, optionally followed by the vault authority bump as in Withdraw
+  WithdrawUnwrapped { amount: u64, authority_bump: Option<u8> },
+
+  //Deposit tokens like Deposit, and vest the whole position at locked_until: no withdrawal from it before that Unix
+  //timestamp, regardless of the vault's lock_duration or cooldown exemption. A later lock never shortens an earlier one
+  //Accounts:
+  //0. [signer, writable] The depositor, pays for the user vault account on first deposit
+  //1. [writable] Source user token account
+  //2. [writable] Vault token account (PDA)
+  //3. [writable] Vault state account
+  //4. [writable] User vault account (PDA of ["user_vault", depositor, vault state])
+  //5. [] Token program
+  //6. [] System program
+  //7. [] Token mint (for transfer_checked)
+  //8. [] Depositor's whitelist entry (PDA of ["whitelist", vault state, depositor]), only for vaults with require_whitelisted
+  //9. [writable] Instruction fee treasury, only for vaults with an instruction fee (index 8 without the whitelist)
+  //Data: amount followed by the i64 locked_until timestamp
+  DepositLocked { amount: u64, locked_until: i64 },
+
+  //Let the vault hold an extra mint by creating its asset account (owner only). The token account must already exist,
+  //hold that mint and be owned by the vault authority PDA
+  //Accounts:
+  //0. [signer, writable] The vault owner, pays for the asset account
+  //1. [] Vault state account
+  //2. [] Mint of the asset
+  //3. [] Token account holding the asset
+  //4. [writable] Vault asset account (PDA of ["vault_asset", vault state, mint])
+  //5. [] System program
+  AddAsset,
+
+  //Deposit tokens of one of the vault's extra assets. Pause, token program, whitelist and lock_duration come from the
+  //root vault; caps, fees and rewards only apply to the vault's own mint
+  //Accounts:
+  //0. [signer, writable] The depositor, pays for the position account on first deposit
+  //1. [writable] Source user token account
+  //2. [writable] Asset token account
   //3. [] Vault state account
-  //4. [] Token Program
-  Withdraw { amount: u64 },
+  //4. [writable] Vault asset account
+  //5. [writable] The depositor's position in the asset (PDA of ["user_vault", depositor, vault asset])
+  //6. [] Token program
+  //7. [] System program
+  //8. [] Asset mint (for transfer_checked)
+  //9. [] Depositor's whitelist entry (PDA of ["whitelist", vault state, depositor]), only for vaults with require_whitelisted
+  DepositAsset { amount: u64 },
+
+  //Withdraw tokens of one of the vault's extra assets back to the user
+  //Accounts:
+  //0. [signer] The user withdrawing
+  //1. [writable] Asset token account
+  //2. [writable] Destination token account for the asset
+  //3. [] Vault state account
+  //4. [writable] Vault asset account
+  //5. [writable] The user's position in the asset (PDA of ["user_vault", user, vault asset])
+  //6. [] Token program
+  //7. [] Asset mint (for transfer_checked)
+  //8. [] Vault authority (PDA of ["vault"]), owner of the asset token account
+  WithdrawAsset { amount: u64 },
+
+  //Split the user's position into what is withdrawable now and what is still locked, returned via return data as three
+  //little-endian values: u64 withdrawable now (as GetWithdrawable), u64 locked amount, and the i64 timestamp the position
+  //unlocks at (0 when it isn't locked)
+  //Accounts:
+  //0. [] The user
+  //1. [] Vault state account
+  //2. [] User vault account
+  //3. [] Vault token account
+  GetLockStatus,
+
+  //Set the time-based reward rate and the token account it is paid from (owner only, a rate of 0 stops accrual).
+  //The token account must be owned by the vault's reward authority PDA; the owner funds it. A position accrues at the
+  //rate in force whenever it is next touched, for all the time since it was last touched
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  //2. [] Reward token account (owned by the PDA of ["reward_authority", vault state])
+  //Data: the u64 rate per deposited token per second, scaled by REWARD_RATE_PRECISION
+  SetRewardRate { reward_rate_per_second: u64 },
+
+  //Pay out the user's time-based rewards, deposited_amount * rate * elapsed, from the vault's reward token account.
+  //When the reward account holds less than is owed, what it holds is paid and the rest stays owed
+  //Accounts:
+  //0. [signer] The user
+  //1. [] Vault state account
+  //2. [writable] User vault account (PDA of ["user_vault", user, vault state])
+  //3. [writable] Reward token account
+  //4. [writable] Destination token account for the reward mint
+  //5. [] Reward authority (PDA of ["reward_authority", vault state])
+  //6. [] Token program
+  //7. [] Reward mint (for transfer_checked)
+  ClaimRewards,
+
+  //Replace the vault's admin set and the number of them that must sign owner-gated instructions (owner only, and
+  //itself subject to the threshold in force). A threshold of 0 leaves the owner's signature as the only requirement
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  //2.. [signer] Admins signing toward the current threshold, as for every owner-gated instruction
+  //Data: ADMIN_COUNT 32-byte admin keys (zeroed for unused slots) followed by the u8 threshold
+  SetAdmins { admins: [Pubkey; ADMIN_COUNT], admin_threshold: u8 },
+
+  //Set the SOL fee every deposit and withdrawal pays into instruction_fee_treasury, a system account. Only charged
+  //when the program is built with the instruction-fees feature; without it a nonzero fee is refused. 0 turns the fee off
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  //2.. [signer] Admins signing toward the current threshold, as for every owner-gated instruction
+  //Data: the u64 fee in lamports followed by the 32-byte treasury key
+  SetInstructionFee { instruction_fee_lamports: u64, instruction_fee_treasury: Pubkey },
+
+  //Freeze the vault's configuration for good (owner only). Afterwards every config-changing instruction fails with
+  //ConfigFrozen, this one included; deposits, withdrawals, reward funding and reads carry on as before
+  //Accounts:
+  //0. [signer] The vault owner
+  //1. [writable] Vault state account
+  //2.. [signer] Admins signing toward the current threshold, as for every owner-gated instruction
+  FreezeConfig,
+
+  //Close an emptied user vault and return its rent to the user. Rewards are settled first, so the position must hold
+  //nothing, unclaimed time-based rewards included; the vault's user_count drops by one. Depositing again later creates
+  //a fresh record. The vault owner may close a record without the user's signature as long as no arbiter is set on it,
+  //so records anyone can open with EnsureUserVault never keep the vault from being closed
+  //Accounts:
+  //0. [signer, writable] The user, receives the rent (signer only when the vault owner doesn't sign)
+  //1. [writable] Vault state account
+  //2. [writable] User vault account (PDA of ["user_vault", user, vault state])
+  //3. [signer] The vault owner, only when closing on the user's behalf
+  CloseUserVault,
+
+  //Return the whole vault as Vault::LEN bytes in the current Pack layout via return data, so a client decodes it with
+  //Vault::unpack just like the account itself. A legacy-layout account is returned as it would be after Migrate
+  //Accounts:
+  //0. [] Vault state account
+  GetConfig,
 }
 
 impl VaultInstruction {
+  //The tag byte this instruction is encoded with, also its bit in Vault::enabled_instructions
+  pub fn tag(&self) -> u8 {
+    match self {
+      VaultInstruction::InitVault { .. } => 0,
+      VaultInstruction::Deposit { .. } => 1,
+      VaultInstruction::Withdraw { .. } => 2,
+      VaultInstruction::InitCompressedTree { .. } => 3,
+      VaultInstruction::CompressedDeposit { .. } => 4,
+      VaultInstruction::SetArbiter { .. } => 5,
+      VaultInstruction::RequestConditionalWithdraw { .. } => 6,
+      VaultInstruction::ConditionalWithdraw => 7,
+      VaultInstruction::GetWithdrawable => 8,
+      VaultInstruction::SetEnabledInstructions { .. } => 9,
+      VaultInstruction::EnsureUserVault => 10,
+      VaultInstruction::DistributeRewards { .. } => 11,
+      VaultInstruction::SetMinWithdraw { .. } => 12,
+      VaultInstruction::GetLimits => 13,
+      VaultInstruction::SetPaused { .. } => 14,
+      VaultInstruction::CloseVault => 15,
+      VaultInstruction::SetMaxSingleDeposit { .. } => 16,
+      VaultInstruction::TransferOwnership => 17,
+      VaultInstruction::SetCooldownExempt { .. } => 18,
+      VaultInstruction::SetName { .. } => 19,
+      VaultInstruction::TransferPosition { .. } => 20,
+      VaultInstruction::WithdrawAll => 21,
+      VaultInstruction::SetMaxVaultWithdrawPerWindow { .. } => 22,
+      VaultInstruction::PreflightCheck { .. } => 23,
+      VaultInstruction::InitVaultAndDeposit { .. } => 24,
+      VaultInstruction::GetUserCount => 25,
+      VaultInstruction::Migrate => 26,
+      VaultInstruction::SetFeeTiers { .. } => 27,
+      VaultInstruction::AddToWhitelist => 28,
+      VaultInstruction::RemoveFromWhitelist => 29,
+      VaultInstruction::SetRequireWhitelisted { .. } => 30,
+      VaultInstruction::WithdrawUnwrapped { .. } => 31,
+      VaultInstruction::DepositLocked { .. } => 32,
+      VaultInstruction::AddAsset => 33,
+      VaultInstruction::DepositAsset { .. } => 34,
+      VaultInstruction::WithdrawAsset { .. } => 35,
+      VaultInstruction::GetLockStatus => 36,
+      VaultInstruction::SetRewardRate { .. } => 37,
+      VaultInstruction::ClaimRewards => 38,
+      VaultInstruction::SetAdmins { .. } => 39,
+      VaultInstruction::SetInstructionFee { .. } => 40,
+      VaultInstruction::FreezeConfig => 41,
+      VaultInstruction::CloseUserVault => 42,
+      VaultInstruction::GetConfig => 43,
+    }
+  }
+
+  //Pack a [VaultInstruction] into the byte buffer `unpack` reads: the tag byte followed by the variant's fields
+  pub fn pack(&self) -> Vec<u8> {
+    let mut buf = vec![self.tag()];
+    match self {
+      VaultInstruction::ConditionalWithdraw
+      | VaultInstruction::GetWithdrawable
+      | VaultInstruction::EnsureUserVault
+      | VaultInstruction::GetLimits
+      | VaultInstruction::CloseVault
+      | VaultInstruction::TransferOwnership
+      | VaultInstruction::WithdrawAll
+      | VaultInstruction::GetUserCount
+      | VaultInstruction::Migrate
+      | VaultInstruction::AddToWhitelist
+      | VaultInstruction::RemoveFromWhitelist
+      | VaultInstruction::AddAsset
+      | VaultInstruction::GetLockStatus
+      | VaultInstruction::ClaimRewards
+      | VaultInstruction::FreezeConfig
+      | VaultInstruction::CloseUserVault
+      | VaultInstruction::GetConfig => {}
+      VaultInstruction::InitVault { name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration } => {
+        buf.extend_from_slice(name);
+        buf.extend_from_slice(&deposit_cap.to_le_bytes());
+        buf.extend_from_slice(&withdrawal_fee_bps.to_le_bytes());
+        buf.extend_from_slice(fee_treasury.as_ref());
+        buf.extend_from_slice(&lock_duration.to_le_bytes());
+      }
+      VaultInstruction::Deposit { amount, memo } => {
+        buf.extend_from_slice(&amount.to_le_bytes());
+        if let Some(memo) = memo {
+          buf.extend_from_slice(&(memo.len() as u16).to_le_bytes());    // Length prefix for the memo bytes
+          buf.extend_from_slice(memo.as_bytes());
+        }
+      }
+      VaultInstruction::Withdraw { amount, authority_bump } => {
+        buf.extend_from_slice(&amount.to_le_bytes());
+        if let Some(bump) = authority_bump {
+          buf.push(*bump);
+        }
+      }
+      VaultInstruction::InitCompressedTree { max_depth, max_buffer_size } => {
+        buf.extend_from_slice(&max_depth.to_le_bytes());
+        buf.extend_from_slice(&max_buffer_size.to_le_bytes());
+      }
+      VaultInstruction::CompressedDeposit { amount, previous_balance, index, root } => {
+        buf.extend_from_slice(&amount.to_le_bytes());
+        buf.extend_from_slice(&previous_balance.to_le_bytes());
+        buf.extend_from_slice(&index.to_le_bytes());
+        buf.extend_from_slice(root);
+      }
+      VaultInstruction::SetArbiter { arbiter } => buf.extend_from_slice(arbiter.as_ref()),
+      VaultInstruction::RequestConditionalWithdraw { amount } => buf.extend_from_slice(&amount.to_le_bytes()),
+      VaultInstruction::SetEnabledInstructions { enabled_instructions } => {
+        buf.extend_from_slice(&enabled_instructions.to_le_bytes());
+      }
+      VaultInstruction::DistributeRewards { total_reward } => buf.extend_from_slice(&total_reward.to_le_bytes()),
+      VaultInstruction::SetMinWithdraw { min_withdraw } => buf.extend_from_slice(&min_withdraw.to_le_bytes()),
+      VaultInstruction::SetPaused { paused } => buf.push(*paused as u8),
+      VaultInstruction::SetMaxSingleDeposit { max_single_deposit } => buf.extend_from_slice(&max_single_deposit.to_le_bytes()),
+      VaultInstruction::SetCooldownExempt { exempt } => buf.extend_from_slice(exempt.as_ref()),
+      VaultInstruction::SetName { name } => buf.extend_from_slice(name),
+      VaultInstruction::TransferPosition { amount, to } => {
+        buf.extend_from_slice(&amount.to_le_bytes());
+        buf.extend_from_slice(to.as_ref());
+      }
+      VaultInstruction::SetMaxVaultWithdrawPerWindow { max_vault_withdraw_per_window } => buf.extend_from_slice(&max_vault_withdraw_per_window.to_le_bytes()),
+      VaultInstruction::PreflightCheck { op, amount } => {
+        buf.push(*op);
+        buf.extend_from_slice(&amount.to_le_bytes());
+      }
+      VaultInstruction::InitVaultAndDeposit { name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration, amount } => {
+        buf.extend_from_slice(name);
+        buf.extend_from_slice(&deposit_cap.to_le_bytes());
+        buf.extend_from_slice(&withdrawal_fee_bps.to_le_bytes());
+        buf.extend_from_slice(fee_treasury.as_ref());
+        buf.extend_from_slice(&lock_duration.to_le_bytes());
+        buf.extend_from_slice(&amount.to_le_bytes());
+      }
+      VaultInstruction::SetFeeTiers { tiers } => {
+        let mut packed = [0u8; FEE_TIERS_LEN];
+        FeeTier::pack_tiers(tiers, &mut packed);
+        buf.extend_from_slice(&packed);
+      }
+      VaultInstruction::SetRequireWhitelisted { require_whitelisted } => buf.push(*require_whitelisted as u8),
+      VaultInstruction::WithdrawUnwrapped { amount, authority_bump } => {
+        buf.extend_from_slice(&amount.to_le_bytes());
+        if let Some(bump) = authority_bump {
+          buf.push(*bump);
+        }
+      }
+      VaultInstruction::DepositLocked { amount, locked_until } => {
+        buf.extend_from_slice(&amount.to_le_bytes());
+        buf.extend_from_slice(&locked_until.to_le_bytes());
+      }
+      VaultInstruction::DepositAsset { amount } => buf.extend_from_slice(&amount.to_le_bytes()),
+      VaultInstruction::WithdrawAsset { amount } => buf.extend_from_slice(&amount.to_le_bytes()),
+      VaultInstruction::SetRewardRate { reward_rate_per_second } => buf.extend_from_slice(&reward_rate_per_second.to_le_bytes()),
+      VaultInstruction::SetAdmins { admins, admin_threshold } => {
+        for admin in admins {
+          buf.extend_from_slice(admin.as_ref());
+        }
+        buf.push(*admin_threshold);
+      }
+      VaultInstruction::SetInstructionFee { instruction_fee_lamports, instruction_fee_treasury } => {
+        buf.extend_from_slice(&instruction_fee_lamports.to_le_bytes());
+        buf.extend_from_slice(instruction_fee_treasury.as_ref());
+      }
+    }
+    buf
+  }
+
   //Unpack a byte buffer into a [VaultInstruction].
-  pub fn unpack(input: &[u8]) -> Option<Self> {               // Takes a slice of bytes and tries to convert i.e deserialize it into one of the program's instructions
-    let (&tag, rest) = input.split_first()?;                  // This line grabs the first byte from the input and puts the rest of the buffer into rest. the first byte usually tells the program which variant to construct.
-    Some(match tag {                                          // Pattern matching the tag value to determine which variant of VaultInstruction this should be
-      0 => VaultInstruction::InitVault,                       // Initialize vault if it's 0
+  pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {  // Takes a slice of bytes and tries to convert i.e deserialize it into one of the program's instructions
+    let (&tag, rest) = input.split_first().ok_or(VaultError::EmptyInstruction)?; // This line grabs the first byte from the input and puts the rest of the buffer into rest. the first byte usually tells the program which variant to construct.
+    let instruction = match tag {                           // Pattern matching the tag value to determine which variant of VaultInstruction this should be
+      0 => {
+        let name = rest
+        .get(..32)
+        .and_then(|slice| slice.try_into().ok())
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let deposit_cap = rest
+        .get(32..40)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let withdrawal_fee_bps = rest
+        .get(40..42)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u16::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let fee_treasury = rest
+        .get(42..74)
+        .and_then(|slice| slice.try_into().ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let lock_duration = rest
+        .get(74..82)
+        .and_then(|slice| slice.try_into().ok())
+        .map(i64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::InitVault {name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration} // Initialize vault if it's 0
+      }
       1 => {
       // Try to read the next 8 bytes from the input and convert to u64
         let amount = rest
         .get(..8)                                             // Get the first 8 bytes of the rest
         .and_then(|slice| slice.try_into().ok())              // Try to convert &[u8] to [u8; 8]
-        .map(u64::from_le_bytes)?;                            // Convert byte array to u64
+        .map(u64::from_le_bytes)                              // Convert byte array to u64
+        .ok_or(VaultError::TruncatedInstruction)?;
+
+        // Anything after the amount is a length-prefixed memo; no trailing bytes means no memo
+        let memo = match rest.get(8..) {
+          Some(memo_bytes) if !memo_bytes.is_empty() => {
+            let len = memo_bytes
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or(VaultError::TruncatedInstruction)? as usize;
+            let text = memo_bytes.get(2..2 + len).ok_or(VaultError::TruncatedInstruction)?;
+            Some(String::from_utf8(text.to_vec()).map_err(|_| ProgramError::InvalidInstructionData)?) // Memos must be valid UTF-8, same as the Memo program requires
+          }
+          _ => None,
+        };
 
-      VaultInstruction::Deposit {amount}                      // Return the Deposit variant
+      VaultInstruction::Deposit {amount, memo}                // Return the Deposit variant
       }
       2 => {
         let amount = rest
         .get(..8)
         .and_then(|slice| slice.try_into().ok())
-        .map(u64::from_le_bytes)?;
-      VaultInstruction::Withdraw {amount}
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        // An optional trailing byte lets the client supply the authority bump it already knows
+        let authority_bump = rest.get(8).copied();
+      VaultInstruction::Withdraw {amount, authority_bump}
+      }
+      3 => {
+        let max_depth = rest
+        .get(..4)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let max_buffer_size = rest
+        .get(4..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::InitCompressedTree {max_depth, max_buffer_size}
+      }
+      4 => {
+        // Layout: amount (8) | previous_balance (8) | index (4) | root (32)
+        let amount = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let previous_balance = rest
+        .get(8..16)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let index = rest
+        .get(16..20)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let root = rest
+        .get(20..52)
+        .and_then(|slice| slice.try_into().ok())
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::CompressedDeposit {amount, previous_balance, index, root}
+      }
+      5 => {
+        let arbiter = rest
+        .get(..32)
+        .and_then(|slice| slice.try_into().ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetArbiter {arbiter}
+      }
+      6 => {
+        let amount = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::RequestConditionalWithdraw {amount}
+      }
+      7 => VaultInstruction::ConditionalWithdraw,
+      8 => VaultInstruction::GetWithdrawable,
+      9 => {
+        let enabled_instructions = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetEnabledInstructions {enabled_instructions}
+      }
+      10 => VaultInstruction::EnsureUserVault,
+      11 => {
+        let total_reward = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::DistributeRewards {total_reward}
+      }
+      12 => {
+        let min_withdraw = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetMinWithdraw {min_withdraw}
+      }
+      13 => VaultInstruction::GetLimits,
+      14 => {
+        let paused = rest
+        .first()
+        .map(|&byte| byte != 0)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetPaused {paused}
+      }
+      15 => VaultInstruction::CloseVault,
+      16 => {
+        let max_single_deposit = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetMaxSingleDeposit {max_single_deposit}
+      }
+      17 => VaultInstruction::TransferOwnership,
+      18 => {
+        let exempt = rest
+        .get(..32)
+        .and_then(|slice| slice.try_into().ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetCooldownExempt {exempt}
+      }
+      19 => {
+        let name = rest
+        .get(..32)
+        .and_then(|slice| slice.try_into().ok())
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetName {name}
+      }
+      20 => {
+        let amount = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let to = rest
+        .get(8..40)
+        .and_then(|slice| slice.try_into().ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::TransferPosition {amount, to}
+      }
+      21 => VaultInstruction::WithdrawAll,
+      22 => {
+        let max_vault_withdraw_per_window = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetMaxVaultWithdrawPerWindow {max_vault_withdraw_per_window}
+      }
+      23 => {
+        let op = *rest
+        .first()
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let amount = rest
+        .get(1..9)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::PreflightCheck {op, amount}
+      }
+      24 => {
+        let name = rest
+        .get(..32)
+        .and_then(|slice| slice.try_into().ok())
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let deposit_cap = rest
+        .get(32..40)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let withdrawal_fee_bps = rest
+        .get(40..42)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u16::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let fee_treasury = rest
+        .get(42..74)
+        .and_then(|slice| slice.try_into().ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let lock_duration = rest
+        .get(74..82)
+        .and_then(|slice| slice.try_into().ok())
+        .map(i64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let amount = rest
+        .get(82..90)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::InitVaultAndDeposit {name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration, amount}
+      }
+      25 => VaultInstruction::GetUserCount,
+      26 => VaultInstruction::Migrate,
+      27 => {
+        let tiers = rest
+        .get(..FEE_TIERS_LEN)
+        .and_then(|slice| slice.try_into().ok())
+        .map(FeeTier::unpack_tiers)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetFeeTiers {tiers}
+      }
+      28 => VaultInstruction::AddToWhitelist,
+      29 => VaultInstruction::RemoveFromWhitelist,
+      30 => {
+        let require_whitelisted = rest
+        .first()
+        .map(|&byte| byte != 0)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetRequireWhitelisted {require_whitelisted}
+      }
+      31 => {
+        let amount = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let authority_bump = rest.get(8).copied();
+      VaultInstruction::WithdrawUnwrapped {amount, authority_bump}
+      }
+      32 => {
+        let amount = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let locked_until = rest
+        .get(8..16)
+        .and_then(|slice| slice.try_into().ok())
+        .map(i64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::DepositLocked {amount, locked_until}
+      }
+      33 => VaultInstruction::AddAsset,
+      34 => {
+        let amount = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::DepositAsset {amount}
+      }
+      35 => {
+        let amount = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::WithdrawAsset {amount}
+      }
+      36 => VaultInstruction::GetLockStatus,
+      37 => {
+        let reward_rate_per_second = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetRewardRate {reward_rate_per_second}
+      }
+      38 => VaultInstruction::ClaimRewards,
+      39 => {
+        let admins = rest
+        .get(..ADMINS_LEN)
+        .and_then(|slice| slice.try_into().ok())
+        .map(unpack_admins)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let admin_threshold = *rest
+        .get(ADMINS_LEN)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetAdmins {admins, admin_threshold}
+      }
+      40 => {
+        let instruction_fee_lamports = rest
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(VaultError::TruncatedInstruction)?;
+        let instruction_fee_treasury = rest
+        .get(8..40)
+        .and_then(|slice| slice.try_into().ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(VaultError::TruncatedInstruction)?;
+      VaultInstruction::SetInstructionFee {instruction_fee_lamports, instruction_fee_treasury}
+      }
+      41 => VaultInstruction::FreezeConfig,
+      42 => VaultInstruction::CloseUserVault,
+      43 => VaultInstruction::GetConfig,
+      _ => return Err(VaultError::UnknownInstruction.into()), // If the tag doesn’t match a known variant, the input is invalid
+    };
+
+    // Every field was read from a fixed offset, so bytes past the last one would otherwise be silently ignored.
+    // pack is the exact inverse of this function, so its length is the length a well-formed buffer has.
+    if instruction.pack().len() != input.len() {
+      return Err(VaultError::TrailingInstructionData.into());
+    }
+
+    Ok(instruction)
+  }
+}
+
+// Signer/writable flags of every fixed account an instruction expects, in the order the processor reads them.
+// This is the single source of truth the account lists above are documented from, the builders are built from and
+// the processor validates every instruction's accounts against before dispatch.
+pub fn account_flags(ix: &VaultInstruction) -> &'static [(bool, bool)] {
+  // (is_signer, is_writable)
+  match ix {
+    VaultInstruction::InitVault { .. } => &[
+      (true, true),                                   // Vault creator (payer)
+      (false, true),                                  // Vault state account
+      (false, false),                                 // Token mint
+      (false, true),                                  // Vault token account
+      (false, false),                                 // Rent sysvar
+      (false, false),                                 // Token program
+      (false, false),                                 // System program
+    ],
+    VaultInstruction::Deposit { .. } => &[
+      (true, true),                                   // Depositor (payer)
+      (false, true),                                  // Source user token account
+      (false, true),                                  // Vault token account
+      (false, true),                                  // Vault state account
+      (false, true),                                  // User vault account
+      (false, false),                                 // Token program
+      (false, false),                                 // System program
+      (false, false),                                 // Token mint
+    ],
+    VaultInstruction::Withdraw { .. } => &[
+      (true, false),                                  // User
+      (false, true),                                  // Vault token account
+      (false, true),                                  // Destination token account
+      (false, true),                                  // Vault state account
+      (false, true),                                  // User vault account
+      (false, false),                                 // Token program
+      (false, false),                                 // Token mint
+      (false, false),                                 // Vault authority
+      (false, true),                                  // Fee treasury token account (fee-charging vaults only)
+    ],
+    VaultInstruction::InitCompressedTree { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+      (false, true),                                  // Merkle tree account
+      (false, false),                                 // Tree authority
+      (false, false),                                 // Noop program
+      (false, false),                                 // Account compression program
+    ],
+    VaultInstruction::CompressedDeposit { .. } => &[
+      (true, false),                                  // Depositor
+      (false, true),                                  // Source user token account
+      (false, true),                                  // Vault token account
+      (false, false),                                 // Vault state account
+      (false, true),                                  // Merkle tree account
+      (false, false),                                 // Tree authority
+      (false, false),                                 // Noop program
+      (false, false),                                 // Account compression program
+      (false, false),                                 // Token program
+      (false, false),                                 // Token mint
+    ],
+    VaultInstruction::SetArbiter { .. } => &[
+      (true, false),                                  // User
+      (false, true),                                  // User vault account
+      (false, false),                                 // Vault state account
+      (true, false),                                  // Current arbiter
+    ],
+    VaultInstruction::RequestConditionalWithdraw { .. } => &[
+      (true, false),                                  // User
+      (false, true),                                  // User vault account
+      (false, false),                                 // Vault state account
+    ],
+    VaultInstruction::ConditionalWithdraw => &[
+      (true, false),                                  // Arbiter
+      (false, true),                                  // Vault token account
+      (false, true),                                  // Destination token account
+      (false, true),                                  // Vault state account
+      (false, true),                                  // User vault account
+      (false, false),                                 // Token program
+      (false, false),                                 // Token mint
+      (false, false),                                 // Vault authority
+      (false, true),                                  // Fee treasury token account (fee-charging vaults only)
+    ],
+    VaultInstruction::GetWithdrawable => &[
+      (false, false),                                 // User
+      (false, false),                                 // Vault state account
+      (false, false),                                 // User vault account
+      (false, false),                                 // Vault token account
+    ],
+    VaultInstruction::SetEnabledInstructions { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::EnsureUserVault => &[
+      (true, true),                                   // Payer
+      (false, false),                                 // User
+      (false, true),                                  // User vault account
+      (false, true),                                  // Vault state account
+      (false, false),                                 // System program
+    ],
+    VaultInstruction::DistributeRewards { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Owner source token account
+      (false, true),                                  // Vault token account
+      (false, true),                                  // Vault state account
+      (false, false),                                 // Token program
+      (false, false),                                 // Token mint
+    ],
+    VaultInstruction::SetMinWithdraw { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::GetLimits => &[
+      (false, false),                                 // Vault state account
+    ],
+    VaultInstruction::SetPaused { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::CloseVault => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+      (false, true),                                  // Lamport destination
+    ],
+    VaultInstruction::SetMaxSingleDeposit { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::TransferOwnership => &[
+      (true, false),                                  // Current owner
+      (false, true),                                  // Vault state account
+      (false, false),                                 // New owner
+    ],
+    VaultInstruction::SetCooldownExempt { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::SetName { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::TransferPosition { .. } => &[
+      (true, true),                                   // User (payer)
+      (false, true),                                  // User vault account
+      (false, true),                                  // Recipient user vault account
+      (false, true),                                  // Vault state account
+      (false, false),                                 // System program
+    ],
+    VaultInstruction::WithdrawAll => &[
+      (true, false),                                  // User
+      (false, true),                                  // Vault token account
+      (false, true),                                  // Destination token account
+      (false, true),                                  // Vault state account
+      (false, true),                                  // User vault account
+      (false, false),                                 // Token program
+      (false, false),                                 // Token mint
+      (false, false),                                 // Vault authority
+      (false, true),                                  // Fee treasury token account (fee-charging vaults only)
+    ],
+    VaultInstruction::SetMaxVaultWithdrawPerWindow { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::PreflightCheck { .. } => &[
+      (false, false),                                 // User
+      (false, false),                                 // Vault state account
+      (false, false),                                 // User vault account
+      (false, false),                                 // Vault token account
+    ],
+    VaultInstruction::InitVaultAndDeposit { .. } => &[
+      (true, true),                                   // Vault creator (payer, depositor)
+      (false, true),                                  // Vault state account
+      (false, false),                                 // Token mint
+      (false, true),                                  // Vault token account
+      (false, false),                                 // Rent sysvar
+      (false, false),                                 // Token program
+      (false, false),                                 // System program
+      (false, true),                                  // Source token account
+      (false, true),                                  // User vault account
+    ],
+    VaultInstruction::GetUserCount => &[
+      (false, false),                                 // Vault state account
+    ],
+    VaultInstruction::Migrate => &[
+      (true, true),                                   // Vault owner (payer)
+      (false, true),                                  // Vault state account
+      (false, false),                                 // System program
+    ],
+    VaultInstruction::SetFeeTiers { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::AddToWhitelist => &[
+      (true, true),                                   // Vault owner (payer)
+      (false, false),                                 // Vault state account
+      (false, false),                                 // User
+      (false, true),                                  // Whitelist entry
+      (false, false),                                 // System program
+    ],
+    VaultInstruction::RemoveFromWhitelist => &[
+      (true, true),                                   // Vault owner (rent destination)
+      (false, false),                                 // Vault state account
+      (false, false),                                 // User
+      (false, true),                                  // Whitelist entry
+    ],
+    VaultInstruction::SetRequireWhitelisted { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::WithdrawUnwrapped { .. } => &[
+      (true, true),                                   // User (lamport destination)
+      (false, true),                                  // Vault token account
+      (false, true),                                  // Wrapped-SOL destination, closed
+      (false, true),                                  // Vault state account
+      (false, true),                                  // User vault account
+      (false, false),                                 // Token program
+      (false, false),                                 // Token mint
+      (false, false),                                 // Vault authority
+      (false, true),                                  // Fee treasury token account (fee-charging vaults only)
+    ],
+    VaultInstruction::DepositLocked { .. } => &[
+      (true, true),                                   // Depositor (payer)
+      (false, true),                                  // Source user token account
+      (false, true),                                  // Vault token account
+      (false, true),                                  // Vault state account
+      (false, true),                                  // User vault account
+      (false, false),                                 // Token program
+      (false, false),                                 // System program
+      (false, false),                                 // Token mint
+    ],
+    VaultInstruction::AddAsset => &[
+      (true, true),                                   // Vault owner (payer)
+      (false, false),                                 // Vault state account
+      (false, false),                                 // Asset mint
+      (false, false),                                 // Asset token account
+      (false, true),                                  // Vault asset account
+      (false, false),                                 // System program
+    ],
+    VaultInstruction::DepositAsset { .. } => &[
+      (true, true),                                   // Depositor (payer)
+      (false, true),                                  // Source user token account
+      (false, true),                                  // Asset token account
+      (false, false),                                 // Vault state account
+      (false, true),                                  // Vault asset account
+      (false, true),                                  // Position in the asset
+      (false, false),                                 // Token program
+      (false, false),                                 // System program
+      (false, false),                                 // Asset mint
+    ],
+    VaultInstruction::WithdrawAsset { .. } => &[
+      (true, false),                                  // User
+      (false, true),                                  // Asset token account
+      (false, true),                                  // Destination token account
+      (false, false),                                 // Vault state account
+      (false, true),                                  // Vault asset account
+      (false, true),                                  // Position in the asset
+      (false, false),                                 // Token program
+      (false, false),                                 // Asset mint
+      (false, false),                                 // Vault authority
+    ],
+    VaultInstruction::GetLockStatus => &[
+      (false, false),                                 // User
+      (false, false),                                 // Vault state account
+      (false, false),                                 // User vault account
+      (false, false),                                 // Vault token account
+    ],
+    VaultInstruction::SetRewardRate { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+      (false, false),                                 // Reward token account
+    ],
+    VaultInstruction::ClaimRewards => &[
+      (true, false),                                  // User
+      (false, false),                                 // Vault state account
+      (false, true),                                  // User vault account
+      (false, true),                                  // Reward token account
+      (false, true),                                  // Destination token account
+      (false, false),                                 // Reward authority
+      (false, false),                                 // Token program
+      (false, false),                                 // Reward mint
+    ],
+    VaultInstruction::SetAdmins { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::SetInstructionFee { .. } => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::FreezeConfig => &[
+      (true, false),                                  // Vault owner
+      (false, true),                                  // Vault state account
+    ],
+    VaultInstruction::CloseUserVault => &[
+      (false, true),                                  // User (rent destination), signs unless the vault owner does
+      (false, true),                                  // Vault state account
+      (false, true),                                  // User vault account
+      (true, false),                                  // Vault owner, only when closing on the user's behalf
+    ],
+    VaultInstruction::GetConfig => &[
+      (false, false),                                 // Vault state account
+    ],
+  }
+}
+
+// Most accounts the instruction reads: its fixed accounts plus optional trailing ones like the Deposit memo program
+// or a whitelist entry.
+// None when the list is open-ended (CompressedDeposit's Merkle proof nodes).
+pub fn max_accounts(ix: &VaultInstruction) -> Option<usize> {
+  let fixed = account_flags(ix).len();
+  match ix {
+    VaultInstruction::CompressedDeposit { .. } => None,
+    VaultInstruction::Deposit { memo, .. } => Some(fixed + 2 + memo.is_some() as usize), // Whitelist entry, fee treasury, memo
+    VaultInstruction::Withdraw { .. }
+    | VaultInstruction::WithdrawAll
+    | VaultInstruction::WithdrawUnwrapped { .. } => Some(fixed + 2),                      // Instruction fee treasury, system program
+    VaultInstruction::TransferPosition { .. } => Some(fixed + 1),                         // Recipient's whitelist entry
+    VaultInstruction::DepositLocked { .. } => Some(fixed + 2),                            // Whitelist entry, fee treasury
+    VaultInstruction::DepositAsset { .. } => Some(fixed + 1),                             // Whitelist entry
+    VaultInstruction::PreflightCheck { .. } => Some(fixed + 1),                           // Whitelist entry
+    _ if is_owner_gated(ix) => Some(fixed + ADMIN_COUNT),                                 // Admin signers toward the threshold
+    _ => Some(fixed),
+  }
+}
+
+// Position of the vault state account in the instruction's account list, used to apply per-vault gating before dispatch.
+// None for instructions that run before a vault exists or that must stay reachable regardless of the vault's settings.
+pub fn vault_state_index(ix: &VaultInstruction) -> Option<usize> {
+  match ix {
+    VaultInstruction::InitVault { .. } => None,                   // The vault doesn't exist yet
+    VaultInstruction::SetEnabledInstructions { .. } => None,      // Never lockable, or the owner could disable their way out
+    VaultInstruction::Deposit { .. }
+    | VaultInstruction::Withdraw { .. }
+    | VaultInstruction::CompressedDeposit { .. }
+    | VaultInstruction::ConditionalWithdraw
+    | VaultInstruction::DistributeRewards { .. } => Some(3),
+    VaultInstruction::SetArbiter { .. } | VaultInstruction::RequestConditionalWithdraw { .. } => Some(2),
+    VaultInstruction::InitCompressedTree { .. } | VaultInstruction::GetWithdrawable => Some(1),
+    VaultInstruction::EnsureUserVault => Some(3),
+    VaultInstruction::SetMinWithdraw { .. } => Some(1),
+    VaultInstruction::GetLimits => Some(0),
+    VaultInstruction::SetPaused { .. } => Some(1),
+    VaultInstruction::CloseVault => Some(1),
+    VaultInstruction::SetMaxSingleDeposit { .. } => Some(1),
+    VaultInstruction::TransferOwnership => Some(1),
+    VaultInstruction::SetCooldownExempt { .. } => Some(1),
+    VaultInstruction::SetName { .. } => Some(1),
+    VaultInstruction::TransferPosition { .. } => Some(3),
+    VaultInstruction::WithdrawAll => Some(3),
+    VaultInstruction::SetMaxVaultWithdrawPerWindow { .. } => Some(1),
+    VaultInstruction::PreflightCheck { .. } => Some(1),
+    VaultInstruction::InitVaultAndDeposit { .. } => None,         // The vault doesn't exist yet either
+    VaultInstruction::GetUserCount => Some(0),
+    VaultInstruction::Migrate => None,                            // Must stay reachable for every legacy account
+    VaultInstruction::SetFeeTiers { .. } => Some(1),
+    VaultInstruction::AddToWhitelist => Some(1),
+    VaultInstruction::RemoveFromWhitelist => Some(1),
+    VaultInstruction::SetRequireWhitelisted { .. } => Some(1),
+    VaultInstruction::WithdrawUnwrapped { .. } => Some(3),
+    VaultInstruction::DepositLocked { .. } => Some(3),
+    VaultInstruction::AddAsset => Some(1),
+    VaultInstruction::DepositAsset { .. } => Some(3),
+    VaultInstruction::WithdrawAsset { .. } => Some(3),
+    VaultInstruction::GetLockStatus => Some(1),
+    VaultInstruction::SetRewardRate { .. } => Some(1),
+    VaultInstruction::ClaimRewards => Some(1),
+    VaultInstruction::SetAdmins { .. } => Some(1),
+    VaultInstruction::SetInstructionFee { .. } => Some(1),
+    VaultInstruction::FreezeConfig => Some(1),
+    VaultInstruction::CloseUserVault => Some(1),
+    VaultInstruction::GetConfig => Some(0),
+  }
+}
+
+// Whether the instruction is gated on the vault owner's signature (admin instructions). On vaults with an admin
+// threshold these also take that many admin signers, after the instruction's own accounts
+pub fn is_owner_gated(ix: &VaultInstruction) -> bool {
+  match ix {
+    VaultInstruction::InitCompressedTree { .. }
+    | VaultInstruction::SetEnabledInstructions { .. }
+    | VaultInstruction::DistributeRewards { .. }
+    | VaultInstruction::SetMinWithdraw { .. }
+    | VaultInstruction::SetPaused { .. }
+    | VaultInstruction::CloseVault
+    | VaultInstruction::SetMaxSingleDeposit { .. }
+    | VaultInstruction::TransferOwnership
+    | VaultInstruction::SetCooldownExempt { .. }
+    | VaultInstruction::SetName { .. }
+    | VaultInstruction::SetMaxVaultWithdrawPerWindow { .. }
+    | VaultInstruction::Migrate
+    | VaultInstruction::SetFeeTiers { .. }
+    | VaultInstruction::AddToWhitelist
+    | VaultInstruction::RemoveFromWhitelist
+    | VaultInstruction::SetRequireWhitelisted { .. }
+    | VaultInstruction::AddAsset
+    | VaultInstruction::SetRewardRate { .. }
+    | VaultInstruction::SetAdmins { .. }
+    | VaultInstruction::SetInstructionFee { .. }
+    | VaultInstruction::FreezeConfig => true,
+    VaultInstruction::InitVault { .. }
+    | VaultInstruction::Deposit { .. }
+    | VaultInstruction::Withdraw { .. }
+    | VaultInstruction::CompressedDeposit { .. }
+    | VaultInstruction::SetArbiter { .. }
+    | VaultInstruction::RequestConditionalWithdraw { .. }
+    | VaultInstruction::ConditionalWithdraw
+    | VaultInstruction::GetWithdrawable
+    | VaultInstruction::EnsureUserVault
+    | VaultInstruction::GetLimits
+    | VaultInstruction::TransferPosition { .. }
+    | VaultInstruction::WithdrawAll
+    | VaultInstruction::PreflightCheck { .. }
+    | VaultInstruction::InitVaultAndDeposit { .. }
+    | VaultInstruction::GetUserCount
+    | VaultInstruction::WithdrawUnwrapped { .. }
+    | VaultInstruction::DepositLocked { .. }
+    | VaultInstruction::DepositAsset { .. }
+    | VaultInstruction::WithdrawAsset { .. }
+    | VaultInstruction::GetLockStatus
+    | VaultInstruction::ClaimRewards
+    | VaultInstruction::CloseUserVault
+    | VaultInstruction::GetConfig => false,
+  }
+}
+
+// Whether the instruction can move tokens out of the vault token account. The owner role never moves user principal, so
+// no instruction is both this and owner-gated; fund-moving instructions are authorized by the position's user (or their
+// arbiter) and always pay out to the user
+pub fn moves_vault_funds(ix: &VaultInstruction) -> bool {
+  match ix {
+    VaultInstruction::Withdraw { .. }
+    | VaultInstruction::ConditionalWithdraw
+    | VaultInstruction::WithdrawAll
+    | VaultInstruction::WithdrawUnwrapped { .. }
+    | VaultInstruction::WithdrawAsset { .. } => true,
+    VaultInstruction::InitVault { .. }
+    | VaultInstruction::Deposit { .. }
+    | VaultInstruction::CompressedDeposit { .. }
+    | VaultInstruction::InitCompressedTree { .. }
+    | VaultInstruction::SetArbiter { .. }
+    | VaultInstruction::RequestConditionalWithdraw { .. }
+    | VaultInstruction::GetWithdrawable
+    | VaultInstruction::SetEnabledInstructions { .. }
+    | VaultInstruction::EnsureUserVault
+    | VaultInstruction::DistributeRewards { .. }
+    | VaultInstruction::SetMinWithdraw { .. }
+    | VaultInstruction::GetLimits
+    | VaultInstruction::SetPaused { .. }
+    | VaultInstruction::CloseVault
+    | VaultInstruction::SetMaxSingleDeposit { .. }
+    | VaultInstruction::TransferOwnership
+    | VaultInstruction::SetCooldownExempt { .. }
+    | VaultInstruction::SetName { .. }
+    | VaultInstruction::TransferPosition { .. }
+    | VaultInstruction::SetMaxVaultWithdrawPerWindow { .. }
+    | VaultInstruction::PreflightCheck { .. }
+    | VaultInstruction::InitVaultAndDeposit { .. }
+    | VaultInstruction::GetUserCount
+    | VaultInstruction::Migrate
+    | VaultInstruction::SetFeeTiers { .. }
+    | VaultInstruction::AddToWhitelist
+    | VaultInstruction::RemoveFromWhitelist
+    | VaultInstruction::SetRequireWhitelisted { .. }
+    | VaultInstruction::DepositLocked { .. }
+    | VaultInstruction::AddAsset
+    | VaultInstruction::DepositAsset { .. }
+    | VaultInstruction::GetLockStatus
+    | VaultInstruction::SetRewardRate { .. }
+    | VaultInstruction::ClaimRewards
+    | VaultInstruction::SetAdmins { .. }
+    | VaultInstruction::SetInstructionFee { .. }
+    | VaultInstruction::FreezeConfig
+    | VaultInstruction::CloseUserVault
+    | VaultInstruction::GetConfig => false,
+  }
+}
+
+// Whether the instruction changes the vault's configuration, and so is refused once the config is frozen. Every one of
+// these takes the vault state account at index 1
+pub fn changes_config(ix: &VaultInstruction) -> bool {
+  match ix {
+    VaultInstruction::InitCompressedTree { .. }
+    | VaultInstruction::SetEnabledInstructions { .. }
+    | VaultInstruction::SetMinWithdraw { .. }
+    | VaultInstruction::SetPaused { .. }
+    | VaultInstruction::SetMaxSingleDeposit { .. }
+    | VaultInstruction::TransferOwnership
+    | VaultInstruction::SetCooldownExempt { .. }
+    | VaultInstruction::SetName { .. }
+    | VaultInstruction::SetMaxVaultWithdrawPerWindow { .. }
+    | VaultInstruction::SetFeeTiers { .. }
+    | VaultInstruction::AddToWhitelist
+    | VaultInstruction::RemoveFromWhitelist
+    | VaultInstruction::SetRequireWhitelisted { .. }
+    | VaultInstruction::AddAsset
+    | VaultInstruction::SetRewardRate { .. }
+    | VaultInstruction::SetAdmins { .. }
+    | VaultInstruction::SetInstructionFee { .. }
+    | VaultInstruction::FreezeConfig => true,
+    VaultInstruction::InitVault { .. }
+    | VaultInstruction::Deposit { .. }
+    | VaultInstruction::Withdraw { .. }
+    | VaultInstruction::CompressedDeposit { .. }
+    | VaultInstruction::SetArbiter { .. }
+    | VaultInstruction::RequestConditionalWithdraw { .. }
+    | VaultInstruction::ConditionalWithdraw
+    | VaultInstruction::GetWithdrawable
+    | VaultInstruction::EnsureUserVault
+    | VaultInstruction::DistributeRewards { .. }            // Funding rewards uses the config, it doesn't change it
+    | VaultInstruction::GetLimits
+    | VaultInstruction::CloseVault                          // Only an empty vault, and it leaves nothing to configure
+    | VaultInstruction::TransferPosition { .. }
+    | VaultInstruction::WithdrawAll
+    | VaultInstruction::PreflightCheck { .. }
+    | VaultInstruction::InitVaultAndDeposit { .. }
+    | VaultInstruction::GetUserCount
+    | VaultInstruction::Migrate                             // Rewrites the layout, not the values in it
+    | VaultInstruction::WithdrawUnwrapped { .. }
+    | VaultInstruction::DepositLocked { .. }
+    | VaultInstruction::DepositAsset { .. }
+    | VaultInstruction::WithdrawAsset { .. }
+    | VaultInstruction::GetLockStatus
+    | VaultInstruction::ClaimRewards
+    | VaultInstruction::CloseUserVault
+    | VaultInstruction::GetConfig => false,
+  }
+}
+
+// Produce the exact ordered account metas for `ix` from the keys the caller supplies in documented order.
+// Keys past the fixed accounts (e.g. Merkle proof nodes) are appended as read-only, non-signer metas.
+pub fn required_accounts(ix: &VaultInstruction, keys: &[Pubkey]) -> Vec<AccountMeta> {
+  let flags = account_flags(ix);
+
+  keys
+    .iter()
+    .enumerate()
+    .map(|(i, key)| {
+      let (is_signer, is_writable) = flags.get(i).copied().unwrap_or((false, false));
+      if is_writable {
+        AccountMeta::new(*key, is_signer)
+      } else {
+        AccountMeta::new_readonly(*key, is_signer)
       }
-      _ => return None,                                     // If the tag doesn’t match 0, 1, or 2, the input is invalid, returns None
     })
+    .collect()
+}
+
+// Like the SPL program builders, the builders below take one argument per account and data field in instruction order,
+// so most of them go past clippy's argument limit
+
+// Build an InitVault instruction; the vault state PDA is derived from the owner and mint
+#[allow(clippy::too_many_arguments)]
+pub fn init_vault(
+  program_id: &Pubkey,
+  owner: &Pubkey,
+  token_mint: &Pubkey,
+  vault_token_account: &Pubkey,
+  token_program_id: &Pubkey,
+  name: [u8; 32],
+  deposit_cap: u64,
+  withdrawal_fee_bps: u16,
+  fee_treasury: &Pubkey,
+  lock_duration: i64,
+) -> Instruction {
+  let (vault_state, _bump) = pda::vault_state(program_id, owner, token_mint);
+  let ix = VaultInstruction::InitVault { name, deposit_cap, withdrawal_fee_bps, fee_treasury: *fee_treasury, lock_duration };
+
+  Instruction {
+    program_id: *program_id,
+    accounts: required_accounts(
+      &ix,
+      &[*owner, vault_state, *token_mint, *vault_token_account, sysvar::rent::id(), *token_program_id, system_program::id()],
+    ),
+    data: ix.pack(),
+  }
+}
+
+// Build an InitVaultAndDeposit instruction, the one-step version of init_vault followed by deposit
+#[allow(clippy::too_many_arguments)]
+pub fn init_vault_and_deposit(
+  program_id: &Pubkey,
+  owner: &Pubkey,
+  token_mint: &Pubkey,
+  vault_token_account: &Pubkey,
+  owner_source_token_account: &Pubkey,
+  token_program_id: &Pubkey,
+  name: [u8; 32],
+  deposit_cap: u64,
+  withdrawal_fee_bps: u16,
+  fee_treasury: &Pubkey,
+  lock_duration: i64,
+  amount: u64,
+) -> Instruction {
+  let (vault_state, _bump) = pda::vault_state(program_id, owner, token_mint);
+  let (user_vault, _bump) = pda::user_vault(program_id, owner, &vault_state);
+  let ix = VaultInstruction::InitVaultAndDeposit {
+    name,
+    deposit_cap,
+    withdrawal_fee_bps,
+    fee_treasury: *fee_treasury,
+    lock_duration,
+    amount,
+  };
+
+  Instruction {
+    program_id: *program_id,
+    accounts: required_accounts(
+      &ix,
+      &[
+        *owner,
+        vault_state,
+        *token_mint,
+        *vault_token_account,
+        sysvar::rent::id(),
+        *token_program_id,
+        system_program::id(),
+        *owner_source_token_account,
+        user_vault,
+      ],
+    ),
+    data: ix.pack(),
+  }
+}
+
+// Build a Deposit instruction; the user vault PDA is derived from the depositor and vault state
+#[allow(clippy::too_many_arguments)]
+pub fn deposit(
+  program_id: &Pubkey,
+  amount: u64,
+  depositor: &Pubkey,
+  source_token_account: &Pubkey,
+  vault_token_account: &Pubkey,
+  vault_state: &Pubkey,
+  token_program_id: &Pubkey,
+  token_mint: &Pubkey,
+  require_whitelisted: bool,                      // Whether the vault only takes whitelisted depositors
+  instruction_fee_treasury: Option<&Pubkey>,      // Only for vaults that charge an instruction fee
+) -> Instruction {
+  let (user_vault, _bump) = pda::user_vault(program_id, depositor, vault_state);
+  let ix = VaultInstruction::Deposit { amount, memo: None };
+  let mut keys = vec![*depositor, *source_token_account, *vault_token_account, *vault_state, user_vault, *token_program_id, system_program::id(), *token_mint];
+  if require_whitelisted {
+    keys.push(pda::whitelist(program_id, vault_state, depositor).0);
+  }
+
+  let mut accounts = required_accounts(&ix, &keys);
+  accounts.extend(instruction_fee_treasury.map(|treasury| AccountMeta::new(*treasury, false)));
+
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: ix.pack(),
+  }
+}
+
+// Build a DepositLocked instruction, which takes the same accounts as Deposit
+#[allow(clippy::too_many_arguments)]
+pub fn deposit_locked(
+  program_id: &Pubkey,
+  amount: u64,
+  locked_until: i64,
+  depositor: &Pubkey,
+  source_token_account: &Pubkey,
+  vault_token_account: &Pubkey,
+  vault_state: &Pubkey,
+  token_program_id: &Pubkey,
+  token_mint: &Pubkey,
+  require_whitelisted: bool,                      // Whether the vault only takes whitelisted depositors
+  instruction_fee_treasury: Option<&Pubkey>,      // Only for vaults that charge an instruction fee
+) -> Instruction {
+  let deposit_ix = deposit(
+    program_id,
+    amount,
+    depositor,
+    source_token_account,
+    vault_token_account,
+    vault_state,
+    token_program_id,
+    token_mint,
+    require_whitelisted,
+    instruction_fee_treasury,
+  );
+
+  Instruction {
+    data: VaultInstruction::DepositLocked { amount, locked_until }.pack(),
+    ..deposit_ix
   }
-}
\ No newline at end of file
+}
+
+// Build a Withdraw instruction; the vault authority bump is included so the program can skip the bump search
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw(
+  program_id: &Pubkey,
+  amount: u64,
+  user: &Pubkey,
+  vault_token_account: &Pubkey,
+  destination_token_account: &Pubkey,
+  vault_state: &Pubkey,
+  token_program_id: &Pubkey,
+  token_mint: &Pubkey,
+  fee_treasury: Option<&Pubkey>,                  // Only for vaults that charge a withdrawal fee
+  instruction_fee_treasury: Option<&Pubkey>,      // Only for vaults that charge an instruction fee
+) -> Instruction {
+  let (user_vault, _bump) = pda::user_vault(program_id, user, vault_state);
+  let (vault_authority, authority_bump) = pda::vault_authority(program_id);
+  let ix = VaultInstruction::Withdraw { amount, authority_bump: Some(authority_bump) };
+  let mut keys = vec![
+    *user,
+    *vault_token_account,
+    *destination_token_account,
+    *vault_state,
+    user_vault,
+    *token_program_id,
+    *token_mint,
+    vault_authority,
+  ];
+  keys.extend(fee_treasury.copied());
+
+  let mut accounts = required_accounts(&ix, &keys);
+  if let Some(treasury) = instruction_fee_treasury {
+    accounts[0].is_writable = true;                // The user pays the fee
+    accounts.push(AccountMeta::new(*treasury, false));
+    accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+  }
+
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: ix.pack(),
+  }
+}
+
+// Build a CloseUserVault instruction, signed by the user or, when `vault_owner` is given, by the vault owner instead
+pub fn close_user_vault(program_id: &Pubkey, user: &Pubkey, vault_state: &Pubkey, vault_owner: Option<&Pubkey>) -> Instruction {
+  let (user_vault, _bump) = pda::user_vault(program_id, user, vault_state);
+  let ix = VaultInstruction::CloseUserVault;
+  let mut keys = vec![*user, *vault_state, user_vault];
+  keys.extend(vault_owner.copied());
+
+  let mut accounts = required_accounts(&ix, &keys);
+  accounts[0].is_signer = vault_owner.is_none();
+
+  Instruction {
+    program_id: *program_id,
+    accounts,
+    data: ix.pack(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pack_round_trips_through_unpack() {
+    let instructions = [
+      VaultInstruction::InitVault {
+        name: [b'n'; 32],
+        deposit_cap: 1_000,
+        withdrawal_fee_bps: 25,
+        fee_treasury: Pubkey::new_unique(),
+        lock_duration: 60,
+      },
+      VaultInstruction::Deposit { amount: 5, memo: None },
+      VaultInstruction::Deposit { amount: 5, memo: Some("invoice 42".to_string()) },
+      VaultInstruction::Withdraw { amount: 7, authority_bump: None },
+      VaultInstruction::Withdraw { amount: 7, authority_bump: Some(253) },
+      VaultInstruction::ConditionalWithdraw,
+      VaultInstruction::SetPaused { paused: true },
+      VaultInstruction::DepositLocked { amount: 3, locked_until: -1 },
+      VaultInstruction::SetAdmins { admins: [Pubkey::new_unique(); ADMIN_COUNT], admin_threshold: 2 },
+      VaultInstruction::GetConfig,
+    ];
+
+    for ix in instructions {
+      let data = ix.pack();
+      assert_eq!(data[0], ix.tag());
+      assert_eq!(VaultInstruction::unpack(&data).unwrap(), ix);
+    }
+  }
+
+  #[test]
+  fn builders_derive_their_pdas_and_encode_the_data() {
+    let program_id = Pubkey::new_unique();
+    let (user, mint, vault_token_account, source) =
+      (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let (vault_state, _) = pda::vault_state(&program_id, &user, &mint);
+
+    let ix = init_vault(&program_id, &user, &mint, &vault_token_account, &spl_token::id(), [0; 32], 0, 0, &Pubkey::default(), 0);
+    assert_eq!(ix.accounts[1].pubkey, vault_state);
+    assert!(ix.accounts[0].is_signer && ix.accounts[0].is_writable);
+
+    let ix = deposit(&program_id, 9, &user, &source, &vault_token_account, &vault_state, &spl_token::id(), &mint, false, None);
+    assert_eq!(ix.accounts.len(), 8);
+    assert_eq!(ix.accounts[4].pubkey, pda::user_vault(&program_id, &user, &vault_state).0);
+    assert_eq!(VaultInstruction::unpack(&ix.data).unwrap(), VaultInstruction::Deposit { amount: 9, memo: None });
+
+    let fee_treasury = Pubkey::new_unique();
+    let ix = withdraw(&program_id, 4, &user, &vault_token_account, &source, &vault_state, &spl_token::id(), &mint, Some(&fee_treasury), None);
+    assert_eq!(ix.accounts[7], AccountMeta::new_readonly(pda::vault_authority(&program_id).0, false));
+    assert_eq!(ix.accounts[8], AccountMeta::new(fee_treasury, false));
+    assert_eq!(
+      VaultInstruction::unpack(&ix.data).unwrap(),
+      VaultInstruction::Withdraw { amount: 4, authority_bump: Some(pda::vault_authority(&program_id).1) },
+    );
+  }
+
+  #[test]
+  fn unpack_reports_each_decode_failure() {
+    assert_eq!(VaultInstruction::unpack(&[]), Err(VaultError::EmptyInstruction.into()));
+    assert_eq!(VaultInstruction::unpack(&[255]), Err(VaultError::UnknownInstruction.into()));
+    assert_eq!(VaultInstruction::unpack(&[1, 0, 0, 0]), Err(VaultError::TruncatedInstruction.into()));
+    assert_eq!(VaultInstruction::unpack(&[14]), Err(VaultError::TruncatedInstruction.into()));
+  }
+
+  #[test]
+  fn unpack_rejects_trailing_bytes() {
+    let mut data = VaultInstruction::Withdraw { amount: 1, authority_bump: Some(255) }.pack();
+    data.push(0);
+    assert_eq!(VaultInstruction::unpack(&data), Err(VaultError::TrailingInstructionData.into()));
+
+    assert_eq!(VaultInstruction::unpack(&[7, 0]), Err(VaultError::TrailingInstructionData.into()));
+  }
+
+  // One instance of every variant: each tag decoded from the shortest all-zero payload it accepts
+  fn every_variant() -> Vec<VaultInstruction> {
+    (0..=u8::MAX)
+      .filter_map(|tag| {
+        (0..512).find_map(|len| {
+          let mut data = vec![0u8; len + 1];
+          data[0] = tag;
+          VaultInstruction::unpack(&data).ok()
+        })
+      })
+      .collect()
+  }
+
+  #[test]
+  fn every_tag_up_to_the_last_decodes() {
+    let variants = every_variant();
+    assert_eq!(variants.len(), VaultInstruction::GetConfig.tag() as usize + 1);
+    for (tag, ix) in variants.iter().enumerate() {
+      assert_eq!(ix.tag() as usize, tag);
+    }
+  }
+
+  #[test]
+  fn no_owner_gated_instruction_moves_vault_funds() {
+    for ix in every_variant() {
+      assert!(!(is_owner_gated(&ix) && moves_vault_funds(&ix)), "{:?} is owner-gated and moves vault funds", ix);
+    }
+  }
+
+  #[test]
+  fn compressed_deposit_is_the_unavailable_bit() {
+    let ix = VaultInstruction::CompressedDeposit { amount: 0, previous_balance: 0, index: 0, root: [0; 32] };
+    assert_eq!(crate::state::UNAVAILABLE_INSTRUCTIONS, 1 << ix.tag());
+  }
+
+  #[test]
+  fn required_accounts_follow_the_flags_and_append_extras_read_only() {
+    let ix = VaultInstruction::SetPaused { paused: false };
+    let keys = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+    let accounts = required_accounts(&ix, &keys);
+
+    assert_eq!(accounts[0], AccountMeta::new_readonly(keys[0], true));
+    assert_eq!(accounts[1], AccountMeta::new(keys[1], false));
+    assert_eq!(accounts[2], AccountMeta::new_readonly(keys[2], false));
+  }
+}
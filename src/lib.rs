@@ -9,7 +9,11 @@ use solana_program::{
 
 // Declare separate modules for organization and maintainability
 
+pub mod compression;                            // Optional concurrent Merkle tree storage for user balances (spl-account-compression CPI)
+pub mod error;                                  // Custom VaultError codes surfaced to clients as ProgramError::Custom
+pub mod events;                                 // Event payloads logged with sol_log_data, and the decode_event parser
 pub mod instruction;                            // Defines custom instruction data formats (e.g., VaultCreate, VaultDeposit)
+pub mod pda;                                    // Seeds and derivation helpers for every PDA the program uses
 pub mod processor;                             // Contains the core logic for handling instructions
 pub mod state;                                // Defines the accounts (data structures) used in the program, e.g., Vault
 
@@ -0,0 +1,81 @@
+// Program derived addresses used by the vault. The processor derives every PDA through these helpers, so clients
+// calling the same functions can't end up with seeds that have drifted from what the program checks.
+use solana_program::pubkey::Pubkey;                  // For identifying accounts and programs
+
+// Seed prefix of a vault's state account: ["vault_state", owner, mint]
+pub const VAULT_STATE_SEED: &[u8] = b"vault_state";
+
+// Seed prefix of a user's position in a vault: ["user_vault", user, vault state]
+pub const USER_VAULT_SEED: &[u8] = b"user_vault";
+
+// Seed of the authority PDA that owns vault token accounts and signs withdrawals: ["vault"]
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault";
+
+// Seed prefix of a user's whitelist entry for a permissioned vault: ["whitelist", vault state, user]
+pub const WHITELIST_SEED: &[u8] = b"whitelist";
+
+// Seed prefix of a vault's reward authority, which owns the token account ClaimRewards pays from: ["reward_authority", vault state]
+pub const REWARD_AUTHORITY_SEED: &[u8] = b"reward_authority";
+
+// Seed prefix of an extra mint held by a vault: ["vault_asset", vault state, mint]
+pub const VAULT_ASSET_SEED: &[u8] = b"vault_asset";
+
+// The vault state account for `owner`'s vault of `mint`
+pub fn vault_state(program_id: &Pubkey, owner: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[VAULT_STATE_SEED, owner.as_ref(), mint.as_ref()], program_id)
+}
+
+// The user vault account holding `user`'s position in `vault_state`
+pub fn user_vault(program_id: &Pubkey, user: &Pubkey, vault_state: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[USER_VAULT_SEED, user.as_ref(), vault_state.as_ref()], program_id)
+}
+
+// The vault authority PDA
+pub fn vault_authority(program_id: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[VAULT_AUTHORITY_SEED], program_id)
+}
+
+// The whitelist entry letting `user` deposit into `vault_state`
+pub fn whitelist(program_id: &Pubkey, vault_state: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[WHITELIST_SEED, vault_state.as_ref(), user.as_ref()], program_id)
+}
+
+// The asset account for `mint` held by `vault_state`
+pub fn vault_asset(program_id: &Pubkey, vault_state: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[VAULT_ASSET_SEED, vault_state.as_ref(), mint.as_ref()], program_id)
+}
+
+// The reward authority of `vault_state`; per vault, unlike the vault authority, so claims can't reach another vault's tokens
+pub fn reward_authority(program_id: &Pubkey, vault_state: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(&[REWARD_AUTHORITY_SEED, vault_state.as_ref()], program_id)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn helpers_match_find_program_address_on_the_documented_seeds() {
+    let program_id = Pubkey::new_unique();
+    let (owner, mint, user) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+    let vault = vault_state(&program_id, &owner, &mint);
+    assert_eq!(vault, Pubkey::find_program_address(&[b"vault_state", owner.as_ref(), mint.as_ref()], &program_id));
+    assert_eq!(
+      user_vault(&program_id, &user, &vault.0),
+      Pubkey::find_program_address(&[b"user_vault", user.as_ref(), vault.0.as_ref()], &program_id),
+    );
+    assert_eq!(vault_authority(&program_id), Pubkey::find_program_address(&[b"vault"], &program_id));
+    assert_eq!(
+      whitelist(&program_id, &vault.0, &user),
+      Pubkey::find_program_address(&[b"whitelist", vault.0.as_ref(), user.as_ref()], &program_id),
+    );
+  }
+
+  #[test]
+  fn user_vaults_differ_per_vault() {
+    let program_id = Pubkey::new_unique();
+    let user = Pubkey::new_unique();
+    assert_ne!(user_vault(&program_id, &user, &Pubkey::new_unique()), user_vault(&program_id, &user, &Pubkey::new_unique()));
+  }
+}
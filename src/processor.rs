@@ -5,16 +5,29 @@ use solana_program::{
   msg,                                                    // Logging macro for debugging
   program::{invoke, invoke_signed},                       // For making CPI (cross-program invocations)
   program_error::ProgramError,                            // Standard error type
+  program_option::COption,                                // Optional token account fields such as close_authority
+  program_pack::{IsInitialized, Pack},                    // Brings pack/unpack into scope for Vault, UserVault and TokenAccount
   pubkey::Pubkey,                                         // Public key type used for account IDs
-  sysvar::{rent::Rent, Sysvar},                           // Rent system variable for checking rent-exempt status
+  system_instruction,                                     // System program instructions, used to create program accounts
+  sysvar::{clock::Clock, rent::Rent, Sysvar},                           // Rent system variable for checking rent-exempt status
 };
 
+// Returning data to callers needs runtime support, so it's only pulled in with the `return-data` feature
+#[cfg(feature = "return-data")]
+use solana_program::program::set_return_data;
+
 // Import the SPL Token account state definition to interact with token accounts
-use spl_token::state::Account as TokenAccount;
+use spl_token::state::{Account as TokenAccount, Mint};
 
 // Import your program-specific types
-use crate::instruction::VaultInstruction;                 // Custom enum representing supported instructions
-use crate::state::Vault;                                  // Vault account struct
+use crate::instruction::{self, VaultInstruction};         // Custom enum representing supported instructions
+use crate::state::{self, Vault};                           // Vault account struct and name helpers
+use crate::state::UserVault;                              // Per-user deposit record struct
+use crate::state::VaultLimits;                            // Limits snapshot returned by GetLimits
+use crate::error::VaultError;                             // Program-specific error codes
+use crate::compression;                                   // Concurrent Merkle tree CPI helpers
+use crate::pda;                                           // Vault, user vault and authority PDA derivations
+use crate::events::{DepositEvent, VaultEvent, WithdrawEvent}; // Structured events for indexers
 
 // Main entry point for the program's logic
 pub fn process_instruction(
@@ -23,17 +36,134 @@ pub fn process_instruction(
   instruction_data: &[u8],                              // Raw instruction data that will be deserialized into an enum
 ) -> ProgramResult {
   // Deserialize the instruction data into a VaultInstruction variant
-  let instruction = VaultInstruction::unpack(instruction_data).ok_or(ProgramError::InvalidInstructionData)?;
+  let instruction = VaultInstruction::unpack(instruction_data)?;
+
+  // With strict-accounts, trailing accounts the instruction never reads point to a client bug rather than being ignored
+  #[cfg(feature = "strict-accounts")]
+  if let Some(max) = instruction::max_accounts(&instruction) {
+    if accounts.len() > max {
+      return Err(VaultError::TooManyAccounts.into());
+    }
+  }
+
+  // Every account passed must be at least as privileged as account_flags declares, the same table the builders use
+  validate_accounts(&instruction, accounts)?;
+
+  // Two required signers must be two different keys, otherwise one signature would satisfy both roles
+  check_distinct_signers(&instruction, accounts)?;
+
+  // Per-vault allow-list: the owner can switch individual instructions off for their vault
+  if let Some(index) = instruction::vault_state_index(&instruction) {
+    let vault_state_account = accounts.get(index).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let vault = Vault::load(vault_state_account, program_id)?;
+    if !vault.is_instruction_enabled(instruction.tag()) {
+      return Err(VaultError::InstructionDisabled.into());
+    }
+  }
+
+  // A frozen vault's configuration is final, whoever signs
+  if instruction::changes_config(&instruction) {
+    let vault_state_account = accounts.get(1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if Vault::load(vault_state_account, program_id)?.config_frozen {
+      return Err(VaultError::ConfigFrozen.into());
+    }
+  }
 
   // Dispatch logic based on which instruction was sent
   match instruction {
-    VaultInstruction::InitVault => init_vault(program_id, accounts),                            // Handle vault creation
-    VaultInstruction::Deposit { amount } => deposit_tokens(program_id, accounts, amount),       // Handle token deposit
-    VaultInstruction::Withdraw { amount } => withdraw_tokens(program_id, accounts, amount),     // Handle token withdrawal
+    VaultInstruction::InitVault { name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration } => {
+      init_vault(program_id, accounts, name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration) // Handle vault creation
+    }
+    VaultInstruction::Deposit { amount, memo } => deposit_tokens(program_id, accounts, amount, memo, 0), // Handle token deposit
+    VaultInstruction::Withdraw { amount, authority_bump } => {
+      withdraw_tokens(program_id, accounts, amount, authority_bump, false)                      // Handle token withdrawal
+    }
+    VaultInstruction::InitCompressedTree { max_depth, max_buffer_size } => {
+      init_compressed_tree(program_id, accounts, max_depth, max_buffer_size)                    // Switch the vault to compressed balances
+    }
+    VaultInstruction::CompressedDeposit { amount, previous_balance, index, root } => {
+      compressed_deposit(program_id, accounts, amount, previous_balance, index, root)           // Deposit into a compressed vault
+    }
+    VaultInstruction::SetArbiter { arbiter } => set_arbiter(program_id, accounts, arbiter),     // Assign or clear the release arbiter
+    VaultInstruction::RequestConditionalWithdraw { amount } => {
+      request_conditional_withdraw(program_id, accounts, amount)                                // User opens a release request
+    }
+    VaultInstruction::ConditionalWithdraw => conditional_withdraw(program_id, accounts),        // Arbiter releases the request
+    VaultInstruction::GetWithdrawable => get_withdrawable(program_id, accounts),                // Read-only withdrawal estimate
+    VaultInstruction::SetEnabledInstructions { enabled_instructions } => {
+      set_enabled_instructions(program_id, accounts, enabled_instructions)                      // Owner toggles instructions
+    }
+    VaultInstruction::EnsureUserVault => ensure_user_vault(program_id, accounts),                // Idempotent user PDA creation
+    VaultInstruction::DistributeRewards { total_reward } => distribute_rewards(program_id, accounts, total_reward), // Owner funds rewards
+    VaultInstruction::SetMinWithdraw { min_withdraw } => {
+      set_min_withdraw(program_id, accounts, min_withdraw)                                      // Owner sets the withdrawal floor
+    }
+    VaultInstruction::GetLimits => get_limits(program_id, accounts),                            // Read-only limits snapshot
+    VaultInstruction::SetPaused { paused } => set_paused(program_id, accounts, paused),         // Owner pauses or unpauses the vault
+    VaultInstruction::CloseVault => close_vault(program_id, accounts),                          // Owner reclaims rent from an empty vault
+    VaultInstruction::SetMaxSingleDeposit { max_single_deposit } => {
+      set_max_single_deposit(program_id, accounts, max_single_deposit)                          // Owner caps individual deposits
+    }
+    VaultInstruction::TransferOwnership => transfer_ownership(program_id, accounts),            // Owner rotates the vault authority
+    VaultInstruction::SetCooldownExempt { exempt } => set_cooldown_exempt(program_id, accounts, exempt), // Owner sets the cooldown-exempt account
+    VaultInstruction::SetName { name } => set_name(program_id, accounts, name),                 // Owner renames the vault
+    VaultInstruction::TransferPosition { amount, to } => {
+      transfer_position(program_id, accounts, amount, to)                                       // User hands their balance to another user
+    }
+    VaultInstruction::WithdrawAll => withdraw_all(program_id, accounts),                        // Withdraw the whole position
+    VaultInstruction::SetMaxVaultWithdrawPerWindow { max_vault_withdraw_per_window } => {
+      set_max_vault_withdraw_per_window(program_id, accounts, max_vault_withdraw_per_window)    // Owner caps vault-wide withdrawals per window
+    }
+    VaultInstruction::PreflightCheck { op, amount } => {
+      preflight_check(program_id, accounts, op, amount)                                         // Read-only gating dry run
+    }
+    VaultInstruction::InitVaultAndDeposit { name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration, amount } => {
+      init_vault(program_id, accounts, name, deposit_cap, withdrawal_fee_bps, fee_treasury, lock_duration)?; // Create the vault
+      deposit_into_new_vault(program_id, accounts, amount)                                      // Then make its first deposit
+    }
+    VaultInstruction::GetUserCount => get_user_count(program_id, accounts),                     // Read-only user counter
+    VaultInstruction::Migrate => migrate(program_id, accounts),                                 // Owner upgrades a legacy vault account
+    VaultInstruction::SetFeeTiers { tiers } => set_fee_tiers(program_id, accounts, tiers),      // Owner sets size-based withdrawal fees
+    VaultInstruction::AddToWhitelist => add_to_whitelist(program_id, accounts),                 // Owner whitelists a depositor
+    VaultInstruction::RemoveFromWhitelist => remove_from_whitelist(program_id, accounts),       // Owner removes a depositor
+    VaultInstruction::SetRequireWhitelisted { require_whitelisted } => {
+      set_require_whitelisted(program_id, accounts, require_whitelisted)                        // Owner toggles the deposit whitelist
+    }
+    VaultInstruction::WithdrawUnwrapped { amount, authority_bump } => {
+      withdraw_tokens(program_id, accounts, amount, authority_bump, true)                       // Withdraw wrapped SOL as native SOL
+    }
+    VaultInstruction::DepositLocked { amount, locked_until } => {
+      deposit_tokens(program_id, accounts, amount, None, locked_until)                          // Deposit that vests the position
+    }
+    VaultInstruction::AddAsset => add_asset(program_id, accounts),                              // Register another mint for the vault
+    VaultInstruction::DepositAsset { amount } => deposit_asset(program_id, accounts, amount),   // Deposit into one of the vault's extra assets
+    VaultInstruction::WithdrawAsset { amount } => withdraw_asset(program_id, accounts, amount), // Withdraw from one of the vault's extra assets
+    VaultInstruction::GetLockStatus => get_lock_status(program_id, accounts),                   // Locked vs withdrawable split of a position
+    VaultInstruction::SetRewardRate { reward_rate_per_second } => {
+      set_reward_rate(program_id, accounts, reward_rate_per_second)                             // Configure time-based rewards
+    }
+    VaultInstruction::ClaimRewards => claim_rewards(program_id, accounts),                      // Pay out accrued time-based rewards
+    VaultInstruction::SetAdmins { admins, admin_threshold } => {
+      set_admins(program_id, accounts, admins, admin_threshold)                                 // Configure threshold-signed admin instructions
+    }
+    VaultInstruction::SetInstructionFee { instruction_fee_lamports, instruction_fee_treasury } => {
+      set_instruction_fee(program_id, accounts, instruction_fee_lamports, instruction_fee_treasury) // Owner sets the per-instruction SOL fee
+    }
+    VaultInstruction::FreezeConfig => freeze_config(program_id, accounts),                      // Owner makes the vault config permanent
+    VaultInstruction::CloseUserVault => close_user_vault(program_id, accounts),                 // User reclaims rent from an emptied position
+    VaultInstruction::GetConfig => get_config(program_id, accounts),                            // Read-only full vault snapshot
   }
 }
 
-fn init_vault(program_id: &Pubkey, accounts: &[AccountInfo],) -> ProgramResult {
+fn init_vault(
+  program_id: &Pubkey,
+  accounts: &[AccountInfo],
+  name: [u8; 32],                                       // UTF-8 label, zero padded
+  deposit_cap: u64,                                     // Ceiling on total deposits, 0 for unlimited
+  withdrawal_fee_bps: u16,                              // Fee on withdrawals, at most MAX_WITHDRAWAL_FEE_BPS
+  fee_treasury: Pubkey,                                 // Token account the fee is paid into
+  lock_duration: i64,                                   // Seconds between a user's deposit and their next withdrawal
+) -> ProgramResult {
   // Create an iterator over the accounts passed into the transaction
   let account_info_iter = &mut accounts.iter();
 
@@ -63,19 +193,104 @@ fn init_vault(program_id: &Pubkey, accounts: &[AccountInfo],) -> ProgramResult {
     return Err(ProgramError::MissingRequiredSignature);
   }
 
-  // Try to load (but not validate) the vault account data into a Vault struct
-  let mut vault_data = Vault::unpack_unchecked(&vault_account.try_borrow_data()?)?;
+  // Reject a name UIs couldn't render before any account gets created
+  state::check_name(&name)?;
+
+  // The fee is fixed for the vault's lifetime, so it's bounded here; a fee also needs somewhere to go
+  if withdrawal_fee_bps > state::MAX_WITHDRAWAL_FEE_BPS {
+    return Err(VaultError::FeeTooHigh.into());
+  }
+  if withdrawal_fee_bps > 0 && fee_treasury == Pubkey::default() {
+    return Err(ProgramError::InvalidArgument);
+  }
+
+  // A negative lock would read as "no lock" anyway; reject it rather than store a value that means something else
+  if lock_duration < 0 {
+    return Err(ProgramError::InvalidArgument);
+  }
+
+  // The vault gets pinned to this token program below, so it has to be the real SPL Token program
+  if token_program.key != &spl_token::id() {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  // total_deposits starts at 0, so tokens already sitting in the vault token account (pre-seeded, or sent by an attacker)
+  // would be unaccounted for; the account has to hold the vault's mint and start out empty
+  let vault_token = check_token_account_mint(vault_token_account, token_mint.key)?;
+  if vault_token.amount != 0 {
+    return Err(VaultError::PreFundedAccount.into());
+  }
+
+  // The vault state account is a PDA of ["vault_state", owner, mint], so each owner gets one vault per mint
+  let (expected_vault_pda, vault_bump) = pda::vault_state(program_id, initializer.key, token_mint.key);
+  if expected_vault_pda != *vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  // The vault authority bump is recorded once here so withdrawals can rebuild the signer with create_program_address
+  let (vault_authority, vault_authority_bump) = pda::vault_authority(program_id);
+
+  // Deposits are only safe if the program controls the account they land in; a token account whose authority is
+  // anyone else could be drained by that key
+  if vault_token.owner != vault_authority {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  let rent = Rent::from_account_info(rent_sysvar)?;
+
+  // Create the vault state account on first use: allocate Vault::LEN bytes, fund it to rent exemption and hand it to this program
+  if vault_account.data_is_empty() {
+    invoke_signed(
+      &system_instruction::create_account(
+        initializer.key,                                   // Payer
+        vault_account.key,                                 // New account
+        rent.minimum_balance(Vault::LEN),                  // Lamports for rent exemption
+        Vault::LEN as u64,                                 // Space
+        program_id,                                        // Owner
+      ),
+      &[
+        initializer.clone(),
+        vault_account.clone(),
+        system_program.clone(),
+      ],
+      &[&[pda::VAULT_STATE_SEED, initializer.key.as_ref(), token_mint.key.as_ref(), &[vault_bump]]],
+    )?;
+  }
+
+  // Whether just created or pre-allocated by the client, the vault must never be at risk of being purged for rent
+  if !rent.is_exempt(vault_account.lamports(), Vault::LEN) {
+    return Err(ProgramError::AccountNotRentExempt);
+  }
 
-   // Make sure we're not reusing an already-initialized vault account
-  if vault_data.is_initialized {
+  // Make sure we're not reusing an already-initialized vault account. A closed vault is a different matter: reopening it
+  // is intended. The address is the owner's own ["vault_state", owner, mint] PDA, so nobody else can reopen it. CloseVault
+  // zeroes the data and drains the lamports, so the account is gone once that transaction ends, and InitVault creates
+  // it afresh. Within the closing transaction, create_account still fails because the account is owned by this program.
+  // No user vault record outlives the vault (CloseVault requires user_count == 0), so the reopened vault's user_count
+  // starting from zero is accurate. Whitelist entries and VaultAsset accounts do outlive it and start working again.
+  if Vault::unpack_unchecked(&vault_account.try_borrow_data()?)?.is_initialized {
     return Err(ProgramError::AccountAlreadyInitialized);
   }
 
-  // Populate the Vault struct with the initial values
-  vault_data.is_initialized = true;
-  vault_data.owner = *initializer.key;
-  vault_data.token_mint = *token_mint.key;
-  vault_data.vault_token_account = *vault_token_account.key;
+  // Populate the Vault struct with the initial values. Everything not listed starts at zero: no deposits, no tree,
+  // not paused, no limits, caps, tiers or whitelist until the owner sets them, and no user vaults yet.
+  let vault_data = Vault {
+    version: state::VAULT_VERSION,                           // A zeroed account reads as legacy, but new vaults start on the current layout
+    is_initialized: true,
+    owner: *initializer.key,
+    token_mint: *token_mint.key,
+    vault_token_account: *vault_token_account.key,
+    allowed_token_program: *token_program.key,               // Every later token CPI must go to this same program
+    enabled_instructions: !state::UNAVAILABLE_INSTRUCTIONS,  // Every instruction that can be enabled starts enabled
+    last_reward_epoch: u64::MAX,                             // Sentinel for "never", so epoch 0 isn't treated as already rewarded
+    vault_bump: vault_authority_bump,                        // Reused by every withdrawal instead of a fresh bump search
+    name,                                                    // Label shown by UIs, renameable with SetName
+    deposit_cap,                                             // 0 leaves total deposits uncapped
+    withdrawal_fee_bps,                                      // Taken out of every Withdraw
+    fee_treasury,
+    lock_duration,                                           // 0 lets users withdraw right after depositing
+    ..Vault::default()
+  };
 
   // Serialize the updated Vault struct back into the vault account's data
   Vault::pack(vault_data, &mut vault_account.try_borrow_mut_data()?)?;
@@ -83,14 +298,56 @@ fn init_vault(program_id: &Pubkey, accounts: &[AccountInfo],) -> ProgramResult {
    // Log success message for debugging
   msg!("Vault successfully initialized");
 
+  // Hand back the vault authority and its bump, so the client can pass the bump to Withdraw without deriving it
+  let mut authority = [0u8; 33];
+  authority[..32].copy_from_slice(vault_authority.as_ref());
+  authority[32] = vault_authority_bump;
+  emit_return_data(&authority);
+
   Ok(())
 
 }
 
+// Second half of InitVaultAndDeposit, run right after init_vault has set the vault up from accounts 0-6. The deposit goes
+// through the regular Deposit path with the accounts rearranged into Deposit's order, so it gets every deposit check
+// and creates the owner's user vault. An error here fails the whole instruction, vault creation included.
+fn deposit_into_new_vault(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let token_mint = next_account_info(account_info_iter)?;
+  let vault_token_account = next_account_info(account_info_iter)?;
+  let _rent_sysvar = next_account_info(account_info_iter)?;
+  let token_program = next_account_info(account_info_iter)?;
+  let system_program = next_account_info(account_info_iter)?;
+  let owner_source_token_account = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+
+  deposit_tokens(
+    program_id,
+    &[
+      owner.clone(),
+      owner_source_token_account.clone(),
+      vault_token_account.clone(),
+      vault_state_account.clone(),
+      user_vault_account.clone(),
+      token_program.clone(),
+      system_program.clone(),
+      token_mint.clone(),
+    ],
+    amount,
+    None,
+    0,
+  )
+}
+
 fn deposit_tokens(
   program_id: &Pubkey,                                 // Public key of the program
-  accounts: &[accounts],                                // The list of accounts passed to the instruction
+  accounts: &[AccountInfo],                             // The list of accounts passed to the instruction
   amount: u64,                                          // The amount or number of tokens to deposit
+  memo: Option<String>,                                 // Optional memo logged through the SPL Memo program
+  locked_until: i64,                                    // Vesting timestamp for the position, 0 to leave it as is
 ) -> ProgramResult {
   // Create a mutable iterator over the accounts list so that each account can be processed in order
   let account_info_iter = &mut accounts.iter();          
@@ -101,145 +358,342 @@ fn deposit_tokens(
   let vault_state_account = next_account_info(account_info_iter)?;          // The account holding the vault's state/configuration data
   let user_vault_account = next_account_info(account_info_iter)?;           // New PDA account
   let token_program = next_account_info(account_info_iter)?;                // The SPL Token program required for token transfer
+  let system_program = next_account_info(account_info_iter)?;               // The System program, used to create the user vault PDA on first deposit
+  let token_mint = next_account_info(account_info_iter)?;                   // The vault's mint, transfer_checked validates decimals against it
 
   // Check that the depositor signed the transaction to prevent unauthorized access
   if !depositor.is_signer {
     return Err(ProgramError::MissingRequiredSignature);
   }
 
-  // Deserialize the vault state account into a Vault struct
-  let mut vault = Vault::unpack(&vault_state_account.try_borrow_data()?)?;
+  // A zero deposit would be a no-op transfer and could leave behind an empty user vault record
+  if amount == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  // Deserialize the vault state account into a Vault struct, refusing anything this program doesn't own
+  let mut vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;                                 // Frozen vaults move no funds in or out
+  vault.check_single_deposit(amount)?;
+  vault.check_deposit_cap(amount)?;                          // Keeps assets under management within the operator's limit
+
+  // Permissioned vaults only take deposits from users the owner has whitelisted
+  if vault.require_whitelisted {
+    check_whitelisted(program_id, vault_state_account.key, depositor.key, next_account_info(account_info_iter)?)?;
+  }
+
+  // The depositor pays the vault's SOL fee, if it charges one, before the deposit goes through
+  #[cfg(feature = "instruction-fees")]
+  if vault.instruction_fee_lamports > 0 {
+    charge_instruction_fee(&vault, depositor, next_account_info(account_info_iter)?, system_program)?;
+  }
+
+  // Only CPI into the token program this vault was initialized with, and never into anything but the real SPL Token
+  // program: a look-alike could fake the transfer
+  vault.check_token_program(token_program.key)?;
+  if token_program.key != &spl_token::id() {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  // Tokens must land in this vault's own token account, and both sides must hold the vault's mint,
+  // otherwise a depositor could get credited for sending a different (worthless) token
+  if *vault_token_account.key != vault.vault_token_account {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  let vault_token = check_token_account_mint(vault_token_account, &vault.token_mint)?;
+  check_token_account_mint(user_source_token_account, &vault.token_mint)?;
+
+  // Re-checked on every deposit: if the authority were ever moved off the vault PDA, deposits would go to someone else's account
+  let (vault_authority, _bump) = vault_authority(program_id, &vault, None)?;
+  if vault_token.owner != vault_authority {
+    return Err(ProgramError::InvalidAccountData);
+  }
 
   // Safely increment the vault's total_deposits by the new deposit amount. `checked_add` protects against overflow; returns error if overflow would occur.
-  vault.total_deposits = vault.total_deposits.checked_add(amount).ok_or(ProgramError::InvalidInstructionData)?;
+  vault.total_deposits = vault.total_deposits.checked_add(amount).ok_or(VaultError::Overflow)?;
 
-  // Save (pack) the updated vault state back into the vault_state_account's data. `try_borrow_mut_data` ensures we're safely getting a mutable reference to the account's data.
-  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+  // Keep the reward accumulator around to settle the user's position below
+  let reward_index = vault.reward_index;
 
   // Derive the expected PDA for the user's vault account. Seeds for include "user_vault", depositor pubkey, and vault state pubkey.
   // This ensures a unique address per user-vault combination and program.
-  let (expected_user_vault_pda, _bump) = Pubkey::find_program_address(
-    &[b"user_vault", depositor.key.as_ref(), vault_state_account.key.as_ref()],
-    program_id,
-  );
+  let (expected_user_vault_pda, user_vault_bump) = pda::user_vault(program_id, depositor.key, vault_state_account.key);
 
   // Check if the derived PDA matches the actual provided user_vault_account. This ensures the user isn't trying to spoof a different PDA.
   if expected_user_vault_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  // First-time depositors have no account yet, so allocate it (paid by the depositor) before anything is packed into it
+  let is_new_user = user_vault_account.data_is_empty();
+  if is_new_user {
+    vault.user_count = vault.user_count.checked_add(1).ok_or(VaultError::Overflow)?;
+    create_user_vault_account(
+      program_id,
+      depositor,
+      user_vault_account,
+      system_program,
+      &[pda::USER_VAULT_SEED, depositor.key.as_ref(), vault_state_account.key.as_ref(), &[user_vault_bump]],
+    )?;
+  }
+
+  // Handle initialization or loading of the user's vault data. A first-time depositor gets a fully populated record in memory;
+  // nothing is written to the account until the single pack below, so a failure anywhere in between reverts cleanly.
+  let mut user_vault_data = load_or_init_user_vault(user_vault_account, depositor.key, vault_state_account.key)?;
+
+  // Defense in depth on top of the PDA check: the record being credited has to be this vault's, never another vault's
+  // that happened to land on the same address
+  if user_vault_data.vault != *vault_state_account.key || user_vault_data.user != *depositor.key {
     return Err(ProgramError::InvalidAccountData);
   }
 
-  // Handle initialization or loading of the user's vault data. If the user vault account is empty (first-time depositor), initialize it.
-  let mut user_vault_data = if user_vault_account.data_is_empty() {
-    UserVault {
-      is_initialized: true,
-      user: *depositor.key,
-      vault: *vault_state_account.key,
-      deposited_amount: 0,
-    }
+  // Credit rewards earned on the existing balance before the new deposit starts earning
+  user_vault_data.accrue_time_rewards(vault.reward_rate_per_second, Clock::get()?.unix_timestamp);
+  user_vault_data.settle_rewards(reward_index)?;
+
+  // Safely add the deposit amount to the user's personal deposited amount. As usual `checked_add` again avoids overflow and ensures safe arithmetic.
+  user_vault_data.deposited_amount = user_vault_data
+  .deposited_amount
+  .checked_add(amount)
+  .ok_or(VaultError::Overflow)?;
+
+  // Every deposit restarts the withdrawal lock for the whole position
+  user_vault_data.last_deposit_ts = Clock::get()?.unix_timestamp;
+
+  // A vesting lock only ever extends, so topping up a position can't release what an earlier deposit locked
+  user_vault_data.locked_until = user_vault_data.locked_until.max(locked_until);
+
+  // Kept for the event below
+  let new_total = vault.total_deposits;
+
+  // Save the updated vault state back into the vault_state_account's data. A repeat deposit only moves total_deposits,
+  // so that's updated in place; a first deposit also bumped user_count and needs the full pack.
+  if is_new_user {
+    Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
   } else {
-    // Otherwise, unpack the existing user vault data from the account.
-    UserVault::unpack(&user_vault_account.try_borrow_data()?)?
-  };
-  
+    Vault::add_deposits(&mut vault_state_account.try_borrow_mut_data()?, amount)?;
+  }
+
+  // Write (serialize) the updated user vault struct back into the user_vault_account data. This persists the updated user deposit to Solana storage.
+  UserVault::pack(user_vault_data, &mut user_vault_account.try_borrow_mut_data()?)?;
+
+  // The mint's decimals go into transfer_checked, so the token program rejects the transfer if they don't match
+  let mint = load_vault_mint(token_mint, &vault)?;
+
   // Build the SPL Token transfer instruction
   // This will transfer `amount` tokens from the user's token account to the vault token account
-  let transfer_ix = spl_token::instruction::transfer(
+  let transfer_ix = spl_token::instruction::transfer_checked(
     token_program.key,                             // SPL Token program ID
     user_source_token_account.key,                 // Source token account of user
+    token_mint.key,                                // The vault's mint
     vault_token_account.key,                       // Destination token account (vault's)
     depositor.key,                                 // Authority account that must sign
     &[],                                           // For implementing multi-signers (empty for now)
     amount,                                        // Amount of tokens to deposit to vault
+    mint.decimals,                                 // Decimals the token program checks against the mint
   )?;
 
   // Actually invoke the transfer instruction inside this program. This is a Cross-Program Invocation (CPI) to the Token program
+  // Plain invoke is enough for PDA depositors too: the calling program's invoke_signed already made them a signer here,
+  // and seeds passed to invoke_signed from this program could only ever sign for this program's own PDAs
   invoke(
     &transfer_ix,
     &[
       user_source_token_account.clone(),              // Source account
+      token_mint.clone(),                             // Mint account
       vault_token_account.clone(),                    // Destination account
       depositor.clone(),                              // Authority account
       token_program.clone(),                          // SPL Token program
     ]
   )?;
 
-  // Safely add the deposit amount to the user's personal deposited amount. As usual `checked_add` again avoids overflow and ensures safe arithmetic.
-  user_vault_data.deposited_amount = user_vault_data
-  .deposited_amount
-  .checked_add(amount)
-  .ok_or(ProgramError::InvalidInstructionData)?;
-
-  // Write (serialize) the updated user vault struct back into the user_vault_account data. This persists the updated user deposit to Solana storage.
-  UserVault::pack(user_vault_data, &mut user_vault_account.try_borrow_mut_data()?)?;
-
   // Log a message indicating the deposit was successful plus the actual amount deposited
   msg!("{} tokens deposited by {}", amount, depositor.key);
+  VaultEvent::Deposit(DepositEvent {
+    user: *depositor.key,
+    vault: *vault_state_account.key,
+    amount,
+    new_total,
+  })
+  .emit();
+
+  // The memo is purely informational, so it's attached last once the deposit itself is fully recorded
+  if let Some(memo) = memo {
+    let memo_program = next_account_info(account_info_iter)?;
+    if *memo_program.key != spl_memo::id() {
+      return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke(&spl_memo::build_memo(memo.as_bytes(), &[]), std::slice::from_ref(memo_program))?;
+  }
 
   Ok(())
 }
 
-fn withdraw_tokens(program_id: &Pubkey, accounts: &[accounts], amount: u64) -> ProgramResult {
+fn withdraw_tokens(
+  program_id: &Pubkey,
+  accounts: &[AccountInfo],
+  amount: u64,
+  authority_bump: Option<u8>,                           // Client-supplied bump hint for vaults that never recorded one
+  unwrap_sol: bool,                                     // Close the wrapped-SOL destination into the user's wallet afterwards
+) -> ProgramResult {
   let account_info_iter = &mut accounts.iter();
 
   let user = next_account_info(account_info_iter)?;
   let vault_token_account = next_account_info(account_info_iter)?;
   let user_destination_token_account = next_account_info(account_info_iter)?;
-  let vault_state = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
   let user_vault_account = next_account_info(account_info_iter)?;
   let token_program = next_account_info(account_info_iter)?;
+  let token_mint = next_account_info(account_info_iter)?;
+  let vault_authority_account = next_account_info(account_info_iter)?;
 
   if !user.is_signer {
     return Err(ProgramError::MissingRequiredSignature);
   }
 
-  // Load the current vault state from its account data
-  let mut vault = Vault::unpack(&vault_state_account.try_borrow_data()?)?;
+  // Nothing to move, so don't spend compute on a no-op transfer and repack
+  if amount == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  // Load the current vault state from its account data, refusing anything this program doesn't own
+  let mut vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;                                 // Withdrawals freeze along with deposits
+
+  // Only CPI into the token program this vault was initialized with, and never into anything but the real SPL Token
+  // program: a look-alike could fake the transfer
+  vault.check_token_program(token_program.key)?;
+  if token_program.key != &spl_token::id() {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  // Catch a bad destination here with a clear error instead of an opaque failure inside the transfer CPI
+  let destination = load_destination_token_account(user_destination_token_account, &vault.token_mint)?;
+
+  // Paying out to the vault's own token account would be a self-transfer: no tokens leave, but the position and
+  // total_deposits would still be debited
+  if user_destination_token_account.key == vault_token_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // Unwrapping closes the destination with the user's signature, so it must be a wrapped-SOL account the user alone can
+  // close; checked up front rather than failing in the token program after the withdrawal went through
+  if unwrap_sol {
+    if vault.token_mint != spl_token::native_mint::id() || destination.owner != *user.key {
+      return Err(ProgramError::InvalidArgument);
+    }
+    if destination.close_authority.is_some() && destination.close_authority != COption::Some(*user.key) {
+      return Err(ProgramError::InvalidArgument);
+    }
+  }
+
+  // Funds can only leave from this vault's own token account
+  if *vault_token_account.key != vault.vault_token_account {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  let vault_token = check_token_account_mint(vault_token_account, &vault.token_mint)?;
+
+  // Keep the reward accumulator and withdrawal floor around to check the user's position below
+  let reward_index = vault.reward_index;
+  let min_withdraw = vault.min_withdraw;
 
   // Safely subtract the withdrawal amount from the vault's total deposits. If the vault doesn’t have enough funds recorded, return an error
   vault.total_deposits = vault.total_deposits.checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
 
-  // Save the updated vault state back into the account data
-  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+  // Every withdrawal counts toward the vault-wide allowance for the current window
+  let now = Clock::get()?.unix_timestamp;
+  vault.record_window_withdrawal(amount, now)?;
 
   // Recompute the expected PDA for the user's vault account using seeds. This ensures the client isn't passing in a spoofed or incorrect account
-  let (expected_pda, _bump) = Pubkey::find_program_address(
-    &[b"user_vault", user.key.as_ref(), vault_state_account.key.as_ref()],
-    program_id,
-  );
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
 
   // Validate that the expected PDA matches the provided user vault account
   if expected_pda != *user_vault_account.key {
-    return Err(ProgramError::InvalidAccountData);
+    return Err(VaultError::InvalidPda.into());
   }
 
   // Load the user's vault record.
   let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
 
+  // Defense in depth on top of the PDA check: the record must belong to this signer and this vault
+  if user_vault.user != *user.key {
+    return Err(ProgramError::IllegalOwner);
+  }
+  if user_vault.vault != *vault_state_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // Positions with an arbiter can only be released through ConditionalWithdraw
+  if user_vault.has_arbiter() {
+    return Err(VaultError::ArbiterRequired.into());
+  }
+
+  // Deposits have to sit for lock_duration before anything can come back out
+  if vault.is_withdraw_locked(&user_vault, now) {
+    return Err(VaultError::Locked.into());
+  }
+
+  // Rewards accrued since the last interaction count toward what can be withdrawn; time-based ones are owed separately
+  user_vault.accrue_time_rewards(vault.reward_rate_per_second, now);
+  user_vault.settle_rewards(reward_index)?;
+
   // Ensure the user has enough tokens deposited to withdraw the requested amount
   if user_vault.deposited_amount < amount {
     return Err(ProgramError::InsufficientFunds);
   }
 
+  // Dust-sized withdrawals make deposit/withdraw loops cheap enough to farm rounding, so they're refused.
+  // Emptying the position is always allowed, otherwise a balance under the floor could never leave; such an exit still
+  // pays the minimum fee, so a dust round-trip costs at least one unit on a vault that charges fees.
+  if min_withdraw > 0 && amount < min_withdraw && amount != user_vault.deposited_amount {
+    return Err(VaultError::WithdrawBelowMinimum.into());
+  }
+
+  // The position can be good for more than the token account actually holds, e.g. while funds are deployed.
+  // Payout and fee together come to `amount`, so refuse here with a distinct error rather than fail inside the transfer.
+  if vault_token.amount < amount {
+    return Err(VaultError::InsufficientVaultLiquidity.into());
+  }
+
+  // The whole amount leaves the position, but the vault's fee share of it goes to the treasury instead of the user.
+  // The fee tier is picked by the position's size before the withdrawal, rewards included.
+  // The owner rebalancing their own position pays no fee; it is still only their own position, checked above like anyone's.
+  let fee = vault.withdrawal_fee_for(user.key, amount, user_vault.deposited_amount)?;
+  let payout = amount - fee;
+
   // Subtract the withdrawal amount from the user's deposited balance
   user_vault.deposited_amount -= amount;
 
+  // Save the updated vault state back into the account data
+  write_withdrawal(vault, vault_state_account, amount)?;
+
   // Save the updated user state back into the user vault account
   UserVault::pack(user_vault, &mut user_vault_account.try_borrow_mut_data()?)?;
 
-  // Derive the vault authority PDA, which will sign the token transfer.
-  let (vault_authority, bump_seed) = Pubkey::find_program_address(&[b"vault"], program_id);
+  // Rebuild the vault authority PDA, which will sign the token transfer, from the bump stored at init
+  let (vault_authority, bump_seed) = vault_authority(program_id, &vault, authority_bump)?;
+
+  // The authority has to be among the instruction's accounts for the token program to see its signature
+  if *vault_authority_account.key != vault_authority {
+    return Err(VaultError::InvalidPda.into());
+  }
 
   // Prepare the signer seeds used for invoke_signed, it must match the PDA derivation
-  let seeds = &[b"vault", &[bump_seed]];
+  let seeds: &[&[u8]] = &[pda::VAULT_AUTHORITY_SEED, &[bump_seed]];
+
+  let mint = load_vault_mint(token_mint, &vault)?;
 
   // Construct a token program transfer instruction to send tokens from vault to user.
-  let transfer_ix = spl_token::instruction::transfer(
+  let transfer_ix = spl_token::instruction::transfer_checked(
     token_program.key,
     vault_token_account.key,                          // Vault_token_account = source which is the vault's token holding account
+    token_mint.key,                                   // Token_mint = the vault's mint, whose decimals the token program checks
     user_destination_token_account.key,               // User_destination_token_account which is user's receiving account
     &vault_authority,                                 // Vault_authority = the signer (PDA that owns the vault_token_account). Authority is a PDA, so needs invoke_signed
     &[],                                              // No additional signers needed for now
-    amount,
+    payout,                                           // The requested amount minus the withdrawal fee
+    mint.decimals,
   )?;
 
   // Execute the token transfer with PDA signing via invoke_signed.
@@ -247,14 +701,2151 @@ fn withdraw_tokens(program_id: &Pubkey, accounts: &[accounts], amount: u64) -> P
     &transfer_ix,
     &[
       vault_token_account.clone(),
+      token_mint.clone(),
       user_destination_token_account.clone(),
+      vault_authority_account.clone(),
       token_program.clone(),
     ],
    &[seeds],                                    // Signer seeds used to authorize PDA
   )?;
 
+  // The fee goes to the treasury recorded at init, in the same mint
+  if fee > 0 {
+    let fee_treasury = next_account_info(account_info_iter)?;
+    if *fee_treasury.key != vault.fee_treasury {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    check_token_account_mint(fee_treasury, &vault.token_mint)?;
+
+    let fee_ix = spl_token::instruction::transfer_checked(
+      token_program.key,
+      vault_token_account.key,
+      token_mint.key,
+      fee_treasury.key,
+      &vault_authority,
+      &[],
+      fee,
+      mint.decimals,
+    )?;
+
+    invoke_signed(
+      &fee_ix,
+      &[
+        vault_token_account.clone(),
+        token_mint.clone(),
+        fee_treasury.clone(),
+        vault_authority_account.clone(),
+        token_program.clone(),
+      ],
+      &[seeds],
+    )?;
+  }
+
+  // The vault's SOL fee, if it charges one, follows the withdrawal fee's treasury in the account list
+  #[cfg(feature = "instruction-fees")]
+  if vault.instruction_fee_lamports > 0 {
+    let instruction_fee_treasury = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    charge_instruction_fee(&vault, user, instruction_fee_treasury, system_program)?;
+  }
+
+  let new_total = vault.total_deposits;
+
   // Log a message for off-chain indexing or debugging.
-  msg!("{} tokens withdrawn by {}", amount, user.key);
+  msg!("{} tokens withdrawn by {} ({} fee)", amount, user.key, fee);
+  VaultEvent::Withdraw(WithdrawEvent {
+    user: *user.key,
+    vault: *vault_state_account.key,
+    amount,
+    fee,
+    new_total,
+  })
+  .emit();
+
+  // Closing a native account hands all of its lamports, the withdrawn SOL and its rent reserve, to the user's wallet
+  if unwrap_sol {
+    let close_ix = spl_token::instruction::close_account(
+      token_program.key,
+      user_destination_token_account.key,
+      user.key,                                       // Lamport destination
+      user.key,                                       // Account owner, signed for by the user
+      &[],
+    )?;
+    invoke(&close_ix, &[user_destination_token_account.clone(), user.clone(), token_program.clone()])?;
+  }
+
+  Ok(())
+}
+
+// Withdraw whatever the position holds at execution time, so the client doesn't race its own off-chain balance read.
+// The amount is only read here; withdraw_tokens repeats every check and does the accounting.
+fn withdraw_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let vault_state_account = accounts.get(3).ok_or(ProgramError::NotEnoughAccountKeys)?;
+  let user_vault_account = accounts.get(4).ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+
+  // Include rewards not yet settled into the balance, so the position really ends at zero
+  user_vault.settle_rewards(vault.reward_index)?;
+
+  withdraw_tokens(program_id, accounts, user_vault.deposited_amount, None, false)
+}
+
+fn init_compressed_tree(
+  program_id: &Pubkey,
+  accounts: &[AccountInfo],
+  max_depth: u32,                                       // Depth of the tree, bounds the number of users to 2^max_depth
+  max_buffer_size: u32,                                 // Number of concurrent changelog entries the tree tolerates
+) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let merkle_tree = next_account_info(account_info_iter)?;
+  let tree_authority = next_account_info(account_info_iter)?;
+  let noop_program = next_account_info(account_info_iter)?;
+  let compression_program = next_account_info(account_info_iter)?;
+
+  // Only the vault owner may change how user balances are stored
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Switching modes once users have PDAs (or a tree already exists) would strand existing balances
+  if vault.is_compressed() {
+    return Err(ProgramError::AccountAlreadyInitialized);
+  }
+  if vault.user_count != 0 || vault.total_deposits != 0 {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  if *compression_program.key != compression::ACCOUNT_COMPRESSION_ID || *noop_program.key != compression::NOOP_ID {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  let (expected_authority, authority_bump) = compression::find_tree_authority(vault_state_account.key, program_id);
+  if expected_authority != *tree_authority.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let init_ix = compression::init_empty_merkle_tree(merkle_tree.key, tree_authority.key, max_depth, max_buffer_size);
+
+  // The tree authority is our PDA, so the program signs for it
+  invoke_signed(
+    &init_ix,
+    &[
+      merkle_tree.clone(),
+      tree_authority.clone(),
+      noop_program.clone(),
+      compression_program.clone(),
+    ],
+    &[&[compression::TREE_AUTHORITY_SEED, vault_state_account.key.as_ref(), &[authority_bump]]],
+  )?;
+
+  vault.merkle_tree = *merkle_tree.key;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Compressed balances enabled with tree {}", merkle_tree.key);
 
   Ok(())
-}
\ No newline at end of file
+}
+
+fn compressed_deposit(
+  program_id: &Pubkey,
+  accounts: &[AccountInfo],
+  amount: u64,                                          // The amount of tokens to deposit
+  previous_balance: u64,                                // The user's balance currently committed in their leaf (0 for a new user)
+  index: u32,                                           // The user's leaf index in the tree
+  root: [u8; 32],                                       // The tree root the client built the proof against
+) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let depositor = next_account_info(account_info_iter)?;
+  let user_source_token_account = next_account_info(account_info_iter)?;
+  let vault_token_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let merkle_tree = next_account_info(account_info_iter)?;
+  let tree_authority = next_account_info(account_info_iter)?;
+  let noop_program = next_account_info(account_info_iter)?;
+  let compression_program = next_account_info(account_info_iter)?;
+  let token_program = next_account_info(account_info_iter)?;
+  let token_mint = next_account_info(account_info_iter)?;
+
+  if !depositor.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  if amount == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;
+  vault.check_single_deposit(amount)?;
+  vault.check_deposit_cap(amount)?;                          // Compressed balances count toward the cap like any other
+
+  // A permissioned vault's whitelist entry comes ahead of the proof nodes
+  if vault.require_whitelisted {
+    check_whitelisted(program_id, vault_state_account.key, depositor.key, next_account_info(account_info_iter)?)?;
+  }
+  let proof_accounts = account_info_iter.as_slice();                       // Whatever remains is the leaf proof
+
+  // The tree must be the one recorded on the vault, otherwise a user could credit themselves in a tree they control
+  if !vault.is_compressed() || vault.merkle_tree != *merkle_tree.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  if vault.vault_token_account != *vault_token_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  vault.check_token_program(token_program.key)?;
+
+  if *compression_program.key != compression::ACCOUNT_COMPRESSION_ID || *noop_program.key != compression::NOOP_ID {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  let (expected_authority, authority_bump) = compression::find_tree_authority(vault_state_account.key, program_id);
+  if expected_authority != *tree_authority.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let new_balance = previous_balance.checked_add(amount).ok_or(VaultError::Overflow)?;
+
+  // An empty leaf is all zeroes, so a first-time depositor proves against the zero leaf instead of a balance of 0
+  let previous_leaf = if previous_balance == 0 {
+    [0u8; 32]
+  } else {
+    compression::leaf_hash(vault_state_account.key, depositor.key, index, previous_balance)
+  };
+  let new_leaf = compression::leaf_hash(vault_state_account.key, depositor.key, index, new_balance);
+
+  let mint = load_vault_mint(token_mint, &vault)?;
+
+  // Move the tokens first, the leaf update below reverts the whole transaction if the proof is stale or forged
+  let transfer_ix = spl_token::instruction::transfer_checked(
+    token_program.key,
+    user_source_token_account.key,
+    token_mint.key,
+    vault_token_account.key,
+    depositor.key,
+    &[],
+    amount,
+    mint.decimals,
+  )?;
+
+  invoke(
+    &transfer_ix,
+    &[
+      user_source_token_account.clone(),
+      token_mint.clone(),
+      vault_token_account.clone(),
+      depositor.clone(),
+      token_program.clone(),
+    ]
+  )?;
+
+  let proof: Vec<Pubkey> = proof_accounts.iter().map(|node| *node.key).collect();
+  let replace_ix = compression::replace_leaf(
+    merkle_tree.key,
+    tree_authority.key,
+    root,
+    previous_leaf,
+    new_leaf,
+    index,
+    &proof,
+  );
+
+  let mut cpi_accounts = vec![
+    merkle_tree.clone(),
+    tree_authority.clone(),
+    noop_program.clone(),
+    compression_program.clone(),
+  ];
+  cpi_accounts.extend(proof_accounts.iter().cloned());
+
+  invoke_signed(
+    &replace_ix,
+    &cpi_accounts,
+    &[&[compression::TREE_AUTHORITY_SEED, vault_state_account.key.as_ref(), &[authority_bump]]],
+  )?;
+
+  // The tokens sit in the same vault token account as uncompressed deposits, so they're recorded in the same total
+  Vault::add_deposits(&mut vault_state_account.try_borrow_mut_data()?, amount)?;
+
+  msg!("{} tokens deposited by {} into leaf {}", amount, depositor.key, index);
+
+  Ok(())
+}
+
+
+fn set_arbiter(program_id: &Pubkey, accounts: &[AccountInfo], arbiter: Pubkey) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  if !user.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+
+  // Once an arbiter is in place the user alone can't swap it out, otherwise the escrow would be meaningless
+  if user_vault.has_arbiter() {
+    let current_arbiter = next_account_info(account_info_iter)?;
+    if *current_arbiter.key != user_vault.arbiter {
+      return Err(VaultError::Unauthorized.into());
+    }
+    if !current_arbiter.is_signer {
+      return Err(ProgramError::MissingRequiredSignature);
+    }
+  }
+
+  user_vault.arbiter = arbiter;
+  user_vault.pending_withdrawal = 0;                        // Any open request was made under the old arbiter
+  UserVault::pack(user_vault, &mut user_vault_account.try_borrow_mut_data()?)?;
+
+  msg!("Arbiter for {} set to {}", user.key, arbiter);
+
+  Ok(())
+}
+
+fn request_conditional_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  if !user.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+
+  if !user_vault.has_arbiter() {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  user_vault.settle_rewards(vault.reward_index)?;
+
+  if amount == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  if user_vault.deposited_amount < amount {
+    return Err(ProgramError::InsufficientFunds);
+  }
+
+  // A new request replaces any previous one rather than stacking on top of it
+  user_vault.pending_withdrawal = amount;
+  UserVault::pack(user_vault, &mut user_vault_account.try_borrow_mut_data()?)?;
+
+  msg!("{} requested release of {} tokens", user.key, amount);
+
+  Ok(())
+}
+
+fn conditional_withdraw(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let arbiter = next_account_info(account_info_iter)?;
+  let vault_token_account = next_account_info(account_info_iter)?;
+  let user_destination_token_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let token_program = next_account_info(account_info_iter)?;
+  let token_mint = next_account_info(account_info_iter)?;
+  let vault_authority_account = next_account_info(account_info_iter)?;
+
+  if !arbiter.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+
+  // The user vault must be the genuine PDA for the user and vault it records
+  let (expected_pda, _bump) = pda::user_vault(program_id, &user_vault.user, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  if !user_vault.has_arbiter() || user_vault.arbiter != *arbiter.key {
+    return Err(VaultError::Unauthorized.into());
+  }
+
+  // The arbiter can only release what the user asked for
+  let amount = user_vault.pending_withdrawal;
+  if amount == 0 {
+    return Err(VaultError::NoPendingWithdrawal.into());
+  }
+
+  let mut vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;
+  vault.check_token_program(token_program.key)?;
+  user_vault.accrue_time_rewards(vault.reward_rate_per_second, Clock::get()?.unix_timestamp);
+  user_vault.settle_rewards(vault.reward_index)?;
+
+  // Same floor as Withdraw; emptying the position is still always allowed
+  if vault.min_withdraw > 0 && amount < vault.min_withdraw && amount != user_vault.deposited_amount {
+    return Err(VaultError::WithdrawBelowMinimum.into());
+  }
+
+  // Funds always go back to the user, the arbiter only decides when
+  let destination = load_destination_token_account(user_destination_token_account, &vault.token_mint)?;
+  if destination.owner != user_vault.user {
+    return Err(ProgramError::IllegalOwner);
+  }
+
+  // Resolved before the vault is packed away below
+  let (vault_authority, bump_seed) = vault_authority(program_id, &vault, None)?;
+  if *vault_authority_account.key != vault_authority {
+    return Err(VaultError::InvalidPda.into());
+  }
+  let mint = load_vault_mint(token_mint, &vault)?;
+
+  // Same liquidity check as Withdraw, before any accounting is written
+  if *vault_token_account.key != vault.vault_token_account {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  if check_token_account_mint(vault_token_account, &vault.token_mint)?.amount < amount {
+    return Err(VaultError::InsufficientVaultLiquidity.into());
+  }
+
+  // The arbiter decides when within what the position allows; an unvested or still-locked position stays put even if
+  // the user named a key of their own as arbiter
+  let now = Clock::get()?.unix_timestamp;
+  if vault.is_withdraw_locked(&user_vault, now) {
+    return Err(VaultError::Locked.into());
+  }
+
+  // Same fee as Withdraw, so naming a key of one's own as arbiter is no way around it
+  let fee = vault.withdrawal_fee_for(&user_vault.user, amount, user_vault.deposited_amount)?;
+  let payout = amount - fee;
+
+  // The treasury is checked before any accounting is written
+  let fee_treasury = if fee > 0 {
+    let fee_treasury = next_account_info(account_info_iter)?;
+    if *fee_treasury.key != vault.fee_treasury {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    check_token_account_mint(fee_treasury, &vault.token_mint)?;
+    Some(fee_treasury)
+  } else {
+    None
+  };
+
+  vault.total_deposits = vault.total_deposits.checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+  vault.record_window_withdrawal(amount, now)?;                              // Arbiter releases count toward the window too
+  write_withdrawal(vault, vault_state_account, amount)?;
+
+  user_vault.deposited_amount = user_vault.deposited_amount.checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+  user_vault.pending_withdrawal = 0;
+  UserVault::pack(user_vault, &mut user_vault_account.try_borrow_mut_data()?)?;
+
+  let seeds: &[&[u8]] = &[pda::VAULT_AUTHORITY_SEED, &[bump_seed]];
+
+  let transfer_ix = spl_token::instruction::transfer_checked(
+    token_program.key,
+    vault_token_account.key,
+    token_mint.key,
+    user_destination_token_account.key,
+    &vault_authority,
+    &[],
+    payout,
+    mint.decimals,
+  )?;
+
+  invoke_signed(
+    &transfer_ix,
+    &[
+      vault_token_account.clone(),
+      token_mint.clone(),
+      user_destination_token_account.clone(),
+      vault_authority_account.clone(),
+      token_program.clone(),
+    ],
+    &[seeds],
+  )?;
+
+  if let Some(fee_treasury) = fee_treasury {
+    let fee_ix = spl_token::instruction::transfer_checked(
+      token_program.key,
+      vault_token_account.key,
+      token_mint.key,
+      fee_treasury.key,
+      &vault_authority,
+      &[],
+      fee,
+      mint.decimals,
+    )?;
+
+    invoke_signed(
+      &fee_ix,
+      &[
+        vault_token_account.clone(),
+        token_mint.clone(),
+        fee_treasury.clone(),
+        vault_authority_account.clone(),
+        token_program.clone(),
+      ],
+      &[seeds],
+    )?;
+  }
+
+  msg!("{} tokens released to {} by arbiter {} ({} fee)", payout, destination.owner, arbiter.key, fee);
+
+  Ok(())
+}
+
+
+// Load the vault for an owner-gated instruction: `owner` must have signed and be the vault's recorded owner, and the
+// vault's admin threshold, if it has one, must be met as well
+fn load_owned_vault(
+  program_id: &Pubkey,
+  owner: &AccountInfo,
+  vault_state_account: &AccountInfo,
+  accounts: &[AccountInfo],
+) -> Result<Vault, ProgramError> {
+  if !owner.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+
+  if vault.owner != *owner.key {
+    return Err(VaultError::Unauthorized.into());
+  }
+  check_admin_threshold(&vault, accounts)?;
+
+  Ok(vault)
+}
+
+// On vaults with an admin threshold, owner-gated instructions also need that many distinct admins to have signed.
+// Extra admin signers are passed after the instruction's own accounts; the owner counts too if listed as an admin.
+fn check_admin_threshold(vault: &Vault, accounts: &[AccountInfo]) -> ProgramResult {
+  if vault.admin_threshold == 0 {
+    return Ok(());
+  }
+
+  let mut signed = [false; state::ADMIN_COUNT];
+  for account in accounts.iter().filter(|account| account.is_signer) {
+    if let Some(slot) = vault.admins.iter().position(|admin| *admin != Pubkey::default() && admin == account.key) {
+      signed[slot] = true;                                                  // Listing the same signer twice still counts once
+    }
+  }
+
+  if signed.iter().filter(|&&signed| signed).count() < vault.admin_threshold as usize {
+    return Err(VaultError::ThresholdNotMet.into());
+  }
+  Ok(())
+}
+
+// Persist a vault after `amount` was withdrawn from it. record_window_withdrawal only touches the window fields when
+// there's a vault-wide cap, otherwise total_deposits is the one field that changed and is written in place.
+fn write_withdrawal(vault: Vault, vault_state_account: &AccountInfo, amount: u64) -> ProgramResult {
+  if vault.max_vault_withdraw_per_window == 0 {
+    Vault::sub_deposits(&mut vault_state_account.try_borrow_mut_data()?, amount)
+  } else {
+    Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)
+  }
+}
+
+// Charge the vault's per-instruction SOL fee: a system transfer from `payer`, which must be writable, into the
+// treasury the owner configured
+#[cfg(feature = "instruction-fees")]
+fn charge_instruction_fee<'a>(
+  vault: &Vault,
+  payer: &AccountInfo<'a>,
+  treasury: &AccountInfo<'a>,
+  system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+  if *treasury.key != vault.instruction_fee_treasury {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  invoke(
+    &system_instruction::transfer(payer.key, treasury.key, vault.instruction_fee_lamports),
+    &[payer.clone(), treasury.clone(), system_program.clone()],
+  )
+}
+
+// Require `token_account` to be a token account holding `mint`
+fn check_token_account_mint(token_account: &AccountInfo, mint: &Pubkey) -> Result<TokenAccount, ProgramError> {
+  let account = TokenAccount::unpack(&token_account.try_borrow_data()?)?;
+
+  if account.mint != *mint {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  Ok(account)
+}
+
+// Load the mint account transfer_checked needs; it has to be the vault's own mint, whose decimals the token program enforces
+fn load_vault_mint(mint: &AccountInfo, vault: &Vault) -> Result<Mint, ProgramError> {
+  if *mint.key != vault.token_mint {
+    return Err(VaultError::MintMismatch.into());
+  }
+  Mint::unpack(&mint.try_borrow_data()?)
+}
+
+// Unpack a withdrawal destination, requiring it to be an initialized token account for the mint being withdrawn
+fn load_destination_token_account(destination: &AccountInfo, mint: &Pubkey) -> Result<TokenAccount, ProgramError> {
+  let token_account = TokenAccount::unpack_unchecked(&destination.try_borrow_data()?)
+    .map_err(|_| VaultError::DestinationNotInitialized)?;               // Not even token-account shaped
+
+  if !token_account.is_initialized() {
+    return Err(VaultError::DestinationNotInitialized.into());
+  }
+
+  if token_account.mint != *mint {
+    return Err(VaultError::MintMismatch.into());
+  }
+
+  Ok(token_account)
+}
+
+// Allocate a user vault PDA of UserVault::LEN bytes owned by this program, funded to rent exemption by `payer`.
+// The data is left zeroed; the caller packs the full record afterwards.
+fn create_user_vault_account<'a>(
+  program_id: &Pubkey,
+  payer: &AccountInfo<'a>,
+  user_vault_account: &AccountInfo<'a>,
+  system_program: &AccountInfo<'a>,
+  signer_seeds: &[&[u8]],                               // ["user_vault", user, vault state, bump]
+) -> ProgramResult {
+  let rent = Rent::get()?;
+
+  invoke_signed(
+    &system_instruction::create_account(
+      payer.key,
+      user_vault_account.key,
+      rent.minimum_balance(UserVault::LEN),
+      UserVault::LEN as u64,
+      program_id,
+    ),
+    &[
+      payer.clone(),
+      user_vault_account.clone(),
+      system_program.clone(),
+    ],
+    &[signer_seeds],
+  )?;
+
+  assert_rent_exempt(user_vault_account)
+}
+
+// Close an account this program owns: move every lamport to `destination` so the runtime reclaims the account at the end
+// of the transaction, and wipe and shrink the data first so nothing later in the same transaction can read it as
+// initialized. Returns the lamports moved
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> Result<u64, ProgramError> {
+  let lamports = account.lamports();
+  **destination.try_borrow_mut_lamports()? = destination.lamports().checked_add(lamports).ok_or(VaultError::Overflow)?;
+  **account.try_borrow_mut_lamports()? = 0;
+
+  account.try_borrow_mut_data()?.fill(0);
+  account.realloc(0, false)?;
+
+  Ok(lamports)
+}
+
+// Check an account this program just created holds enough lamports to be rent-exempt at its allocated size,
+// so an underfunded create_account fails the transaction instead of leaving an account that could be reaped
+fn assert_rent_exempt(account: &AccountInfo) -> ProgramResult {
+  let rent = Rent::get()?;
+  if !rent.is_exempt(account.lamports(), account.data_len()) {
+    return Err(ProgramError::AccountNotRentExempt);
+  }
+  Ok(())
+}
+
+// Require `whitelist_account` to be `user`'s initialized whitelist entry under `vault_state`
+fn check_whitelisted(program_id: &Pubkey, vault_state: &Pubkey, user: &Pubkey, whitelist_account: &AccountInfo) -> ProgramResult {
+  let (expected_pda, _bump) = pda::whitelist(program_id, vault_state, user);
+  if expected_pda != *whitelist_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  // A removed (or never added) user has no entry, which is exactly the not-whitelisted case
+  if whitelist_account.owner != program_id || whitelist_account.data_len() < state::Whitelist::LEN {
+    return Err(VaultError::Unauthorized.into());
+  }
+  let entry = state::Whitelist::unpack_unchecked(&whitelist_account.try_borrow_data()?)?;
+  if !entry.is_initialized || entry.vault != *vault_state || entry.user != *user {
+    return Err(VaultError::Unauthorized.into());
+  }
+
+  Ok(())
+}
+
+// Load a user's vault record, or build a fresh one if the account has never been packed.
+// A just-created PDA is all zeroes, so `is_initialized` reads false; such an account is treated exactly like a missing one
+// and is never handed out as an initialized record with default fields.
+fn load_or_init_user_vault(user_vault_account: &AccountInfo, user: &Pubkey, vault: &Pubkey) -> Result<UserVault, ProgramError> {
+  // Empty position with no arbiter; the reward snapshot is settled against the vault's index before the first balance change
+  let fresh = UserVault {
+    is_initialized: true,
+    user: *user,
+    vault: *vault,
+    ..UserVault::default()
+  };
+
+  if user_vault_account.data_is_empty() {
+    return Ok(fresh);
+  }
+
+  // An allocated account too small for the full record can never be packed into; refuse it up front
+  if user_vault_account.data_len() < UserVault::LEN {
+    return Err(ProgramError::AccountDataTooSmall);
+  }
+
+  let existing = UserVault::unpack_unchecked(&user_vault_account.try_borrow_data()?)?;
+  if !existing.is_initialized {
+    return Ok(fresh);
+  }
+
+  // An initialized record must belong to this user and vault
+  if existing.user != *user || existing.vault != *vault {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  Ok(existing)
+}
+
+
+// Check the accounts against the instruction's account_flags before any handler runs, so a read-only account where one
+// gets written, or a missing signature, fails up front with the account's position instead of deep inside a CPI.
+// Optional trailing accounts that weren't passed are left to the handler, which knows whether it needs them.
+fn validate_accounts(ix: &VaultInstruction, accounts: &[AccountInfo]) -> ProgramResult {
+  for (index, (&(is_signer, is_writable), account)) in instruction::account_flags(ix).iter().zip(accounts.iter()).enumerate() {
+    if is_signer && !account.is_signer {
+      msg!("Account {} must be a signer", index);
+      return Err(ProgramError::MissingRequiredSignature);
+    }
+    if is_writable && !account.is_writable {
+      msg!("Account {} must be writable", index);
+      return Err(VaultError::AccountNotWritable.into());
+    }
+  }
+
+  Ok(())
+}
+
+// Every account position the instruction flags as a signer must hold a different key. Optional trailing
+// signers that the client didn't pass are simply not present, so only the supplied accounts are compared.
+fn check_distinct_signers(ix: &VaultInstruction, accounts: &[AccountInfo]) -> ProgramResult {
+  let signer_keys = instruction::account_flags(ix)
+    .iter()
+    .zip(accounts.iter())
+    .filter(|((is_signer, _), _)| *is_signer)
+    .map(|(_, account)| account.key);
+
+  let mut seen: Vec<&Pubkey> = Vec::new();
+  for key in signer_keys {
+    if seen.contains(&key) {
+      return Err(VaultError::DuplicateSigner.into());
+    }
+    seen.push(key);
+  }
+
+  Ok(())
+}
+
+// The ["vault"] authority PDA and its bump. Vaults that recorded their bump at init only need create_program_address;
+// older accounts without one fall back to the search, optionally seeded with a client-supplied bump hint.
+fn vault_authority(program_id: &Pubkey, vault: &Vault, bump_hint: Option<u8>) -> Result<(Pubkey, u8), ProgramError> {
+  if vault.vault_bump == 0 {
+    return Ok(find_program_address_with_hint(&[pda::VAULT_AUTHORITY_SEED], bump_hint, program_id));
+  }
+
+  let authority = Pubkey::create_program_address(&[pda::VAULT_AUTHORITY_SEED, &[vault.vault_bump]], program_id)
+    .map_err(|_| VaultError::InvalidPda)?;
+  Ok((authority, vault.vault_bump))
+}
+
+// Derive a PDA, trying the caller's bump with the cheap `create_program_address` first and only falling back to the
+// `find_program_address` search when no hint is given or the hint doesn't produce a valid off-curve address.
+// A hint that yields a valid but non-canonical address is accepted as-is, so only use this for signing authorities whose
+// key is checked downstream (e.g. by the token program's owner check), never to validate an account the client passed in.
+fn find_program_address_with_hint(seeds: &[&[u8]], bump_hint: Option<u8>, program_id: &Pubkey) -> (Pubkey, u8) {
+  if let Some(bump) = bump_hint {
+    let bump_seed = [bump];
+    let mut seeds_with_bump = seeds.to_vec();
+    seeds_with_bump.push(&bump_seed);
+
+    if let Ok(address) = Pubkey::create_program_address(&seeds_with_bump, program_id) {
+      return (address, bump);
+    }
+  }
+
+  Pubkey::find_program_address(seeds, program_id)
+}
+
+
+fn set_enabled_instructions(program_id: &Pubkey, accounts: &[AccountInfo], enabled_instructions: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Unavailable bits are dropped rather than refused, so a client can still pass u64::MAX for "everything"
+  vault.enabled_instructions = enabled_instructions & !state::UNAVAILABLE_INSTRUCTIONS;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Enabled instructions set to {:#x}", vault.enabled_instructions);
+
+  Ok(())
+}
+
+fn set_min_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], min_withdraw: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  vault.min_withdraw = min_withdraw;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Minimum withdrawal set to {}", min_withdraw);
+
+  Ok(())
+}
+
+fn set_paused(program_id: &Pubkey, accounts: &[AccountInfo], paused: bool) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  vault.paused = paused;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Vault {}", if paused { "paused" } else { "unpaused" });
+
+  Ok(())
+}
+
+fn close_vault(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let destination = next_account_info(account_info_iter)?;
+
+  let vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Users still have money recorded against this vault, closing it would orphan their positions
+  if vault.total_deposits != 0 {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // Every user vault record has to be closed first (CloseUserVault, which the owner can sign for empty records). A record
+  // outliving the vault could never be closed while the vault is gone, and a reopened vault starts its user_count from
+  // zero, so it would never be counted either
+  if vault.user_count != 0 {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // Compressed balances have no withdraw path yet, and the tree is the only record of who holds what, so a compressed
+  // vault is never closed
+  if vault.is_compressed() {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // No close marker is left behind: the owner may reopen the same vault address later with InitVault
+  let lamports = close_account(vault_state_account, destination)?;
+
+  msg!("Vault closed, {} lamports returned to {}", lamports, destination.key);
+
+  Ok(())
+}
+
+// Bring a legacy vault account onto the current layout in place. The account keeps its address, so every user vault
+// PDA derived from it stays valid; the owner pays for the extra rent-exempt balance the larger account needs.
+fn migrate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let system_program = next_account_info(account_info_iter)?;
+
+  // Read with the legacy layout before the account grows; unpack picks the layout from the leading byte
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // No PDA check: legacy vaults predate the ["vault_state", owner, mint] seeds. Being owned by this program and naming
+  // the signer as owner is what makes the account theirs to migrate
+
+  // Nothing to do for a vault that's already current, so retrying a migration is harmless
+  if vault.version == state::VAULT_VERSION {
+    msg!("Vault is already on layout version {}", vault.version);
+    return Ok(());
+  }
+
+  // Top the account up to rent exemption at the new size before growing it
+  let rent = Rent::get()?;
+  let shortfall = rent.minimum_balance(Vault::LEN).saturating_sub(vault_state_account.lamports());
+  if shortfall > 0 {
+    invoke(
+      &system_instruction::transfer(owner.key, vault_state_account.key, shortfall),
+      &[owner.clone(), vault_state_account.clone(), system_program.clone()],
+    )?;
+  }
+
+  vault_state_account.realloc(Vault::LEN, true)?;
+
+  // Legacy vaults never recorded the authority bump; storing it now spares later withdrawals the search
+  let (_vault_authority, vault_authority_bump) = pda::vault_authority(program_id);
+  vault.vault_bump = vault_authority_bump;
+
+  let from_version = vault.version;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Vault migrated from layout version {} to {}", from_version, state::VAULT_VERSION);
+
+  Ok(())
+}
+
+fn set_max_single_deposit(program_id: &Pubkey, accounts: &[AccountInfo], max_single_deposit: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  vault.max_single_deposit = max_single_deposit;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Maximum single deposit set to {}", max_single_deposit);
+
+  Ok(())
+}
+
+fn transfer_ownership(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let new_owner = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // A no-op transfer is almost certainly a client mistake (e.g. wrong account passed), so it's rejected loudly
+  if *new_owner.key == vault.owner {
+    return Err(VaultError::OwnerUnchanged.into());
+  }
+
+  vault.owner = *new_owner.key;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Vault ownership transferred from {} to {}", owner.key, new_owner.key);
+
+  Ok(())
+}
+
+fn set_cooldown_exempt(program_id: &Pubkey, accounts: &[AccountInfo], exempt: Pubkey) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  vault.cooldown_exempt = exempt;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Cooldown exemption set to {}", exempt);
+
+  Ok(())
+}
+
+fn set_name(program_id: &Pubkey, accounts: &[AccountInfo], name: [u8; 32]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  state::check_name(&name)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  vault.name = name;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Vault renamed to {}", String::from_utf8_lossy(state::trim_name(&name)));
+
+  Ok(())
+}
+
+fn transfer_position(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64, to: Pubkey) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let recipient_vault_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let system_program = next_account_info(account_info_iter)?;
+
+  if !user.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  if amount == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  // Both records would be the same account, so the balance would just be counted twice
+  if to == *user.key {
+    return Err(ProgramError::InvalidArgument);
+  }
+
+  let mut vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;
+
+  // On a permissioned vault a position may only be handed to someone who could have deposited it themselves
+  if vault.require_whitelisted {
+    check_whitelisted(program_id, vault_state_account.key, &to, next_account_info(account_info_iter)?)?;
+  }
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let (expected_recipient_pda, recipient_bump) = pda::user_vault(program_id, &to, vault_state_account.key);
+  if expected_recipient_pda != *recipient_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+  if user_vault.user != *user.key {
+    return Err(ProgramError::IllegalOwner);
+  }
+  if user_vault.vault != *vault_state_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // An arbiter-held position is escrowed; handing it to someone else would sidestep the arbiter
+  if user_vault.has_arbiter() {
+    return Err(VaultError::ArbiterRequired.into());
+  }
+
+  // Both sides settle against the current index, so rewards earned so far stay with whoever earned them
+  let now = Clock::get()?.unix_timestamp;
+  user_vault.accrue_time_rewards(vault.reward_rate_per_second, now);
+  user_vault.settle_rewards(vault.reward_index)?;
+  if user_vault.deposited_amount < amount {
+    return Err(ProgramError::InsufficientFunds);
+  }
+
+  // The recipient doesn't need to have deposited before; their record is created here, paid by the sender
+  if recipient_vault_account.data_is_empty() {
+    create_user_vault_account(
+      program_id,
+      user,
+      recipient_vault_account,
+      system_program,
+      &[pda::USER_VAULT_SEED, to.as_ref(), vault_state_account.key.as_ref(), &[recipient_bump]],
+    )?;
+    vault.user_count = vault.user_count.checked_add(1).ok_or(VaultError::Overflow)?;
+  }
+  let mut recipient_vault = load_or_init_user_vault(recipient_vault_account, &to, vault_state_account.key)?;
+  recipient_vault.accrue_time_rewards(vault.reward_rate_per_second, now);
+  recipient_vault.settle_rewards(vault.reward_index)?;
+
+  user_vault.deposited_amount -= amount;
+  recipient_vault.deposited_amount = recipient_vault.deposited_amount.checked_add(amount).ok_or(VaultError::Overflow)?;
+
+  // The moved balance keeps its lock, otherwise a fresh deposit could be passed to another wallet and withdrawn at once
+  recipient_vault.last_deposit_ts = recipient_vault.last_deposit_ts.max(user_vault.last_deposit_ts);
+  recipient_vault.locked_until = recipient_vault.locked_until.max(user_vault.locked_until);
+
+  // Only ownership of the balance changes, so the vault's total_deposits is left as is; only user_count may have moved
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+  UserVault::pack(user_vault, &mut user_vault_account.try_borrow_mut_data()?)?;
+  UserVault::pack(recipient_vault, &mut recipient_vault_account.try_borrow_mut_data()?)?;
+
+  msg!("{} deposited tokens moved from {} to {}", amount, user.key, to);
+
+  Ok(())
+}
+
+fn set_max_vault_withdraw_per_window(program_id: &Pubkey, accounts: &[AccountInfo], max_vault_withdraw_per_window: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Withdrawals already made in the current window still count against the new allowance
+  vault.max_vault_withdraw_per_window = max_vault_withdraw_per_window;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Vault-wide withdrawal cap set to {} per window", max_vault_withdraw_per_window);
+
+  Ok(())
+}
+
+fn set_fee_tiers(program_id: &Pubkey, accounts: &[AccountInfo], tiers: [state::FeeTier; state::FEE_TIER_COUNT]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Tiers are bounded like the base fee, and a vault created without a treasury has nowhere to send a tier's fee
+  let mut last_threshold = 0;
+  for tier in tiers.iter() {
+    if tier.bps > state::MAX_WITHDRAWAL_FEE_BPS {
+      return Err(VaultError::FeeTooHigh.into());
+    }
+    if tier.bps > 0 && vault.fee_treasury == Pubkey::default() {
+      return Err(ProgramError::InvalidArgument);
+    }
+
+    // Used tiers come first with strictly increasing thresholds, then only unused (all-zero) ones
+    if tier.threshold == 0 {
+      if tier.bps != 0 {
+        return Err(ProgramError::InvalidArgument);
+      }
+      last_threshold = u64::MAX;
+    } else if tier.threshold <= last_threshold {
+      return Err(ProgramError::InvalidArgument);
+    } else {
+      last_threshold = tier.threshold;
+    }
+  }
+
+  vault.fee_tiers = tiers;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Withdrawal fee tiers updated");
+
+  Ok(())
+}
+
+fn add_to_whitelist(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let user = next_account_info(account_info_iter)?;
+  let whitelist_account = next_account_info(account_info_iter)?;
+  let system_program = next_account_info(account_info_iter)?;
+
+  load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  let (expected_pda, bump) = pda::whitelist(program_id, vault_state_account.key, user.key);
+  if expected_pda != *whitelist_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  // Already whitelisted: nothing to do, so retries are harmless
+  if !whitelist_account.data_is_empty() {
+    return Ok(());
+  }
+
+  invoke_signed(
+    &system_instruction::create_account(
+      owner.key,
+      whitelist_account.key,
+      Rent::get()?.minimum_balance(state::Whitelist::LEN),
+      state::Whitelist::LEN as u64,
+      program_id,
+    ),
+    &[owner.clone(), whitelist_account.clone(), system_program.clone()],
+    &[&[pda::WHITELIST_SEED, vault_state_account.key.as_ref(), user.key.as_ref(), &[bump]]],
+  )?;
+  assert_rent_exempt(whitelist_account)?;
+
+  let entry = state::Whitelist {
+    is_initialized: true,
+    vault: *vault_state_account.key,
+    user: *user.key,
+  };
+  state::Whitelist::pack(entry, &mut whitelist_account.try_borrow_mut_data()?)?;
+
+  msg!("{} whitelisted", user.key);
+
+  Ok(())
+}
+
+fn remove_from_whitelist(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let user = next_account_info(account_info_iter)?;
+  let whitelist_account = next_account_info(account_info_iter)?;
+
+  load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  let (expected_pda, _bump) = pda::whitelist(program_id, vault_state_account.key, user.key);
+  if expected_pda != *whitelist_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  // Only entries this program created can be closed through here
+  if whitelist_account.owner != program_id {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  close_account(whitelist_account, owner)?;
+
+  msg!("{} removed from the whitelist", user.key);
+
+  Ok(())
+}
+
+fn set_require_whitelisted(program_id: &Pubkey, accounts: &[AccountInfo], require_whitelisted: bool) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Only gates new deposits and position transfers; balances already in the vault stay withdrawable
+  vault.require_whitelisted = require_whitelisted;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Deposit whitelist {}", if require_whitelisted { "required" } else { "not required" });
+
+  Ok(())
+}
+
+fn add_asset(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let asset_mint = next_account_info(account_info_iter)?;
+  let asset_token_account = next_account_info(account_info_iter)?;
+  let vault_asset_account = next_account_info(account_info_iter)?;
+  let system_program = next_account_info(account_info_iter)?;
+
+  let vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // The vault's own mint is already held through the root accounting
+  if *asset_mint.key == vault.token_mint {
+    return Err(ProgramError::InvalidArgument);
+  }
+
+  let (expected_pda, bump) = pda::vault_asset(program_id, vault_state_account.key, asset_mint.key);
+  if expected_pda != *vault_asset_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  // An asset's token account is fixed once registered, so adding it again is refused rather than repointed
+  if !vault_asset_account.data_is_empty() {
+    return Err(ProgramError::AccountAlreadyInitialized);
+  }
+
+  // Same requirements as the root vault token account: a real mint, the right token program, the vault authority as owner
+  Mint::unpack(&asset_mint.try_borrow_data()?)?;
+  if *asset_token_account.owner != vault.allowed_token_program {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+  let asset_token = check_token_account_mint(asset_token_account, asset_mint.key)?;
+  let (vault_authority, _bump) = vault_authority(program_id, &vault, None)?;
+  if asset_token.owner != vault_authority {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  invoke_signed(
+    &system_instruction::create_account(
+      owner.key,
+      vault_asset_account.key,
+      Rent::get()?.minimum_balance(state::VaultAsset::LEN),
+      state::VaultAsset::LEN as u64,
+      program_id,
+    ),
+    &[owner.clone(), vault_asset_account.clone(), system_program.clone()],
+    &[&[pda::VAULT_ASSET_SEED, vault_state_account.key.as_ref(), asset_mint.key.as_ref(), &[bump]]],
+  )?;
+  assert_rent_exempt(vault_asset_account)?;
+
+  let asset = state::VaultAsset {
+    is_initialized: true,
+    vault: *vault_state_account.key,
+    mint: *asset_mint.key,
+    token_account: *asset_token_account.key,
+    total_deposits: 0,
+  };
+  state::VaultAsset::pack(asset, &mut vault_asset_account.try_borrow_mut_data()?)?;
+
+  msg!("Asset {} added to vault {}", asset_mint.key, vault_state_account.key);
+
+  Ok(())
+}
+
+// Load a vault's asset account, checking it is the genuine PDA for the vault and the mint it records
+fn load_vault_asset(program_id: &Pubkey, vault_state: &Pubkey, vault_asset_account: &AccountInfo) -> Result<state::VaultAsset, ProgramError> {
+  let asset = state::VaultAsset::unpack(&vault_asset_account.try_borrow_data()?)?;
+
+  let (expected_pda, _bump) = pda::vault_asset(program_id, vault_state, &asset.mint);
+  if expected_pda != *vault_asset_account.key || asset.vault != *vault_state {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  Ok(asset)
+}
+
+// Deposit into one of the vault's extra assets. Mirrors deposit_tokens, minus what is denominated in the vault's own mint
+// (caps, fees, rewards); the root vault only supplies its flags and is never written.
+fn deposit_asset(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let depositor = next_account_info(account_info_iter)?;
+  let user_source_token_account = next_account_info(account_info_iter)?;
+  let asset_token_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let vault_asset_account = next_account_info(account_info_iter)?;
+  let position_account = next_account_info(account_info_iter)?;
+  let token_program = next_account_info(account_info_iter)?;
+  let system_program = next_account_info(account_info_iter)?;
+  let asset_mint = next_account_info(account_info_iter)?;
+
+  if !depositor.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  if amount == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;
+
+  if vault.require_whitelisted {
+    check_whitelisted(program_id, vault_state_account.key, depositor.key, next_account_info(account_info_iter)?)?;
+  }
+
+  vault.check_token_program(token_program.key)?;
+  if token_program.key != &spl_token::id() {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  let mut asset = load_vault_asset(program_id, vault_state_account.key, vault_asset_account)?;
+
+  // Tokens must land in the asset's own token account, and the source must hold the asset's mint
+  if *asset_token_account.key != asset.token_account {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  check_token_account_mint(user_source_token_account, &asset.mint)?;
+  if *asset_mint.key != asset.mint {
+    return Err(VaultError::MintMismatch.into());
+  }
+  let mint = Mint::unpack(&asset_mint.try_borrow_data()?)?;
+
+  asset.total_deposits = asset.total_deposits.checked_add(amount).ok_or(VaultError::Overflow)?;
+
+  let (expected_pda, position_bump) = pda::user_vault(program_id, depositor.key, vault_asset_account.key);
+  if expected_pda != *position_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  // Positions in extra assets aren't counted in the root vault's user_count, which stays the number of its own records
+  if position_account.data_is_empty() {
+    create_user_vault_account(
+      program_id,
+      depositor,
+      position_account,
+      system_program,
+      &[pda::USER_VAULT_SEED, depositor.key.as_ref(), vault_asset_account.key.as_ref(), &[position_bump]],
+    )?;
+  }
+
+  let mut position = load_or_init_user_vault(position_account, depositor.key, vault_asset_account.key)?;
+  if position.vault != *vault_asset_account.key || position.user != *depositor.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  position.deposited_amount = position.deposited_amount.checked_add(amount).ok_or(VaultError::Overflow)?;
+  position.last_deposit_ts = Clock::get()?.unix_timestamp;                 // The root vault's lock_duration applies here too
+
+  let transfer_ix = spl_token::instruction::transfer_checked(
+    token_program.key,
+    user_source_token_account.key,
+    asset_mint.key,
+    asset_token_account.key,
+    depositor.key,
+    &[],
+    amount,
+    mint.decimals,
+  )?;
+
+  invoke(
+    &transfer_ix,
+    &[
+      user_source_token_account.clone(),
+      asset_mint.clone(),
+      asset_token_account.clone(),
+      depositor.clone(),
+      token_program.clone(),
+    ],
+  )?;
+
+  state::VaultAsset::pack(asset, &mut vault_asset_account.try_borrow_mut_data()?)?;
+  UserVault::pack(position, &mut position_account.try_borrow_mut_data()?)?;
+
+  msg!("{} tokens of {} deposited by {}", amount, asset.mint, depositor.key);
+
+  Ok(())
+}
+
+// Withdraw from one of the vault's extra assets. The position is the user's own, so the same user-side checks as
+// withdraw_tokens apply: signer, arbiter, lock and balance.
+fn withdraw_asset(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let asset_token_account = next_account_info(account_info_iter)?;
+  let user_destination_token_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let vault_asset_account = next_account_info(account_info_iter)?;
+  let position_account = next_account_info(account_info_iter)?;
+  let token_program = next_account_info(account_info_iter)?;
+  let asset_mint = next_account_info(account_info_iter)?;
+  let vault_authority_account = next_account_info(account_info_iter)?;
+
+  if !user.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  if amount == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;
+  vault.check_token_program(token_program.key)?;
+  if token_program.key != &spl_token::id() {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  let mut asset = load_vault_asset(program_id, vault_state_account.key, vault_asset_account)?;
+
+  // Funds can only leave from the asset's own token account, and only towards a token account of the same mint
+  if *asset_token_account.key != asset.token_account {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  let asset_token = check_token_account_mint(asset_token_account, &asset.mint)?;
+  load_destination_token_account(user_destination_token_account, &asset.mint)?;
+  if user_destination_token_account.key == asset_token_account.key {
+    return Err(ProgramError::InvalidAccountData);                        // A self-transfer would debit the position for nothing
+  }
+  if *asset_mint.key != asset.mint {
+    return Err(VaultError::MintMismatch.into());
+  }
+  let mint = Mint::unpack(&asset_mint.try_borrow_data()?)?;
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_asset_account.key);
+  if expected_pda != *position_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let mut position = UserVault::unpack(&position_account.try_borrow_data()?)?;
+  if position.user != *user.key {
+    return Err(ProgramError::IllegalOwner);
+  }
+  if position.vault != *vault_asset_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  if position.has_arbiter() {
+    return Err(VaultError::ArbiterRequired.into());
+  }
+  if vault.is_withdraw_locked(&position, Clock::get()?.unix_timestamp) {
+    return Err(VaultError::Locked.into());
+  }
+
+  if position.deposited_amount < amount {
+    return Err(ProgramError::InsufficientFunds);
+  }
+  if asset_token.amount < amount {
+    return Err(VaultError::InsufficientVaultLiquidity.into());
+  }
+
+  position.deposited_amount -= amount;
+  asset.total_deposits = asset.total_deposits.checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+
+  let (vault_authority, bump_seed) = vault_authority(program_id, &vault, None)?;
+  if *vault_authority_account.key != vault_authority {
+    return Err(VaultError::InvalidPda.into());
+  }
+  let seeds: &[&[u8]] = &[pda::VAULT_AUTHORITY_SEED, &[bump_seed]];
+
+  let transfer_ix = spl_token::instruction::transfer_checked(
+    token_program.key,
+    asset_token_account.key,
+    asset_mint.key,
+    user_destination_token_account.key,
+    &vault_authority,
+    &[],
+    amount,
+    mint.decimals,
+  )?;
+
+  invoke_signed(
+    &transfer_ix,
+    &[
+      asset_token_account.clone(),
+      asset_mint.clone(),
+      user_destination_token_account.clone(),
+      vault_authority_account.clone(),
+      token_program.clone(),
+    ],
+    &[seeds],
+  )?;
+
+  state::VaultAsset::pack(asset, &mut vault_asset_account.try_borrow_mut_data()?)?;
+  UserVault::pack(position, &mut position_account.try_borrow_mut_data()?)?;
+
+  msg!("{} tokens of {} withdrawn by {}", amount, asset.mint, user.key);
+
+  Ok(())
+}
+
+fn set_reward_rate(program_id: &Pubkey, accounts: &[AccountInfo], reward_rate_per_second: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let reward_token_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Claims are signed for by this vault's reward authority, so that is the only owner the account may have
+  if *reward_token_account.owner != vault.allowed_token_program {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+  let reward_token = TokenAccount::unpack(&reward_token_account.try_borrow_data()?)?;
+  let (reward_authority, _bump) = pda::reward_authority(program_id, vault_state_account.key);
+  if reward_token.owner != reward_authority {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  vault.reward_rate_per_second = reward_rate_per_second;
+  vault.reward_token_account = *reward_token_account.key;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Reward rate set to {} per second from {}", reward_rate_per_second, reward_token_account.key);
+
+  Ok(())
+}
+
+fn claim_rewards(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let reward_token_account = next_account_info(account_info_iter)?;
+  let user_destination_token_account = next_account_info(account_info_iter)?;
+  let reward_authority_account = next_account_info(account_info_iter)?;
+  let token_program = next_account_info(account_info_iter)?;
+  let reward_mint = next_account_info(account_info_iter)?;
+
+  if !user.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+  vault.check_not_paused()?;
+  vault.check_token_program(token_program.key)?;
+  if token_program.key != &spl_token::id() {
+    return Err(ProgramError::IncorrectProgramId);
+  }
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+  if user_vault.user != *user.key {
+    return Err(ProgramError::IllegalOwner);
+  }
+  if user_vault.vault != *vault_state_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // Rewards only leave from the account the owner set aside, signed for by this vault's own reward authority
+  if vault.reward_token_account == Pubkey::default() || *reward_token_account.key != vault.reward_token_account {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  let (reward_authority, reward_bump) = pda::reward_authority(program_id, vault_state_account.key);
+  if reward_authority != *reward_authority_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let reward_token = TokenAccount::unpack(&reward_token_account.try_borrow_data()?)?;
+  if *reward_mint.key != reward_token.mint {
+    return Err(VaultError::MintMismatch.into());
+  }
+  let mint = Mint::unpack(&reward_mint.try_borrow_data()?)?;
+  load_destination_token_account(user_destination_token_account, &reward_token.mint)?;
+
+  user_vault.accrue_time_rewards(vault.reward_rate_per_second, Clock::get()?.unix_timestamp);
+  if user_vault.reward_debt == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  // An underfunded reward account pays what it has; the rest stays owed until the owner tops it up
+  let payout = user_vault.reward_debt.min(reward_token.amount);
+  if payout == 0 {
+    return Err(VaultError::InsufficientVaultLiquidity.into());
+  }
+  user_vault.reward_debt -= payout;
+
+  let transfer_ix = spl_token::instruction::transfer_checked(
+    token_program.key,
+    reward_token_account.key,
+    reward_mint.key,
+    user_destination_token_account.key,
+    &reward_authority,
+    &[],
+    payout,
+    mint.decimals,
+  )?;
+
+  invoke_signed(
+    &transfer_ix,
+    &[
+      reward_token_account.clone(),
+      reward_mint.clone(),
+      user_destination_token_account.clone(),
+      reward_authority_account.clone(),
+      token_program.clone(),
+    ],
+    &[&[pda::REWARD_AUTHORITY_SEED, vault_state_account.key.as_ref(), &[reward_bump]]],
+  )?;
+
+  UserVault::pack(user_vault, &mut user_vault_account.try_borrow_mut_data()?)?;
+
+  msg!("{} reward tokens claimed by {}", payout, user.key);
+
+  Ok(())
+}
+
+fn set_admins(program_id: &Pubkey, accounts: &[AccountInfo], admins: [Pubkey; state::ADMIN_COUNT], admin_threshold: u8) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // Each admin counts once, so a repeated key would make the threshold look more distributed than it is,
+  // and a threshold above the filled slots could never be met again, locking every admin instruction for good
+  let filled: Vec<&Pubkey> = admins.iter().filter(|admin| **admin != Pubkey::default()).collect();
+  if filled.iter().enumerate().any(|(i, admin)| filled[..i].contains(admin)) {
+    return Err(ProgramError::InvalidArgument);
+  }
+  if admin_threshold as usize > filled.len() {
+    return Err(ProgramError::InvalidArgument);
+  }
+
+  vault.admins = admins;
+  vault.admin_threshold = admin_threshold;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Admin threshold set to {} of {}", admin_threshold, filled.len());
+
+  Ok(())
+}
+
+fn set_instruction_fee(
+  program_id: &Pubkey,
+  accounts: &[AccountInfo],
+  instruction_fee_lamports: u64,
+  instruction_fee_treasury: Pubkey,
+) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  // A fee this build would never charge is refused rather than stored, and a fee needs somewhere to go
+  if instruction_fee_lamports > 0 && (cfg!(not(feature = "instruction-fees")) || instruction_fee_treasury == Pubkey::default()) {
+    return Err(ProgramError::InvalidArgument);
+  }
+
+  vault.instruction_fee_lamports = instruction_fee_lamports;
+  vault.instruction_fee_treasury = instruction_fee_treasury;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Instruction fee set to {} lamports, paid to {}", instruction_fee_lamports, instruction_fee_treasury);
+
+  Ok(())
+}
+
+// Close an emptied position, returning its rent to the user. The record only goes once nothing is owed on it either way,
+// so rewards are brought up to date before the balance check.
+fn close_user_vault(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+
+  let mut vault = Vault::load(vault_state_account, program_id)?;
+
+  // EnsureUserVault lets anyone open a record for anyone, and CloseVault waits for user_count to reach zero, so the
+  // owner has to be able to clear empty records without the user; the rent still goes back to the user
+  let closed_by_owner = !user.is_signer;
+  if closed_by_owner {
+    let owner = next_account_info(account_info_iter)?;
+    if !owner.is_signer {
+      return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *owner.key != vault.owner {
+      return Err(VaultError::Unauthorized.into());
+    }
+  }
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+  if user_vault.user != *user.key {
+    return Err(ProgramError::IllegalOwner);
+  }
+  if user_vault.vault != *vault_state_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  user_vault.accrue_time_rewards(vault.reward_rate_per_second, Clock::get()?.unix_timestamp);
+  user_vault.settle_rewards(vault.reward_index)?;
+  if user_vault.deposited_amount != 0 || user_vault.reward_debt != 0 {
+    return Err(VaultError::PositionNotEmpty.into());
+  }
+
+  // An arbiter is the user's own choice of escrow for future deposits, so only the user can drop it with the record
+  if closed_by_owner && user_vault.has_arbiter() {
+    return Err(VaultError::ArbiterRequired.into());
+  }
+
+  // Counted when the record was created, so it's uncounted as it goes
+  vault.user_count = vault.user_count.checked_sub(1).ok_or(VaultError::Overflow)?;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  let lamports = close_account(user_vault_account, user)?;
+
+  msg!("User vault of {} closed, {} lamports returned", user.key, lamports);
+
+  Ok(())
+}
+
+// One-way: process_instruction refuses every config-changing instruction once the flag is set, FreezeConfig included
+fn freeze_config(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  vault.config_frozen = true;
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("Vault {} config frozen", vault_state_account.key);
+
+  Ok(())
+}
+
+fn ensure_user_vault(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let payer = next_account_info(account_info_iter)?;
+  let user = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let system_program = next_account_info(account_info_iter)?;
+
+  if !payer.is_signer {
+    return Err(ProgramError::MissingRequiredSignature);
+  }
+
+  // Only create positions under real vaults
+  let mut vault = Vault::load(vault_state_account, program_id)?;
+
+  let (expected_pda, bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  if user_vault_account.data_is_empty() {
+    create_user_vault_account(
+      program_id,
+      payer,
+      user_vault_account,
+      system_program,
+      &[pda::USER_VAULT_SEED, user.key.as_ref(), vault_state_account.key.as_ref(), &[bump]],
+    )?;
+    vault.user_count = vault.user_count.checked_add(1).ok_or(VaultError::Overflow)?;
+    Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+  } else if UserVault::unpack_unchecked(&user_vault_account.try_borrow_data()?)?.is_initialized {
+    // Already set up: nothing to do, so retries are harmless
+    return Ok(());
+  }
+
+  let user_vault = load_or_init_user_vault(user_vault_account, user.key, vault_state_account.key)?;
+  UserVault::pack(user_vault, &mut user_vault_account.try_borrow_mut_data()?)?;
+
+  msg!("User vault created for {}", user.key);
+
+  Ok(())
+}
+
+fn distribute_rewards(program_id: &Pubkey, accounts: &[AccountInfo], total_reward: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let owner = next_account_info(account_info_iter)?;
+  let owner_source_token_account = next_account_info(account_info_iter)?;
+  let vault_token_account = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let token_program = next_account_info(account_info_iter)?;
+  let token_mint = next_account_info(account_info_iter)?;
+
+  if total_reward == 0 {
+    return Err(VaultError::AmountZero.into());
+  }
+
+  let mut vault = load_owned_vault(program_id, owner, vault_state_account, accounts)?;
+
+  if vault.vault_token_account != *vault_token_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  vault.check_token_program(token_program.key)?;
+
+  // One distribution per epoch keeps reward rounds aligned with epochs
+  let epoch = Clock::get()?.epoch;
+  if vault.last_reward_epoch == epoch {
+    return Err(VaultError::RewardsAlreadyDistributed.into());
+  }
+
+  vault.accrue_rewards(total_reward)?;
+  vault.last_reward_epoch = epoch;
+
+  // The owner funds the round out of their own token account
+  let mint = load_vault_mint(token_mint, &vault)?;
+  let transfer_ix = spl_token::instruction::transfer_checked(
+    token_program.key,
+    owner_source_token_account.key,
+    token_mint.key,
+    vault_token_account.key,
+    owner.key,
+    &[],
+    total_reward,
+    mint.decimals,
+  )?;
+
+  invoke(
+    &transfer_ix,
+    &[
+      owner_source_token_account.clone(),
+      token_mint.clone(),
+      vault_token_account.clone(),
+      owner.clone(),
+      token_program.clone(),
+    ],
+  )?;
+
+  Vault::pack(vault, &mut vault_state_account.try_borrow_mut_data()?)?;
+
+  msg!("{} reward tokens distributed in epoch {}", total_reward, epoch);
+
+  Ok(())
+}
+
+// The most `user_vault` could take out through Withdraw right now, given every constraint the withdraw path enforces.
+// Each limit narrows the result, so whichever is smallest is the binding one.
+fn withdrawable_now(vault: &Vault, user_vault: &UserVault, vault_token_balance: u64, now: i64) -> Result<u64, ProgramError> {
+  // Withdraw itself would be refused on a paused vault or one that has switched it off
+  if vault.paused || !vault.is_instruction_enabled(VaultInstruction::Withdraw { amount: 0, authority_bump: None }.tag()) {
+    return Ok(0);
+  }
+
+  // Arbiter-held positions can't use the plain withdraw path at all, and locked ones not yet
+  if user_vault.has_arbiter() || vault.is_withdraw_locked(user_vault, now) {
+    return Ok(0);
+  }
+
+  let available = user_vault
+    .deposited_amount
+    .min(vault.total_deposits)                              // Can't exceed what the vault has recorded overall
+    .min(vault_token_balance)                               // Nor what the vault token account actually holds
+    .min(vault.window_withdraw_remaining(now));             // Nor what's left of the vault-wide window allowance
+
+  // A partial amount under the withdrawal floor would be rejected, only a full exit may go below it
+  if vault.min_withdraw > 0 && available < vault.min_withdraw && available != user_vault.deposited_amount {
+    return Ok(0);
+  }
+
+  // Reported net of the fee, i.e. what would actually reach the user's token account
+  let fee = vault.withdrawal_fee_for(&user_vault.user, available, user_vault.deposited_amount)?;
+  Ok(available - fee)
+}
+
+// Load what GetWithdrawable and GetLockStatus read: the vault, the user's reward-settled position and the vault token balance
+fn load_withdrawable_position(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(Vault, UserVault, u64), ProgramError> {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let vault_token_account = next_account_info(account_info_iter)?;
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  if vault.vault_token_account != *vault_token_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  let mut user_vault = UserVault::unpack(&user_vault_account.try_borrow_data()?)?;
+  user_vault.settle_rewards(vault.reward_index)?;                         // Nothing is written back, this only reflects unsettled rewards
+  let vault_token = TokenAccount::unpack(&vault_token_account.try_borrow_data()?)?;
+
+  Ok((vault, user_vault, vault_token.amount))
+}
+
+fn get_withdrawable(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let (vault, user_vault, vault_token_balance) = load_withdrawable_position(program_id, accounts)?;
+
+  let withdrawable = withdrawable_now(&vault, &user_vault, vault_token_balance, Clock::get()?.unix_timestamp)?;
+  emit_return_data(&withdrawable.to_le_bytes());
+
+  Ok(())
+}
+
+// Report the withdrawable and locked parts of a position along with its unlock time, so a client can show both
+fn get_lock_status(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let (vault, user_vault, vault_token_balance) = load_withdrawable_position(program_id, accounts)?;
+  let now = Clock::get()?.unix_timestamp;
+
+  // A lock always covers the whole position; vesting isn't partial
+  let (locked, unlocks_at) = if vault.is_withdraw_locked(&user_vault, now) {
+    (user_vault.deposited_amount, vault.unlocks_at(&user_vault))
+  } else {
+    (0, 0)
+  };
+
+  let mut status = [0u8; 24];
+  status[..8].copy_from_slice(&withdrawable_now(&vault, &user_vault, vault_token_balance, now)?.to_le_bytes());
+  status[8..16].copy_from_slice(&locked.to_le_bytes());
+  status[16..].copy_from_slice(&unlocks_at.to_le_bytes());
+  emit_return_data(&status);
+
+  Ok(())
+}
+
+
+fn get_limits(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+
+  let mut limits = [0u8; VaultLimits::LEN];
+  VaultLimits::pack(VaultLimits::from_vault(&vault), &mut limits)?;
+  emit_return_data(&limits);
+
+  Ok(())
+}
+
+// Indexers read the user counter kept on the vault instead of scanning for every user vault PDA
+fn get_user_count(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+  emit_return_data(&vault.user_count.to_le_bytes());
+
+  Ok(())
+}
+
+// The full vault has to fit in the runtime's return data
+const _: () = assert!(<Vault as Pack>::LEN <= solana_program::program::MAX_RETURN_DATA);
+
+// Re-packed rather than copied from the account, so legacy-layout vaults come back in the current layout too
+fn get_config(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let vault_state_account = next_account_info(account_info_iter)?;
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+
+  let mut config = [0u8; Vault::LEN];
+  Vault::pack(vault, &mut config)?;
+  emit_return_data(&config);
+
+  Ok(())
+}
+
+// Evaluate every check Deposit or Withdraw would apply to `amount` and report all failures at once, so a client can
+// explain why an operation would be refused before asking the user to sign. Nothing is written.
+fn preflight_check(program_id: &Pubkey, accounts: &[AccountInfo], op: u8, amount: u64) -> ProgramResult {
+  let account_info_iter = &mut accounts.iter();
+
+  let user = next_account_info(account_info_iter)?;
+  let vault_state_account = next_account_info(account_info_iter)?;
+  let user_vault_account = next_account_info(account_info_iter)?;
+  let vault_token_account = next_account_info(account_info_iter)?;
+
+  let vault = Vault::load(vault_state_account, program_id)?;
+
+  let (expected_pda, _bump) = pda::user_vault(program_id, user.key, vault_state_account.key);
+  if expected_pda != *user_vault_account.key {
+    return Err(VaultError::InvalidPda.into());
+  }
+
+  if vault.vault_token_account != *vault_token_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // Someone who never deposited has no record yet, which behaves exactly like an empty position
+  let mut user_vault = load_or_init_user_vault(user_vault_account, user.key, vault_state_account.key)?;
+  user_vault.settle_rewards(vault.reward_index)?;                         // Only in memory, as in GetWithdrawable
+
+  let mut failed = 0u32;
+  if vault.paused {
+    failed |= state::PREFLIGHT_PAUSED;
+  }
+  if amount == 0 {
+    failed |= state::PREFLIGHT_AMOUNT_ZERO;
+  }
+
+  match op {
+    state::PREFLIGHT_OP_DEPOSIT => {
+      if !vault.is_instruction_enabled(VaultInstruction::Deposit { amount, memo: None }.tag()) {
+        failed |= state::PREFLIGHT_INSTRUCTION_DISABLED;
+      }
+      if vault.check_single_deposit(amount).is_err() {
+        failed |= state::PREFLIGHT_SINGLE_DEPOSIT_LIMIT;
+      }
+      if vault.check_deposit_cap(amount).is_err() {
+        failed |= state::PREFLIGHT_DEPOSIT_CAP;
+      }
+      // Same check as Deposit; a wrong entry address is still an error, only a missing or foreign entry is reported
+      if vault.require_whitelisted {
+        let whitelisted = match account_info_iter.next() {
+          Some(entry) => check_whitelisted(program_id, vault_state_account.key, user.key, entry),
+          None => Err(VaultError::Unauthorized.into()),
+        };
+        match whitelisted {
+          Ok(()) => {}
+          Err(err) if err == ProgramError::from(VaultError::Unauthorized) => failed |= state::PREFLIGHT_NOT_WHITELISTED,
+          Err(err) => return Err(err),
+        }
+      }
+    }
+    state::PREFLIGHT_OP_WITHDRAW => {
+      if !vault.is_instruction_enabled(VaultInstruction::Withdraw { amount, authority_bump: None }.tag()) {
+        failed |= state::PREFLIGHT_INSTRUCTION_DISABLED;
+      }
+      if user_vault.has_arbiter() {
+        failed |= state::PREFLIGHT_ARBITER_REQUIRED;
+      }
+      if amount > user_vault.deposited_amount || amount > vault.total_deposits {
+        failed |= state::PREFLIGHT_INSUFFICIENT_BALANCE;
+      }
+      if vault.min_withdraw > 0 && amount < vault.min_withdraw && amount != user_vault.deposited_amount {
+        failed |= state::PREFLIGHT_BELOW_MIN_WITHDRAW;
+      }
+      let now = Clock::get()?.unix_timestamp;
+      if amount > vault.window_withdraw_remaining(now) {
+        failed |= state::PREFLIGHT_VAULT_RATE_LIMITED;
+      }
+      if vault.is_withdraw_locked(&user_vault, now) {
+        failed |= state::PREFLIGHT_LOCKED;
+      }
+      if amount > TokenAccount::unpack(&vault_token_account.try_borrow_data()?)?.amount {
+        failed |= state::PREFLIGHT_INSUFFICIENT_LIQUIDITY;
+      }
+    }
+    _ => return Err(ProgramError::InvalidInstructionData),
+  }
+
+  emit_return_data(&failed.to_le_bytes());
+
+  Ok(())
+}
+
+// Hand the result of a read-style instruction back to the caller through the transaction's return data
+#[cfg(feature = "return-data")]
+fn emit_return_data(data: &[u8]) {
+  set_return_data(data);
+}
+
+// Fallback for runtimes without set_return_data: log the bytes hex-encoded so clients can parse them from the logs
+#[cfg(not(feature = "return-data"))]
+fn emit_return_data(data: &[u8]) {
+  let hex: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+  msg!("return-data: {}", hex);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+
+  // Off-chain there is no runtime to serve Rent::get(), so hand out the default rent
+  struct DefaultRent;
+
+  impl SyscallStubs for DefaultRent {
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+      unsafe { *(var_addr as *mut Rent) = Rent::default() };
+      solana_program::entrypoint::SUCCESS
+    }
+  }
+
+  #[test]
+  fn underfunded_creation_is_caught() {
+    set_syscall_stubs(Box::new(DefaultRent));
+    let (key, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut data = vec![0u8; UserVault::LEN];
+    let exempt = Rent::default().minimum_balance(UserVault::LEN);
+
+    let mut lamports = exempt - 1;
+    let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+    assert_eq!(assert_rent_exempt(&account), Err(ProgramError::AccountNotRentExempt));
+
+    let mut lamports = exempt;
+    let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+    assert_eq!(assert_rent_exempt(&account), Ok(()));
+  }
+}
@@ -1,18 +1,205 @@
 // Import core traits and types from Solana's program SDK
 use solana_program::{
+  account_info::AccountInfo,                                              // Account handle passed to Vault::load
+  program_error::ProgramError,                                            // Standard error type returned by validation helpers
   program_pack::{IsInitialized, Pack, Sealed},                            // Traits for (de)serializing account data
   pubkey::Pubkey,                                                         // Solana's public key type for identifying accounts and programs
 };
 
+use crate::error::VaultError;                                             // Program-specific error codes
+
 // Import helper macros to safely work with byte arrays often used in manual serialization/deserialization
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 
+// Optional Borsh path, see unpack_borsh/pack_borsh below
+#[cfg(feature = "borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
+
+// Fixed-point scale of Vault::reward_index, so small rewards over large deposits don't round to zero
+pub const REWARD_INDEX_PRECISION: u128 = 1_000_000_000_000;
+
+// A reward index as a growth factor: 0 is what every vault and user vault starts with, before any round, and means 1.0
+fn reward_growth(reward_index: u128) -> u128 {
+  if reward_index == 0 { REWARD_INDEX_PRECISION } else { reward_index }
+}
+
+// Fixed-point scale of Vault::reward_rate_per_second: a rate of REWARD_RATE_PRECISION pays one token per deposited token per second
+pub const REWARD_RATE_PRECISION: u128 = 1_000_000_000_000;
+
+// Basis points in 100%, the denominator of Vault::withdrawal_fee_bps
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// Highest withdrawal fee a vault can be created with (10%)
+pub const MAX_WITHDRAWAL_FEE_BPS: u16 = 1_000;
+
+// Length of the vault-wide withdrawal window Vault::max_vault_withdraw_per_window applies to
+pub const VAULT_WITHDRAW_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+// PreflightCheck operations
+pub const PREFLIGHT_OP_DEPOSIT: u8 = 0;
+pub const PREFLIGHT_OP_WITHDRAW: u8 = 1;
+
+// PreflightCheck result bits, one per check that would reject the operation
+pub const PREFLIGHT_PAUSED: u32 = 1 << 0;                  // Vault is paused
+pub const PREFLIGHT_AMOUNT_ZERO: u32 = 1 << 1;             // Amount is zero
+pub const PREFLIGHT_INSTRUCTION_DISABLED: u32 = 1 << 2;    // The owner disabled Deposit/Withdraw on this vault
+pub const PREFLIGHT_SINGLE_DEPOSIT_LIMIT: u32 = 1 << 3;    // Deposit over max_single_deposit
+pub const PREFLIGHT_DEPOSIT_CAP: u32 = 1 << 4;             // Deposit would take total_deposits past deposit_cap
+pub const PREFLIGHT_ARBITER_REQUIRED: u32 = 1 << 5;        // Position can only be released by its arbiter
+pub const PREFLIGHT_INSUFFICIENT_BALANCE: u32 = 1 << 6;    // Withdrawal over the user's (reward-settled) balance
+pub const PREFLIGHT_BELOW_MIN_WITHDRAW: u32 = 1 << 7;      // Partial withdrawal under min_withdraw
+pub const PREFLIGHT_VAULT_RATE_LIMITED: u32 = 1 << 8;      // Withdrawal over the vault-wide window allowance
+pub const PREFLIGHT_LOCKED: u32 = 1 << 9;                  // Position hasn't vested, or is inside the lock after its latest deposit
+pub const PREFLIGHT_NOT_WHITELISTED: u32 = 1 << 10;        // Deposit into a permissioned vault by a user without a whitelist entry
+pub const PREFLIGHT_INSUFFICIENT_LIQUIDITY: u32 = 1 << 11; // Withdrawal over what the vault token account holds
+
+// Size of the original Vault layout: is_initialized, owner, token_mint, vault_token_account
+pub const VAULT_V1_LEN: usize = 1 + 32 + 32 + 32;
+
+// Version byte every Vault is packed with. The original layout began with the is_initialized flag, so the
+// current one starts from 2 to keep the leading byte unambiguous: 0 or 1 is a legacy account, 2 the current layout.
+pub const VAULT_VERSION: u8 = 2;
+
+// Version reported for a Vault read from the original layout
+pub const VAULT_V1_VERSION: u8 = 1;
+
+// Instruction bits no vault can set yet. CompressedDeposit (tag 4) records balances in the tree that nothing can
+// withdraw again, so it stays off until a compressed withdraw path exists.
+pub const UNAVAILABLE_INSTRUCTIONS: u64 = 1 << 4;
+
+// Number of withdrawal fee tiers a vault can configure
+pub const FEE_TIER_COUNT: usize = 4;
+
+// Packed size of all fee tiers: 8 for threshold + 2 for bps, per tier
+pub const FEE_TIERS_LEN: usize = FEE_TIER_COUNT * 10;
+
+// Number of admin slots a vault can fill for threshold-signed admin instructions
+pub const ADMIN_COUNT: usize = 3;
+
+// Packed size of the admin set, one pubkey per slot
+pub const ADMINS_LEN: usize = ADMIN_COUNT * 32;
+
+// A withdrawal fee that applies once a position holds at least `threshold` tokens
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FeeTier {
+  pub threshold: u64,                        // Smallest position the tier applies to, 0 for an unused tier
+  pub bps: u16,                              // Fee in basis points for positions in this tier, at most MAX_WITHDRAWAL_FEE_BPS
+}
+
+impl FeeTier {
+  // Read FEE_TIER_COUNT tiers packed back to back as threshold then bps, little-endian
+  pub fn unpack_tiers(src: &[u8; FEE_TIERS_LEN]) -> [FeeTier; FEE_TIER_COUNT] {
+    let mut tiers = [FeeTier::default(); FEE_TIER_COUNT];
+    for (tier, chunk) in tiers.iter_mut().zip(src.chunks_exact(10)) {
+      let (threshold, bps) = array_refs![array_ref![chunk, 0, 10], 8, 2];
+      tier.threshold = u64::from_le_bytes(*threshold);
+      tier.bps = u16::from_le_bytes(*bps);
+    }
+    tiers
+  }
+
+  // Write tiers in the layout unpack_tiers reads
+  pub fn pack_tiers(tiers: &[FeeTier; FEE_TIER_COUNT], dst: &mut [u8; FEE_TIERS_LEN]) {
+    for (tier, chunk) in tiers.iter().zip(dst.chunks_exact_mut(10)) {
+      let (threshold_dst, bps_dst) = mut_array_refs![array_mut_ref![chunk, 0, 10], 8, 2];
+      *threshold_dst = tier.threshold.to_le_bytes();
+      *bps_dst = tier.bps.to_le_bytes();
+    }
+  }
+}
+
+// Byte offset of every Vault field in the packed layout, each one derived from the field before it. Clients use them for
+// getProgramAccounts memcmp filters (e.g. every vault of an owner), and the assertions below tie the table to Vault::LEN,
+// whose field sizes array_refs! in turn checks at compile time.
+pub const VAULT_VERSION_OFFSET: usize = 0;
+pub const VAULT_IS_INITIALIZED_OFFSET: usize = VAULT_VERSION_OFFSET + 1;
+pub const VAULT_OWNER_OFFSET: usize = VAULT_IS_INITIALIZED_OFFSET + 1;
+pub const VAULT_TOKEN_MINT_OFFSET: usize = VAULT_OWNER_OFFSET + 32;
+pub const VAULT_VAULT_TOKEN_ACCOUNT_OFFSET: usize = VAULT_TOKEN_MINT_OFFSET + 32;
+pub const VAULT_MERKLE_TREE_OFFSET: usize = VAULT_VAULT_TOKEN_ACCOUNT_OFFSET + 32;
+pub const VAULT_ALLOWED_TOKEN_PROGRAM_OFFSET: usize = VAULT_MERKLE_TREE_OFFSET + 32;
+pub const VAULT_TOTAL_DEPOSITS_OFFSET: usize = VAULT_ALLOWED_TOKEN_PROGRAM_OFFSET + 32;
+pub const VAULT_ENABLED_INSTRUCTIONS_OFFSET: usize = VAULT_TOTAL_DEPOSITS_OFFSET + 8;
+pub const VAULT_REWARD_INDEX_OFFSET: usize = VAULT_ENABLED_INSTRUCTIONS_OFFSET + 8;
+pub const VAULT_LAST_REWARD_EPOCH_OFFSET: usize = VAULT_REWARD_INDEX_OFFSET + 16;
+pub const VAULT_MIN_WITHDRAW_OFFSET: usize = VAULT_LAST_REWARD_EPOCH_OFFSET + 8;
+pub const VAULT_PAUSED_OFFSET: usize = VAULT_MIN_WITHDRAW_OFFSET + 8;
+pub const VAULT_MAX_SINGLE_DEPOSIT_OFFSET: usize = VAULT_PAUSED_OFFSET + 1;
+pub const VAULT_COOLDOWN_EXEMPT_OFFSET: usize = VAULT_MAX_SINGLE_DEPOSIT_OFFSET + 8;
+pub const VAULT_VAULT_BUMP_OFFSET: usize = VAULT_COOLDOWN_EXEMPT_OFFSET + 32;
+pub const VAULT_NAME_OFFSET: usize = VAULT_VAULT_BUMP_OFFSET + 1;
+pub const VAULT_DEPOSIT_CAP_OFFSET: usize = VAULT_NAME_OFFSET + 32;
+pub const VAULT_WITHDRAWAL_FEE_BPS_OFFSET: usize = VAULT_DEPOSIT_CAP_OFFSET + 8;
+pub const VAULT_FEE_TREASURY_OFFSET: usize = VAULT_WITHDRAWAL_FEE_BPS_OFFSET + 2;
+pub const VAULT_VAULT_WINDOW_WITHDRAWN_OFFSET: usize = VAULT_FEE_TREASURY_OFFSET + 32;
+pub const VAULT_VAULT_WINDOW_START_OFFSET: usize = VAULT_VAULT_WINDOW_WITHDRAWN_OFFSET + 8;
+pub const VAULT_MAX_VAULT_WITHDRAW_PER_WINDOW_OFFSET: usize = VAULT_VAULT_WINDOW_START_OFFSET + 8;
+pub const VAULT_USER_COUNT_OFFSET: usize = VAULT_MAX_VAULT_WITHDRAW_PER_WINDOW_OFFSET + 8;
+pub const VAULT_LOCK_DURATION_OFFSET: usize = VAULT_USER_COUNT_OFFSET + 8;
+pub const VAULT_FEE_TIERS_OFFSET: usize = VAULT_LOCK_DURATION_OFFSET + 8;
+pub const VAULT_REQUIRE_WHITELISTED_OFFSET: usize = VAULT_FEE_TIERS_OFFSET + FEE_TIERS_LEN;
+pub const VAULT_REWARD_RATE_PER_SECOND_OFFSET: usize = VAULT_REQUIRE_WHITELISTED_OFFSET + 1;
+pub const VAULT_REWARD_TOKEN_ACCOUNT_OFFSET: usize = VAULT_REWARD_RATE_PER_SECOND_OFFSET + 8;
+pub const VAULT_ADMINS_OFFSET: usize = VAULT_REWARD_TOKEN_ACCOUNT_OFFSET + 32;
+pub const VAULT_ADMIN_THRESHOLD_OFFSET: usize = VAULT_ADMINS_OFFSET + ADMINS_LEN;
+pub const VAULT_INSTRUCTION_FEE_LAMPORTS_OFFSET: usize = VAULT_ADMIN_THRESHOLD_OFFSET + 1;
+pub const VAULT_INSTRUCTION_FEE_TREASURY_OFFSET: usize = VAULT_INSTRUCTION_FEE_LAMPORTS_OFFSET + 8;
+pub const VAULT_CONFIG_FROZEN_OFFSET: usize = VAULT_INSTRUCTION_FEE_TREASURY_OFFSET + 32;
+const _: () = assert!(VAULT_CONFIG_FROZEN_OFFSET + 1 == <Vault as Pack>::LEN);
+
+// Byte offset of every UserVault field in the packed layout, as above
+pub const USER_VAULT_IS_INITIALIZED_OFFSET: usize = 0;
+pub const USER_VAULT_USER_OFFSET: usize = USER_VAULT_IS_INITIALIZED_OFFSET + 1;
+pub const USER_VAULT_VAULT_OFFSET: usize = USER_VAULT_USER_OFFSET + 32;
+pub const USER_VAULT_DEPOSITED_AMOUNT_OFFSET: usize = USER_VAULT_VAULT_OFFSET + 32;
+pub const USER_VAULT_ARBITER_OFFSET: usize = USER_VAULT_DEPOSITED_AMOUNT_OFFSET + 8;
+pub const USER_VAULT_PENDING_WITHDRAWAL_OFFSET: usize = USER_VAULT_ARBITER_OFFSET + 32;
+pub const USER_VAULT_REWARD_INDEX_SNAPSHOT_OFFSET: usize = USER_VAULT_PENDING_WITHDRAWAL_OFFSET + 8;
+pub const USER_VAULT_LAST_DEPOSIT_TS_OFFSET: usize = USER_VAULT_REWARD_INDEX_SNAPSHOT_OFFSET + 16;
+pub const USER_VAULT_LOCKED_UNTIL_OFFSET: usize = USER_VAULT_LAST_DEPOSIT_TS_OFFSET + 8;
+pub const USER_VAULT_REWARD_DEBT_OFFSET: usize = USER_VAULT_LOCKED_UNTIL_OFFSET + 8;
+pub const USER_VAULT_LAST_UPDATE_TS_OFFSET: usize = USER_VAULT_REWARD_DEBT_OFFSET + 8;
+const _: () = assert!(USER_VAULT_LAST_UPDATE_TS_OFFSET + 8 == <UserVault as Pack>::LEN);
+
 // Define the Vault struct, this will be the on-chain account structure
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]                 // Default is the all-zero, uninitialized vault
 pub struct Vault { 
+  pub version: u8,                           // Layout version, VAULT_VERSION once packed; VAULT_V1_VERSION when read from a legacy account
   pub is_initialized: bool,                  // Flag to indicate if the vault account has been initialized
   pub owner: Pubkey,                         // The public key of the vault's owner (authority)
   pub token_mint: Pubkey,                    // The token mint this vault is associated with
   pub vault_token_account: Pubkey,           // The associated token account that will actually hold the tokens
+  pub merkle_tree: Pubkey,                   // Concurrent Merkle tree holding compressed user balances, Pubkey::default() when compression is off
+  pub allowed_token_program: Pubkey,         // The only token program this vault will CPI into, fixed at init
+  pub total_deposits: u64,                   // Running total of tokens deposited across all users
+  pub enabled_instructions: u64,             // Bitmask with one bit per instruction tag, a cleared bit disables that instruction
+  pub reward_index: u128,                    // What one token deposited at creation has grown to, scaled by REWARD_INDEX_PRECISION; 0 before any round
+  pub last_reward_epoch: u64,                // Epoch of the most recent DistributeRewards, at most one distribution per epoch
+  pub min_withdraw: u64,                     // Smallest withdrawal allowed, 0 for no minimum; a full exit below it is still allowed
+  pub paused: bool,                          // When set, deposits and withdrawals are frozen by the owner
+  pub max_single_deposit: u64,               // Largest amount a single deposit may carry, 0 for unlimited
+  pub cooldown_exempt: Pubkey,               // Account allowed to skip the withdrawal cooldown (e.g. a rebalancer), Pubkey::default() for none
+  pub vault_bump: u8,                        // Bump of the ["vault"] authority PDA, stored at init so withdrawals skip the bump search, 0 if unknown
+  pub name: [u8; 32],                        // Human-readable label for UIs, UTF-8 padded with trailing zero bytes
+  pub deposit_cap: u64,                      // Ceiling on total_deposits, 0 for unlimited
+  pub withdrawal_fee_bps: u16,               // Fee taken from each withdrawal in basis points, at most MAX_WITHDRAWAL_FEE_BPS
+  pub fee_treasury: Pubkey,                  // Token account withdrawal fees are paid into, Pubkey::default() when there's no fee
+  pub vault_window_withdrawn: u64,           // Tokens withdrawn vault-wide in the current window
+  pub vault_window_start: i64,               // Unix timestamp the current vault-wide withdrawal window started at
+  pub max_vault_withdraw_per_window: u64,    // Vault-wide withdrawal allowance per VAULT_WITHDRAW_WINDOW_SECS window, 0 for unlimited
+  pub user_count: u64,                       // User vault records opened under this vault (by a first deposit, EnsureUserVault or TransferPosition)
+  pub lock_duration: i64,                    // Seconds after a user's latest deposit before they may withdraw, 0 for no lock
+  pub fee_tiers: [FeeTier; FEE_TIER_COUNT],  // Size-based withdrawal fees overriding withdrawal_fee_bps for large enough positions
+  pub require_whitelisted: bool,             // When set, only users with a Whitelist entry under this vault may deposit or receive positions
+  pub reward_rate_per_second: u64,           // Time-based rewards per deposited token per second, scaled by REWARD_RATE_PRECISION, 0 when off
+  pub reward_token_account: Pubkey,          // Token account ClaimRewards pays from, owned by the vault's reward authority PDA
+  pub admins: [Pubkey; ADMIN_COUNT],         // Keys whose signatures count toward admin_threshold, Pubkey::default() for an empty slot
+  pub admin_threshold: u8,                   // Distinct admins that must sign owner-gated instructions, 0 to rely on the owner's signature alone
+  pub instruction_fee_lamports: u64,         // SOL charged per deposit and withdrawal with the instruction-fees feature, 0 for none
+  pub instruction_fee_treasury: Pubkey,      // System account the instruction fee is paid to
+  pub config_frozen: bool,                   // Set once by FreezeConfig, after which no config-changing instruction goes through
 }
 
 // Empty implementation of the Sealed trait, required to implement Pack
@@ -29,24 +216,135 @@ impl IsInitialized for Vault {
 // Implements the Pack trait, which defines how to serialize/deserialize the Vault struct
 impl Pack for Vault {
    // Total length of the serialized Vault in bytes
-  // 1 byte for bool + 32 for owner + 32 for token_mint + 32 for vault_token_account
-  const LEN: usize = 1 + 32 + 32 + 32;
+  // 1 byte for version + 1 byte for bool + 32 for owner + 32 for token_mint + 32 for vault_token_account + 32 for merkle_tree + 32 for allowed_token_program
+  // + 8 for total_deposits + 8 for enabled_instructions + 16 for reward_index + 8 for last_reward_epoch
+  // + 8 for min_withdraw
+  // + 1 for paused
+  // + 8 for max_single_deposit
+  // + 32 for cooldown_exempt
+  // + 1 for vault_bump
+  // + 32 for name
+  // + 8 for deposit_cap
+  // + 2 for withdrawal_fee_bps
+  // + 32 for fee_treasury
+  // + 8 for vault_window_withdrawn
+  // + 8 for vault_window_start
+  // + 8 for max_vault_withdraw_per_window
+  // + 8 for user_count
+  // + 8 for lock_duration
+  // + FEE_TIERS_LEN for fee_tiers
+  // + 1 for require_whitelisted
+  // + 8 for reward_rate_per_second
+  // + 32 for reward_token_account
+  // + ADMINS_LEN for admins
+  // + 1 for admin_threshold
+  // + 8 for instruction_fee_lamports
+  // + 32 for instruction_fee_treasury
+  // + 1 for config_frozen
+  const LEN: usize = 1 + 1 + 32 + 32 + 32 + 32 + 32 + 8 + 8 + 16 + 8 + 8 + 1 + 8 + 32 + 1 + 32 + 8 + 2 + 32 + 8 + 8 + 8 + 8 + 8 + FEE_TIERS_LEN + 1 + 8 + 32 + ADMINS_LEN + 1 + 8 + 32 + 1;
+
+  // Accept both the current layout and the original VAULT_V1_LEN layout, so accounts created before the layout grew stay
+  // readable during a rolling migration. The default implementation would reject anything but exactly LEN bytes.
+  fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+    if input.len() != Vault::LEN && input.len() != VAULT_V1_LEN {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    Self::unpack_from_slice(input)
+  }
 
   // Deserialize a Vault struct from a byte slice
   fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
 
+    // The leading byte tells the layouts apart: current accounts start with VAULT_VERSION, legacy ones with their
+    // is_initialized flag. A zeroed, never-packed account reads as an uninitialized legacy vault.
+    match src.first() {
+      Some(&VAULT_VERSION) => {}
+      Some(0) | Some(1) => return Vault::unpack_v1(src),
+      _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    // array_ref! panics on a short slice, so a truncated account has to be turned into an error first
+    if src.len() < Vault::LEN {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
     // Safely interpret the input slice as an array of Vault::LEN bytes
     let src = array_ref![src, 0, Vault::LEN];
 
     // Split the slice into its individual fields
-    let (is_initialized, owner, token_mint, vault_token_account) = array_ref![src, 1, 32, 32, 32];
+    let (
+      version,
+      is_initialized,
+      owner,
+      token_mint,
+      vault_token_account,
+      merkle_tree,
+      allowed_token_program,
+      total_deposits,
+      enabled_instructions,
+      reward_index,
+      last_reward_epoch,
+      min_withdraw,
+      paused,
+      max_single_deposit,
+      cooldown_exempt,
+      vault_bump,
+      name,
+      deposit_cap,
+      withdrawal_fee_bps,
+      fee_treasury,
+      vault_window_withdrawn,
+      vault_window_start,
+      max_vault_withdraw_per_window,
+      user_count,
+      lock_duration,
+      fee_tiers,
+      require_whitelisted,
+      reward_rate_per_second,
+      reward_token_account,
+      admins,
+      admin_threshold,
+      instruction_fee_lamports,
+      instruction_fee_treasury,
+      config_frozen,
+    ) = array_refs![src, 1, 1, 32, 32, 32, 32, 32, 8, 8, 16, 8, 8, 1, 8, 32, 1, 32, 8, 2, 32, 8, 8, 8, 8, 8, FEE_TIERS_LEN, 1, 8, 32, ADMINS_LEN, 1, 8, 32, 1];
 
     // Construct and return the Vault struct from the split byte fields
     Ok(Vault {
+      version: version[0],
       is_initialized: is_initialized[0] != 0,                                 // Convert byte to bool (non-zero means true)
       owner: Pubkey::new_from_array(*owner),                                  // Convert byte array to Pubkey
       token_mint: Pubkey::new_from_array(*token_mint),
       vault_token_account: Pubkey::new_from_array(*vault_token_account),
+      merkle_tree: Pubkey::new_from_array(*merkle_tree),
+      allowed_token_program: Pubkey::new_from_array(*allowed_token_program),
+      total_deposits: u64::from_le_bytes(*total_deposits),                  // Convert 8 little-endian bytes to u64
+      enabled_instructions: u64::from_le_bytes(*enabled_instructions),
+      reward_index: u128::from_le_bytes(*reward_index),
+      last_reward_epoch: u64::from_le_bytes(*last_reward_epoch),
+      min_withdraw: u64::from_le_bytes(*min_withdraw),
+      paused: paused[0] != 0,
+      max_single_deposit: u64::from_le_bytes(*max_single_deposit),
+      cooldown_exempt: Pubkey::new_from_array(*cooldown_exempt),
+      vault_bump: vault_bump[0],
+      name: *name,
+      deposit_cap: u64::from_le_bytes(*deposit_cap),
+      withdrawal_fee_bps: u16::from_le_bytes(*withdrawal_fee_bps),
+      fee_treasury: Pubkey::new_from_array(*fee_treasury),
+      vault_window_withdrawn: u64::from_le_bytes(*vault_window_withdrawn),
+      vault_window_start: i64::from_le_bytes(*vault_window_start),
+      max_vault_withdraw_per_window: u64::from_le_bytes(*max_vault_withdraw_per_window),
+      user_count: u64::from_le_bytes(*user_count),
+      lock_duration: i64::from_le_bytes(*lock_duration),
+      fee_tiers: FeeTier::unpack_tiers(fee_tiers),
+      require_whitelisted: require_whitelisted[0] != 0,
+      reward_rate_per_second: u64::from_le_bytes(*reward_rate_per_second),
+      reward_token_account: Pubkey::new_from_array(*reward_token_account),
+      admins: unpack_admins(admins),
+      admin_threshold: admin_threshold[0],
+      instruction_fee_lamports: u64::from_le_bytes(*instruction_fee_lamports),
+      instruction_fee_treasury: Pubkey::new_from_array(*instruction_fee_treasury),
+      config_frozen: config_frozen[0] != 0,
     })
   }
 
@@ -57,28 +355,409 @@ impl Pack for Vault {
 
 
     let (
+      version_dst,                        // 1 byte for the layout version
       is_initialized_dst,                 // 1 byte for the bool
       owner_dst,                          // 32 bytes for the owner pubkey
       token_mint_dst,                     // 32 bytes for the mint pubkey
-      vault_token_account_dst             // 32 bytes for the mint pubkey
-    ) = mut_array_refs![dst, 1, 32, 32, 32];
+      vault_token_account_dst,            // 32 bytes for the vault token account pubkey
+      merkle_tree_dst,                    // 32 bytes for the merkle tree pubkey
+      allowed_token_program_dst,          // 32 bytes for the allowed token program id
+      total_deposits_dst,                 // 8 bytes for the running deposit total
+      enabled_instructions_dst,           // 8 bytes for the instruction bitmask
+      reward_index_dst,                   // 16 bytes for the reward accumulator
+      last_reward_epoch_dst,              // 8 bytes for the last distribution epoch
+      min_withdraw_dst,                   // 8 bytes for the minimum withdrawal
+      paused_dst,                         // 1 byte for the paused flag
+      max_single_deposit_dst,             // 8 bytes for the single-deposit limit
+      cooldown_exempt_dst,                // 32 bytes for the cooldown-exempt account
+      vault_bump_dst,                     // 1 byte for the vault authority bump
+      name_dst,                           // 32 bytes for the UTF-8 vault name
+      deposit_cap_dst,                    // 8 bytes for the deposit cap
+      withdrawal_fee_bps_dst,             // 2 bytes for the withdrawal fee
+      fee_treasury_dst,                   // 32 bytes for the fee treasury token account
+      vault_window_withdrawn_dst,         // 8 bytes for the window withdrawn total
+      vault_window_start_dst,             // 8 bytes for the window start
+      max_vault_withdraw_per_window_dst,  // 8 bytes for the per-window withdrawal cap
+      user_count_dst,                     // 8 bytes for the user count
+      lock_duration_dst,                  // 8 bytes for the withdrawal lock
+      fee_tiers_dst,                      // FEE_TIERS_LEN bytes for the fee tiers
+      require_whitelisted_dst,            // 1 byte for the whitelist flag
+      reward_rate_per_second_dst,         // 8 bytes for the reward rate
+      reward_token_account_dst,           // 32 bytes for the reward token account
+      admins_dst,                         // ADMINS_LEN bytes for the admin set
+      admin_threshold_dst,                // 1 byte for the admin threshold
+      instruction_fee_lamports_dst,       // 8 bytes for the per-instruction SOL fee
+      instruction_fee_treasury_dst,       // 32 bytes for the SOL fee treasury
+      config_frozen_dst,                  // 1 byte for the config freeze flag
+    ) = mut_array_refs![dst, 1, 1, 32, 32, 32, 32, 32, 8, 8, 16, 8, 8, 1, 8, 32, 1, 32, 8, 2, 32, 8, 8, 8, 8, 8, FEE_TIERS_LEN, 1, 8, 32, ADMINS_LEN, 1, 8, 32, 1];
 
     
+    version_dst[0] = VAULT_VERSION;                                               // Whatever layout it was read from, it's written as the current one
     is_initialized_dst[0] = self.is_initialized as u8;                            // Store is_initialized as 0 or 1
 
     // Copy the bytes of each Pubkey into their respective destination slices
     owner_dst.copy_from_slice(self.owner.as_ref());
     token_mint_dst.copy_from_slice(self.token_mint.as_ref());
     vault_token_account_dst.copy_from_slice(self.vault_token_account.as_ref());
+    merkle_tree_dst.copy_from_slice(self.merkle_tree.as_ref());
+    allowed_token_program_dst.copy_from_slice(self.allowed_token_program.as_ref());
+    *total_deposits_dst = self.total_deposits.to_le_bytes();                      // Store the total as little-endian bytes
+    *enabled_instructions_dst = self.enabled_instructions.to_le_bytes();
+    *reward_index_dst = self.reward_index.to_le_bytes();
+    *last_reward_epoch_dst = self.last_reward_epoch.to_le_bytes();
+    *min_withdraw_dst = self.min_withdraw.to_le_bytes();
+    paused_dst[0] = self.paused as u8;
+    *max_single_deposit_dst = self.max_single_deposit.to_le_bytes();
+    cooldown_exempt_dst.copy_from_slice(self.cooldown_exempt.as_ref());
+    vault_bump_dst[0] = self.vault_bump;
+    *name_dst = self.name;
+    *deposit_cap_dst = self.deposit_cap.to_le_bytes();
+    *withdrawal_fee_bps_dst = self.withdrawal_fee_bps.to_le_bytes();
+    fee_treasury_dst.copy_from_slice(self.fee_treasury.as_ref());
+    *vault_window_withdrawn_dst = self.vault_window_withdrawn.to_le_bytes();
+    *vault_window_start_dst = self.vault_window_start.to_le_bytes();
+    *max_vault_withdraw_per_window_dst = self.max_vault_withdraw_per_window.to_le_bytes();
+    *user_count_dst = self.user_count.to_le_bytes();
+    *lock_duration_dst = self.lock_duration.to_le_bytes();
+    FeeTier::pack_tiers(&self.fee_tiers, fee_tiers_dst);
+    require_whitelisted_dst[0] = self.require_whitelisted as u8;
+    *reward_rate_per_second_dst = self.reward_rate_per_second.to_le_bytes();
+    reward_token_account_dst.copy_from_slice(self.reward_token_account.as_ref());
+    for (admin, chunk) in self.admins.iter().zip(admins_dst.chunks_exact_mut(32)) {
+      chunk.copy_from_slice(admin.as_ref());
+    }
+    admin_threshold_dst[0] = self.admin_threshold;
+    *instruction_fee_lamports_dst = self.instruction_fee_lamports.to_le_bytes();
+    instruction_fee_treasury_dst.copy_from_slice(self.instruction_fee_treasury.as_ref());
+    config_frozen_dst[0] = self.config_frozen as u8;
+  }
+}
+
+// Read ADMIN_COUNT pubkeys packed back to back
+pub fn unpack_admins(src: &[u8; ADMINS_LEN]) -> [Pubkey; ADMIN_COUNT] {
+  let mut admins = [Pubkey::default(); ADMIN_COUNT];
+  for (admin, chunk) in admins.iter_mut().zip(src.chunks_exact(32)) {
+    *admin = Pubkey::new_from_array(*array_ref![chunk, 0, 32]);
+  }
+  admins
+}
+
+impl Vault {
+  // Parse an original-layout account. The v1 fields follow the version byte in the current layout, so they're copied in
+  // behind it in a zeroed current-size buffer and every newer field starts at zero, except those whose neutral value isn't zero.
+  fn unpack_v1(src: &[u8]) -> Result<Self, ProgramError> {
+    if src.len() < VAULT_V1_LEN {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut upgraded = [0u8; Vault::LEN];
+    upgraded[VAULT_VERSION_OFFSET] = VAULT_VERSION;
+    upgraded[VAULT_IS_INITIALIZED_OFFSET..VAULT_IS_INITIALIZED_OFFSET + VAULT_V1_LEN].copy_from_slice(&src[..VAULT_V1_LEN]);
+
+    let mut vault = Vault::unpack_from_slice(&upgraded)?;
+    vault.version = VAULT_V1_VERSION;                          // Rewritten as VAULT_VERSION when packed
+    vault.allowed_token_program = spl_token::id();             // v1 vaults could only ever be used with SPL Token
+    vault.enabled_instructions = u64::MAX;                     // Nothing was disableable before, so everything is enabled
+    vault.last_reward_epoch = u64::MAX;                        // Never rewarded
+    // vault_bump stays 0: v1 accounts never recorded it, so the processor falls back to deriving it
+    Ok(vault)
+  }
+
+  // Unpack a vault state account, rejecting one this program doesn't own. The bytes alone prove nothing: an account
+  // owned by another program could be crafted to look like any vault, and only this program can write a genuine one.
+  // Length and initialization are checked by unpack, which also accepts the legacy layout so Migrate can still read it.
+  pub fn load(account: &AccountInfo, program_id: &Pubkey) -> Result<Vault, ProgramError> {
+    if account.owner != program_id {
+      return Err(ProgramError::IllegalOwner);
+    }
+    Vault::unpack(&account.try_borrow_data()?)
+  }
+
+  // Add `amount` to total_deposits directly in a packed vault account's data. For hot paths where nothing else in the
+  // vault changes, this skips re-serializing every other field.
+  pub fn add_deposits(data: &mut [u8], amount: u64) -> Result<(), ProgramError> {
+    let total_deposits = Vault::total_deposits_mut(data)?;
+    let new_total = u64::from_le_bytes(*total_deposits).checked_add(amount).ok_or(VaultError::Overflow)?;
+    *total_deposits = new_total.to_le_bytes();
+    Ok(())
+  }
+
+  // Subtract `amount` from total_deposits in place, the counterpart of add_deposits
+  pub fn sub_deposits(data: &mut [u8], amount: u64) -> Result<(), ProgramError> {
+    let total_deposits = Vault::total_deposits_mut(data)?;
+    let new_total = u64::from_le_bytes(*total_deposits).checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+    *total_deposits = new_total.to_le_bytes();
+    Ok(())
+  }
+
+  // The total_deposits bytes of a packed vault. Only the current layout can be written in place; a legacy account has
+  // its fields one byte earlier and, just as with pack, has to go through Migrate first.
+  fn total_deposits_mut(data: &mut [u8]) -> Result<&mut [u8; 8], ProgramError> {
+    if data.len() != Vault::LEN || data[VAULT_VERSION_OFFSET] != VAULT_VERSION {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(array_mut_ref![data, VAULT_TOTAL_DEPOSITS_OFFSET, 8])
+  }
+
+  // The vault name without its zero padding
+  pub fn name_bytes(&self) -> &[u8] {
+    trim_name(&self.name)
+  }
+
+  // Whether user balances for this vault live in a compressed Merkle tree rather than UserVault PDAs
+  pub fn is_compressed(&self) -> bool {
+    self.merkle_tree != Pubkey::default()
+  }
+
+  // Grow every deposited token by total_reward / total_deposits by scaling the index, and count what that credits into
+  // the vault's total. Users pick up their share the next time they interact, via UserVault::settle_rewards.
+  // total_deposits already counts earlier rounds nobody has settled yet, and the index compounds, so those unsettled
+  // rewards earn their share of this round too; an additive index would pay them nothing and strand their part.
+  // The rounding remainder isn't owed to anyone, so it stays out of total_deposits and sits in the token account.
+  pub fn accrue_rewards(&mut self, total_reward: u64) -> Result<(), ProgramError> {
+    if self.total_deposits == 0 {
+      return Err(VaultError::NoDepositsToReward.into());       // Nobody to credit, the tokens would be stranded
+    }
+
+    let total_deposits = self.total_deposits as u128;
+    let index = reward_growth(self.reward_index);
+    let new_index = index
+      .checked_mul(total_deposits + total_reward as u128)
+      .ok_or(VaultError::Overflow)?
+      / total_deposits;
+
+    // At most total_reward, since new_index was rounded down, and at least what the positions settle to in sum
+    let distributed = (total_deposits.checked_mul(new_index).ok_or(VaultError::Overflow)? / index - total_deposits) as u64;
+
+    self.reward_index = new_index;
+    self.total_deposits = self.total_deposits.checked_add(distributed).ok_or(VaultError::Overflow)?;
+
+    Ok(())
+  }
+
+  // Fail with VaultPaused while the owner has the vault frozen
+  pub fn check_not_paused(&self) -> Result<(), ProgramError> {
+    if self.paused {
+      return Err(VaultError::VaultPaused.into());
+    }
+    Ok(())
+  }
+
+  // Reject a deposit larger than the per-deposit limit (0 means unlimited)
+  pub fn check_single_deposit(&self, amount: u64) -> Result<(), ProgramError> {
+    if self.max_single_deposit > 0 && amount > self.max_single_deposit {
+      return Err(VaultError::SingleDepositTooLarge.into());
+    }
+    Ok(())
+  }
+
+  // Reject a deposit that would take total_deposits past the cap (0 means unlimited)
+  pub fn check_deposit_cap(&self, amount: u64) -> Result<(), ProgramError> {
+    let new_total = self.total_deposits.checked_add(amount).ok_or(VaultError::Overflow)?;
+    if self.deposit_cap > 0 && new_total > self.deposit_cap {
+      return Err(VaultError::CapExceeded.into());
+    }
+    Ok(())
+  }
+
+  // The fee rate for a position of `position` tokens: the tier with the highest threshold the position reaches,
+  // or withdrawal_fee_bps when it reaches none
+  pub fn fee_bps_for(&self, position: u64) -> u16 {
+    let mut bps = self.withdrawal_fee_bps;
+    let mut reached = 0;
+    for tier in self.fee_tiers.iter() {
+      if tier.threshold > reached && position >= tier.threshold {
+        reached = tier.threshold;
+        bps = tier.bps;
+      }
+    }
+    bps
+  }
+
+  // The fee kept back from a withdrawal of `amount` out of a `position`-sized balance. Rounded down, but never below one
+  // unit while a fee rate applies, so splitting a withdrawal into pieces small enough to round to zero doesn't dodge it
+  pub fn withdrawal_fee(&self, amount: u64, position: u64) -> Result<u64, ProgramError> {
+    let bps = self.fee_bps_for(position);
+    let fee = (amount as u128).checked_mul(bps as u128).ok_or(VaultError::Overflow)? / BPS_DENOMINATOR as u128;
+    if bps > 0 && amount > 0 {
+      return Ok((fee as u64).max(1));                          // At most amount: the rate is capped below 100% and amount >= 1
+    }
+    Ok(fee as u64)
+  }
+
+  // The fee `user` pays on a withdrawal: nothing when it's the owner rebalancing their own position, the tiered fee
+  // for everyone else
+  pub fn withdrawal_fee_for(&self, user: &Pubkey, amount: u64, position: u64) -> Result<u64, ProgramError> {
+    if *user == self.owner {
+      return Ok(0);
+    }
+    self.withdrawal_fee(amount, position)
+  }
+
+  // What can still leave the vault in the window that's current at `now`, u64::MAX when there's no vault-wide cap
+  pub fn window_withdraw_remaining(&self, now: i64) -> u64 {
+    if self.max_vault_withdraw_per_window == 0 {
+      return u64::MAX;
+    }
+    if now.saturating_sub(self.vault_window_start) >= VAULT_WITHDRAW_WINDOW_SECS {
+      return self.max_vault_withdraw_per_window;              // The window has rolled over, the full allowance is back
+    }
+    self.max_vault_withdraw_per_window.saturating_sub(self.vault_window_withdrawn)
+  }
+
+  // Count a withdrawal against the vault-wide window, starting a new window once the current one has elapsed.
+  // Slows a bank run down to max_vault_withdraw_per_window per window instead of letting the vault drain at once.
+  pub fn record_window_withdrawal(&mut self, amount: u64, now: i64) -> Result<(), ProgramError> {
+    if self.max_vault_withdraw_per_window == 0 {
+      return Ok(());
+    }
+
+    if now.saturating_sub(self.vault_window_start) >= VAULT_WITHDRAW_WINDOW_SECS {
+      self.vault_window_start = now;
+      self.vault_window_withdrawn = 0;
+    }
+
+    let withdrawn = self.vault_window_withdrawn.checked_add(amount).ok_or(VaultError::Overflow)?;
+    if withdrawn > self.max_vault_withdraw_per_window {
+      return Err(VaultError::VaultRateLimited.into());
+    }
+    self.vault_window_withdrawn = withdrawn;
+
+    Ok(())
+  }
+
+  // Whether `user` may withdraw without waiting out the cooldown
+  pub fn is_cooldown_exempt(&self, user: &Pubkey) -> bool {
+    self.cooldown_exempt != Pubkey::default() && self.cooldown_exempt == *user
+  }
+
+  // Whether `user_vault` can't be withdrawn from at `now`: it hasn't vested yet, or it's still inside the vault-wide lock
+  // that follows its latest deposit. Cooldown exemption only lifts the latter; a vesting date is the position's own.
+  pub fn is_withdraw_locked(&self, user_vault: &UserVault, now: i64) -> bool {
+    now < user_vault.locked_until
+      || (self.lock_duration > 0
+        && !self.is_cooldown_exempt(&user_vault.user)
+        && now.saturating_sub(user_vault.last_deposit_ts) < self.lock_duration)
+  }
+
+  // When `user_vault` stops being locked: the later of its vesting date and the end of the lock after its latest
+  // deposit. Only meaningful while is_withdraw_locked holds.
+  pub fn unlocks_at(&self, user_vault: &UserVault) -> i64 {
+    let lock_end = if self.lock_duration > 0 && !self.is_cooldown_exempt(&user_vault.user) {
+      user_vault.last_deposit_ts.saturating_add(self.lock_duration)
+    } else {
+      0
+    };
+    user_vault.locked_until.max(lock_end)
+  }
+
+  // Whether the instruction with the given tag is enabled on this vault
+  pub fn is_instruction_enabled(&self, tag: u8) -> bool {
+    // Tags past the mask width can't be disabled, so they're always allowed. Unavailable ones are masked out here as
+    // well, so vaults that stored every bit before they were withdrawn don't keep them
+    tag >= 64 || self.enabled_instructions & !UNAVAILABLE_INSTRUCTIONS & (1u64 << tag) != 0
+  }
+
+  // Reject any token program other than the one recorded at init, so a substituted program can never be CPI'd into
+  pub fn check_token_program(&self, token_program: &Pubkey) -> Result<(), ProgramError> {
+    if *token_program != self.allowed_token_program {
+      return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+  }
+}
+
+// Strip the trailing zero bytes a fixed-size name is padded with
+pub fn trim_name(name: &[u8; 32]) -> &[u8] {
+  let len = name.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+  &name[..len]
+}
+
+// A name is stored as given, so it has to be valid UTF-8 once the padding is dropped for UIs to render it
+pub fn check_name(name: &[u8; 32]) -> Result<(), ProgramError> {
+  std::str::from_utf8(trim_name(name)).map_err(|_| ProgramError::InvalidInstructionData)?;
+  Ok(())
+}
+
+// Every constraint a client needs to validate a deposit/withdraw form, returned by GetLimits in one call.
+// Limits a vault doesn't enforce are reported as 0.
+pub struct VaultLimits {
+  pub min_deposit: u64,                     // Smallest deposit accepted
+  pub deposit_cap: u64,                     // Maximum total deposits the vault accepts
+  pub min_withdraw: u64,                    // Smallest partial withdrawal accepted
+  pub cooldown_secs: u64,                   // Seconds a user must wait between a deposit and a withdrawal
+  pub withdraw_fee_bps: u16,                // Fee charged on withdrawals, in basis points
+  pub reserve: u64,                         // Tokens the vault keeps back from withdrawals
+}
+
+impl VaultLimits {
+  // Collect the limits currently configured on `vault`
+  pub fn from_vault(vault: &Vault) -> Self {
+    VaultLimits {
+      min_deposit: 0,
+      deposit_cap: vault.deposit_cap,
+      min_withdraw: vault.min_withdraw,
+      cooldown_secs: vault.lock_duration.max(0) as u64,
+      withdraw_fee_bps: vault.withdrawal_fee_bps,
+      reserve: 0,
+    }
+  }
+}
+
+impl Sealed for VaultLimits {}
+
+// Pack gives clients the same unpack_from_slice they use for the account structs to decode the return data
+impl Pack for VaultLimits {
+  // 8 for min_deposit + 8 for deposit_cap + 8 for min_withdraw + 8 for cooldown_secs + 2 for withdraw_fee_bps + 8 for reserve
+  const LEN: usize = 8 + 8 + 8 + 8 + 2 + 8;
+
+  fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+    if src.len() < VaultLimits::LEN {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    let src = array_ref![src, 0, VaultLimits::LEN];
+    let (min_deposit, deposit_cap, min_withdraw, cooldown_secs, withdraw_fee_bps, reserve) =
+      array_refs![src, 8, 8, 8, 8, 2, 8];
+
+    Ok(VaultLimits {
+      min_deposit: u64::from_le_bytes(*min_deposit),
+      deposit_cap: u64::from_le_bytes(*deposit_cap),
+      min_withdraw: u64::from_le_bytes(*min_withdraw),
+      cooldown_secs: u64::from_le_bytes(*cooldown_secs),
+      withdraw_fee_bps: u16::from_le_bytes(*withdraw_fee_bps),
+      reserve: u64::from_le_bytes(*reserve),
+    })
+  }
+
+  fn pack_into_slice(&self, dst: &mut [u8]) {
+    let dst = array_mut_ref![dst, 0, VaultLimits::LEN];
+    let (min_deposit_dst, deposit_cap_dst, min_withdraw_dst, cooldown_secs_dst, withdraw_fee_bps_dst, reserve_dst) =
+      mut_array_refs![dst, 8, 8, 8, 8, 2, 8];
+
+    *min_deposit_dst = self.min_deposit.to_le_bytes();
+    *deposit_cap_dst = self.deposit_cap.to_le_bytes();
+    *min_withdraw_dst = self.min_withdraw.to_le_bytes();
+    *cooldown_secs_dst = self.cooldown_secs.to_le_bytes();
+    *withdraw_fee_bps_dst = self.withdraw_fee_bps.to_le_bytes();
+    *reserve_dst = self.reserve.to_le_bytes();
   }
 }
 
 // Structure to hold a user's individual vault state
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]                 // Default is the all-zero, uninitialized record
 pub struct UserVault {
   pub is_initialized: bool,                 // Flag to check if the account has been initialized
   pub user: Pubkey,                         // The public key of the depositor i.e the user
   pub vault: Pubkey,                        // The vault this user is interacting with
   pub deposited_amount: u64,                // Total amount this user has deposited
+  pub arbiter: Pubkey,                      // Third party that must co-sign releases, Pubkey::default() when withdrawals are unrestricted
+  pub pending_withdrawal: u64,              // Amount the user has requested for arbiter release, 0 when no request is open
+  pub reward_index_snapshot: u128,          // Vault::reward_index as of this user's last settlement
+  pub last_deposit_ts: i64,                 // Unix timestamp of this user's latest deposit, starts the Vault::lock_duration lock
+  pub locked_until: i64,                    // Unix timestamp this position vests at, set by DepositLocked; 0 when never locked
+  pub reward_debt: u64,                     // Time-based rewards the vault owes this user, paid out by ClaimRewards
+  pub last_update_ts: i64,                  // Unix timestamp reward_debt was last accrued up to
 }
 
 // Empty implementation of the Sealed trait, required to implement Pack
@@ -93,21 +772,44 @@ fn is_initialized(&self) -> bool {
 
 // Implement Pack so the struct can be serialized/deserialized into account data
 impl Pack for UserVault {
-  // The total size of the struct in bytes: 1 (bool) + 32 + 32 + 8 = 73 bytes
-  const LEN: usize = 1 + 32 + 32 + 8;
+  // The total size of the struct in bytes: 1 (bool) + 32 + 32 + 8 + 32 + 8 + 16 + 8 + 8 + 8 + 8 = 161 bytes
+  const LEN: usize = 1 + 32 + 32 + 8 + 32 + 8 + 16 + 8 + 8 + 8 + 8;
 
   // Deserialize from raw byte slice into a UserVault struct
   fn unpack_from_slice(src: &[u8]) -> Result<Self, solana_program::program_error::ProgramError> {
+    // Error out on a short slice instead of letting array_ref! panic
+    if src.len() < UserVault::LEN {
+      return Err(ProgramError::InvalidAccountData);
+    }
     let src = array_ref![src, 0, UserVault::LEN];
 
     // Split the byte slice into parts matching the field sizes
-    let (is_initialized, user, vault, deposited_amount) = array_refs![src, 1, 32, 32, 8];
+    let (
+      is_initialized,
+      user,
+      vault,
+      deposited_amount,
+      arbiter,
+      pending_withdrawal,
+      reward_index_snapshot,
+      last_deposit_ts,
+      locked_until,
+      reward_debt,
+      last_update_ts,
+    ) = array_refs![src, 1, 32, 32, 8, 32, 8, 16, 8, 8, 8, 8];
 
     Ok(UserVault{
       is_initialized: is_initialized[0] != 0,                     // Convert byte to bool
       user: Pubkey::new_from_array(*user),                        // Deserialize user pubkey
       vault: Pubkey::new_from_array(*vault),                      // Deserialize vault pubkey
       deposited_amount: u64::from_le_bytes(*deposited_amount),    // Convert 8 bytes to u64
+      arbiter: Pubkey::new_from_array(*arbiter),                  // Deserialize arbiter pubkey
+      pending_withdrawal: u64::from_le_bytes(*pending_withdrawal),
+      reward_index_snapshot: u128::from_le_bytes(*reward_index_snapshot),
+      last_deposit_ts: i64::from_le_bytes(*last_deposit_ts),
+      locked_until: i64::from_le_bytes(*locked_until),
+      reward_debt: u64::from_le_bytes(*reward_debt),
+      last_update_ts: i64::from_le_bytes(*last_update_ts),
     })
   }
 
@@ -116,12 +818,520 @@ impl Pack for UserVault {
     let dst = array_mut_ref![dst, 0, UserVault::LEN];
 
     // Split the destination slice into pieces for each field
-    let (is_initialized_dst, user_dst, vault_dst, deposited_amount_dst) = mut_array_refs![dst, 1, 32, 32, 8];
+    let (
+      is_initialized_dst,
+      user_dst,
+      vault_dst,
+      deposited_amount_dst,
+      arbiter_dst,
+      pending_withdrawal_dst,
+      reward_index_snapshot_dst,
+      last_deposit_ts_dst,
+      locked_until_dst,
+      reward_debt_dst,
+      last_update_ts_dst,
+    ) = mut_array_refs![dst, 1, 32, 32, 8, 32, 8, 16, 8, 8, 8, 8];
 
      // Convert each field into bytes and write it
     is_initialized_dst[0] = self.is_initialized as u8;
     user_dst.copy_from_slice(self.user.as_ref());
     vault_dst.copy_from_slice(self.vault.as_ref());
     *deposited_amount_dst = self.deposited_amount.to_le_bytes();
+    arbiter_dst.copy_from_slice(self.arbiter.as_ref());
+    *pending_withdrawal_dst = self.pending_withdrawal.to_le_bytes();
+    *reward_index_snapshot_dst = self.reward_index_snapshot.to_le_bytes();
+    *last_deposit_ts_dst = self.last_deposit_ts.to_le_bytes();
+    *locked_until_dst = self.locked_until.to_le_bytes();
+    *reward_debt_dst = self.reward_debt.to_le_bytes();
+    *last_update_ts_dst = self.last_update_ts.to_le_bytes();
+  }
+}
+
+impl UserVault {
+  // Whether withdrawals from this position must be released by an arbiter
+  pub fn has_arbiter(&self) -> bool {
+    self.arbiter != Pubkey::default()
+  }
+
+  // Compound the rewards accrued since the last settlement into the deposited balance and move the snapshot forward.
+  // Must run before any change to deposited_amount so the new balance only earns from the current index onward.
+  pub fn settle_rewards(&mut self, reward_index: u128) -> Result<(), ProgramError> {
+    // An empty position has nothing to earn. It just catches up, even to a lower index, which is what a record left
+    // over from a vault closed before CloseVault required user_count == 0 sees; the check below would otherwise
+    // reject it forever.
+    if self.deposited_amount == 0 {
+      self.reward_index_snapshot = reward_index;
+      return Ok(());
+    }
+
+    let (snapshot, index) = (reward_growth(self.reward_index_snapshot), reward_growth(reward_index));
+    if index < snapshot {
+      return Err(VaultError::Overflow.into());
+    }
+
+    // The balance grows by the same factor as the index did since the snapshot, compounding every round in between
+    let grown = (self.deposited_amount as u128)
+      .checked_mul(index)
+      .ok_or(VaultError::Overflow)?
+      / snapshot;
+
+    self.deposited_amount = u64::try_from(grown).map_err(|_| VaultError::Overflow)?;
+    self.reward_index_snapshot = reward_index;
+
+    Ok(())
+  }
+
+  // Add the time-based rewards earned at `reward_rate_per_second` since last_update_ts to reward_debt and move
+  // last_update_ts to `now`. Like settle_rewards it must run before any change to deposited_amount. It saturates
+  // rather than failing: it runs ahead of every withdrawal, and an owner-set rate must never be able to block principal.
+  pub fn accrue_time_rewards(&mut self, reward_rate_per_second: u64, now: i64) {
+    let elapsed = now.saturating_sub(self.last_update_ts).max(0) as u128;
+
+    let earned = (self.deposited_amount as u128)
+      .checked_mul(reward_rate_per_second as u128)
+      .and_then(|per_second| per_second.checked_mul(elapsed))
+      .map_or(u128::MAX, |scaled| scaled / REWARD_RATE_PRECISION);
+    let earned = u64::try_from(earned).unwrap_or(u64::MAX);
+
+    self.reward_debt = self.reward_debt.saturating_add(earned);
+    self.last_update_ts = now;
+  }
+}
+
+// A user's entry on a permissioned vault's deposit whitelist. The entry existing (and being initialized) is the
+// permission; removing the user closes the account.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Whitelist {
+  pub is_initialized: bool,                 // Set when the owner adds the user
+  pub vault: Pubkey,                        // The vault state account the entry belongs to
+  pub user: Pubkey,                         // The whitelisted user
+}
+
+impl Sealed for Whitelist {}
+
+impl IsInitialized for Whitelist {
+  fn is_initialized(&self) -> bool {
+    self.is_initialized
+  }
+}
+
+impl Pack for Whitelist {
+  // 1 (bool) + 32 + 32 = 65 bytes
+  const LEN: usize = 1 + 32 + 32;
+
+  fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+    if src.len() < Whitelist::LEN {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    let src = array_ref![src, 0, Whitelist::LEN];
+    let (is_initialized, vault, user) = array_refs![src, 1, 32, 32];
+
+    Ok(Whitelist {
+      is_initialized: is_initialized[0] != 0,
+      vault: Pubkey::new_from_array(*vault),
+      user: Pubkey::new_from_array(*user),
+    })
+  }
+
+  fn pack_into_slice(&self, dst: &mut [u8]) {
+    let dst = array_mut_ref![dst, 0, Whitelist::LEN];
+    let (is_initialized_dst, vault_dst, user_dst) = mut_array_refs![dst, 1, 32, 32];
+
+    is_initialized_dst[0] = self.is_initialized as u8;
+    vault_dst.copy_from_slice(self.vault.as_ref());
+    user_dst.copy_from_slice(self.user.as_ref());
+  }
+}
+
+// One extra mint held by a vault, next to the root Vault's own token_mint. The root vault keeps the owner and the
+// vault-wide flags (pause, token program, whitelist, lock); the asset keeps its own token account and total. A user's
+// position in the asset is a UserVault at ["user_vault", user, vault asset], whose `vault` is the asset account.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VaultAsset {
+  pub is_initialized: bool,                 // Set by AddAsset
+  pub vault: Pubkey,                        // The root vault state account
+  pub mint: Pubkey,                         // Mint of the asset
+  pub token_account: Pubkey,                // Token account holding the asset, owned by the vault authority PDA
+  pub total_deposits: u64,                  // Sum of every position in this asset
+}
+
+impl Sealed for VaultAsset {}
+
+impl IsInitialized for VaultAsset {
+  fn is_initialized(&self) -> bool {
+    self.is_initialized
+  }
+}
+
+impl Pack for VaultAsset {
+  // 1 (bool) + 32 + 32 + 32 + 8 = 105 bytes
+  const LEN: usize = 1 + 32 + 32 + 32 + 8;
+
+  fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+    if src.len() < VaultAsset::LEN {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    let src = array_ref![src, 0, VaultAsset::LEN];
+    let (is_initialized, vault, mint, token_account, total_deposits) = array_refs![src, 1, 32, 32, 32, 8];
+
+    Ok(VaultAsset {
+      is_initialized: is_initialized[0] != 0,
+      vault: Pubkey::new_from_array(*vault),
+      mint: Pubkey::new_from_array(*mint),
+      token_account: Pubkey::new_from_array(*token_account),
+      total_deposits: u64::from_le_bytes(*total_deposits),
+    })
+  }
+
+  fn pack_into_slice(&self, dst: &mut [u8]) {
+    let dst = array_mut_ref![dst, 0, VaultAsset::LEN];
+    let (is_initialized_dst, vault_dst, mint_dst, token_account_dst, total_deposits_dst) =
+      mut_array_refs![dst, 1, 32, 32, 32, 8];
+
+    is_initialized_dst[0] = self.is_initialized as u8;
+    vault_dst.copy_from_slice(self.vault.as_ref());
+    mint_dst.copy_from_slice(self.mint.as_ref());
+    token_account_dst.copy_from_slice(self.token_account.as_ref());
+    *total_deposits_dst = self.total_deposits.to_le_bytes();
+  }
+}
+
+// Borsh encodes these structs field by field in declaration order with fixed-width little-endian integers and raw
+// 32-byte keys, which is byte-for-byte the Pack layout. The differences are in what is accepted on the way in:
+// Borsh rejects bool bytes other than 0 and 1 (Pack treats any non-zero byte as true), and it has no fallback
+// for VAULT_V1_LEN accounts, so only current-layout accounts can be read this way.
+// Trailing bytes past the struct are ignored, as with Pack on a larger account.
+#[cfg(feature = "borsh")]
+pub fn unpack_borsh<T: BorshDeserialize>(src: &[u8]) -> Result<T, ProgramError> {
+  T::deserialize(&mut &src[..]).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+// Serialize `value` with Borsh into the start of `dst`
+#[cfg(feature = "borsh")]
+pub fn pack_borsh<T: BorshSerialize>(value: &T, dst: &mut [u8]) -> Result<(), ProgramError> {
+  let bytes = borsh::to_vec(value).map_err(|_| ProgramError::InvalidAccountData)?;
+  dst
+    .get_mut(..bytes.len())
+    .ok_or(ProgramError::AccountDataTooSmall)?
+    .copy_from_slice(&bytes);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A vault with every field set to something other than its zero value, so a field dropped by pack or unpack shows up
+  fn sample_vault() -> Vault {
+    Vault {
+      version: VAULT_VERSION,
+      is_initialized: true,
+      owner: Pubkey::new_unique(),
+      token_mint: Pubkey::new_unique(),
+      vault_token_account: Pubkey::new_unique(),
+      merkle_tree: Pubkey::new_unique(),
+      allowed_token_program: Pubkey::new_unique(),
+      total_deposits: 1_000,
+      enabled_instructions: 0xdead_beef,
+      reward_index: 42 * REWARD_INDEX_PRECISION,
+      last_reward_epoch: 7,
+      min_withdraw: 10,
+      paused: true,
+      max_single_deposit: 500,
+      cooldown_exempt: Pubkey::new_unique(),
+      vault_bump: 254,
+      name: [b'v'; 32],
+      deposit_cap: 1_000_000,
+      withdrawal_fee_bps: 30,
+      fee_treasury: Pubkey::new_unique(),
+      vault_window_withdrawn: 5,
+      vault_window_start: 1_700_000_000,
+      max_vault_withdraw_per_window: 900,
+      user_count: 3,
+      lock_duration: 3_600,
+      fee_tiers: [FeeTier { threshold: 100, bps: 20 }; FEE_TIER_COUNT],
+      require_whitelisted: true,
+      reward_rate_per_second: 11,
+      reward_token_account: Pubkey::new_unique(),
+      admins: [Pubkey::new_unique(); ADMIN_COUNT],
+      admin_threshold: 2,
+      instruction_fee_lamports: 5_000,
+      instruction_fee_treasury: Pubkey::new_unique(),
+      config_frozen: true,
+    }
+  }
+
+  #[test]
+  fn vault_pack_round_trip() {
+    let vault = sample_vault();
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(vault, &mut data).unwrap();
+
+    assert_eq!(Vault::unpack(&data).unwrap(), vault);
+    assert_eq!(data[VAULT_TOTAL_DEPOSITS_OFFSET..VAULT_TOTAL_DEPOSITS_OFFSET + 8], 1_000u64.to_le_bytes());
+  }
+
+  #[test]
+  fn vault_unpack_short_slice_is_an_error() {
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(sample_vault(), &mut data).unwrap();
+
+    assert_eq!(Vault::unpack_from_slice(&data[..Vault::LEN - 1]), Err(ProgramError::InvalidAccountData));
+    assert_eq!(UserVault::unpack_from_slice(&[0u8; UserVault::LEN - 1]), Err(ProgramError::InvalidAccountData));
+  }
+
+  #[test]
+  fn load_checks_the_owner_but_unpack_only_the_bytes() {
+    let vault = sample_vault();
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(vault, &mut data).unwrap();
+
+    let (key, program_id, foreign_owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let mut lamports = 0;
+    let mut account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &foreign_owner, false, 0);
+    assert_eq!(Vault::load(&account, &program_id), Err(ProgramError::IllegalOwner));
+    assert_eq!(Vault::unpack(&account.try_borrow_data().unwrap()).unwrap(), vault);
+
+    account.owner = &program_id;
+    assert_eq!(Vault::load(&account, &program_id).unwrap(), vault);
+  }
+
+  #[test]
+  fn unpack_rejects_data_of_the_wrong_length() {
+    let mut data = vec![0u8; Vault::LEN + 1];
+    Vault::pack(sample_vault(), &mut data[..Vault::LEN]).unwrap();
+    assert_eq!(Vault::unpack(&data), Err(ProgramError::InvalidAccountData));
+    assert_eq!(Vault::unpack(&data[..Vault::LEN - 1]), Err(ProgramError::InvalidAccountData));
+
+    let user_vault = UserVault { is_initialized: true, ..UserVault::default() };
+    let mut data = vec![0u8; UserVault::LEN + 1];
+    UserVault::pack(user_vault, &mut data[..UserVault::LEN]).unwrap();
+    assert_eq!(UserVault::unpack(&data), Err(ProgramError::InvalidAccountData));
+    assert_eq!(UserVault::unpack(&data[..UserVault::LEN]).unwrap(), user_vault);
+  }
+
+  // An original-layout account: is_initialized, owner, token_mint, vault_token_account and nothing else
+  fn legacy_vault_data(owner: &Pubkey, mint: &Pubkey, vault_token_account: &Pubkey) -> [u8; VAULT_V1_LEN] {
+    let mut data = [0u8; VAULT_V1_LEN];
+    data[0] = 1;
+    data[1..33].copy_from_slice(owner.as_ref());
+    data[33..65].copy_from_slice(mint.as_ref());
+    data[65..97].copy_from_slice(vault_token_account.as_ref());
+    data
+  }
+
+  #[test]
+  fn unpack_accepts_both_layout_lengths() {
+    let (owner, mint, vault_token_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+    let legacy = Vault::unpack(&legacy_vault_data(&owner, &mint, &vault_token_account)).unwrap();
+    assert_eq!((legacy.owner, legacy.token_mint, legacy.vault_token_account), (owner, mint, vault_token_account));
+
+    let vault = sample_vault();
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(vault, &mut data).unwrap();
+    assert_eq!(Vault::unpack(&data).unwrap(), vault);
+
+    // Anything between the two layouts is neither
+    assert_eq!(Vault::unpack(&[1u8; VAULT_V1_LEN + 1]), Err(ProgramError::InvalidAccountData));
+  }
+
+  #[test]
+  fn legacy_v1_unpack_defaults_and_repacks_as_current() {
+    let (owner, mint, vault_token_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let legacy = Vault::unpack(&legacy_vault_data(&owner, &mint, &vault_token_account)).unwrap();
+
+    assert_eq!(legacy.version, VAULT_V1_VERSION);
+    assert!(legacy.is_initialized);
+    assert_eq!(legacy.allowed_token_program, spl_token::id());
+    assert_eq!(legacy.enabled_instructions, u64::MAX);
+    assert_eq!(legacy.last_reward_epoch, u64::MAX);
+    assert_eq!(legacy.total_deposits, 0);
+    assert_eq!(legacy.vault_bump, 0);
+
+    // Packing writes the current layout, which then reads back with the current version
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(legacy, &mut data).unwrap();
+    assert_eq!(data[VAULT_VERSION_OFFSET], VAULT_VERSION);
+    assert_eq!(Vault::unpack(&data).unwrap(), Vault { version: VAULT_VERSION, ..legacy });
+  }
+
+  #[test]
+  fn unknown_version_byte_is_rejected() {
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(sample_vault(), &mut data).unwrap();
+    data[VAULT_VERSION_OFFSET] = VAULT_VERSION + 1;
+    assert_eq!(Vault::unpack(&data), Err(ProgramError::InvalidAccountData));
+  }
+
+  #[test]
+  fn layout_offsets_end_at_len() {
+    assert_eq!(VAULT_CONFIG_FROZEN_OFFSET + 1, Vault::LEN);
+    assert_eq!(USER_VAULT_LAST_UPDATE_TS_OFFSET + 8, UserVault::LEN);
+  }
+
+  #[test]
+  fn layout_offsets_match_pack() {
+    let vault = sample_vault();
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(vault, &mut data).unwrap();
+
+    assert_eq!(data[VAULT_OWNER_OFFSET..VAULT_OWNER_OFFSET + 32], vault.owner.to_bytes());
+    assert_eq!(data[VAULT_PAUSED_OFFSET], 1);
+    assert_eq!(data[VAULT_USER_COUNT_OFFSET..VAULT_USER_COUNT_OFFSET + 8], vault.user_count.to_le_bytes());
+    assert_eq!(data[VAULT_ADMIN_THRESHOLD_OFFSET], vault.admin_threshold);
+    assert_eq!(data[VAULT_CONFIG_FROZEN_OFFSET], 1);
+
+    let user_vault = UserVault { deposited_amount: 77, locked_until: -5, ..UserVault::default() };
+    let mut data = [0u8; UserVault::LEN];
+    UserVault::pack(user_vault, &mut data).unwrap();
+
+    assert_eq!(data[USER_VAULT_DEPOSITED_AMOUNT_OFFSET..USER_VAULT_DEPOSITED_AMOUNT_OFFSET + 8], 77u64.to_le_bytes());
+    assert_eq!(data[USER_VAULT_LOCKED_UNTIL_OFFSET..USER_VAULT_LOCKED_UNTIL_OFFSET + 8], (-5i64).to_le_bytes());
+  }
+
+  #[test]
+  fn copies_compare_equal_until_changed() {
+    let vault = sample_vault();
+    let mut copy = vault;
+    assert_eq!(copy, vault);
+    copy.total_deposits += 1;
+    assert_ne!(copy, vault);
+    assert!(format!("{:?}", vault).contains("total_deposits: 1000"));
+
+    let user_vault = UserVault { deposited_amount: 9, ..UserVault::default() };
+    let mut copy = user_vault;
+    assert_eq!(copy, user_vault);
+    copy.deposited_amount = 0;
+    assert_ne!(copy, user_vault);
+  }
+
+  #[test]
+  fn default_is_the_all_zero_uninitialized_record() {
+    let vault = Vault::default();
+    assert!(!vault.is_initialized());
+    assert_eq!(vault.owner, Pubkey::default());
+    assert_eq!(vault.total_deposits, 0);
+    assert!(!vault.is_compressed());
+
+    let user_vault = UserVault::default();
+    assert!(!user_vault.is_initialized());
+    assert!(!user_vault.has_arbiter());
+
+    // A zeroed account and the default record are one and the same
+    assert_eq!(UserVault::unpack_unchecked(&[0u8; UserVault::LEN]).unwrap(), user_vault);
+    let mut data = [0xffu8; UserVault::LEN];
+    user_vault.pack_into_slice(&mut data);
+    assert_eq!(data, [0u8; UserVault::LEN]);
+  }
+
+  #[test]
+  fn accrue_rewards_leaves_rounding_dust_out_of_total_deposits() {
+    let mut vault = Vault { total_deposits: 3, ..Vault::default() };
+    vault.accrue_rewards(10).unwrap();
+
+    // 10 over 3 tokens grows each one to 4.33..; 9 is credited and the 10th token stays unowned in the token account
+    assert_eq!(vault.reward_index, 13 * REWARD_INDEX_PRECISION / 3);
+    assert_eq!(vault.total_deposits, 3 + 9);
+
+    let mut user_vault = UserVault { deposited_amount: 3, ..UserVault::default() };
+    user_vault.settle_rewards(vault.reward_index).unwrap();
+    assert_eq!(user_vault.deposited_amount, vault.total_deposits);
+
+    assert_eq!(Vault::default().accrue_rewards(10), Err(VaultError::NoDepositsToReward.into()));
+  }
+
+  #[test]
+  fn unsettled_rewards_compound_into_later_rounds() {
+    let mut vault = Vault { total_deposits: 400, ..Vault::default() };
+    vault.accrue_rewards(400).unwrap();
+    vault.accrue_rewards(200).unwrap();
+    assert_eq!(vault.total_deposits, 1_000);
+
+    // Neither position settled in between, yet together they're owed every credited token
+    let mut small = UserVault { deposited_amount: 100, ..UserVault::default() };
+    let mut large = UserVault { deposited_amount: 300, ..UserVault::default() };
+    small.settle_rewards(vault.reward_index).unwrap();
+    large.settle_rewards(vault.reward_index).unwrap();
+    assert_eq!((small.deposited_amount, large.deposited_amount), (250, 750));
+  }
+
+  #[test]
+  fn withdrawal_fee_rounds_down_to_at_least_one() {
+    let vault = Vault { withdrawal_fee_bps: 30, ..Vault::default() };
+    assert_eq!(vault.withdrawal_fee(10_000, 10_000).unwrap(), 30);
+    assert_eq!(vault.withdrawal_fee(10_333, 10_333).unwrap(), 30);
+    assert_eq!(vault.withdrawal_fee(333, 10_000).unwrap(), 1);
+    assert_eq!(vault.withdrawal_fee(1, 10_000).unwrap(), 1);
+    assert_eq!(vault.withdrawal_fee(0, 10_000).unwrap(), 0);
+    assert_eq!(Vault::default().withdrawal_fee(10_000, 10_000).unwrap(), 0);
+  }
+
+  #[test]
+  fn fee_tier_with_the_highest_reached_threshold_applies() {
+    let mut vault = Vault { withdrawal_fee_bps: 50, ..Vault::default() };
+    vault.fee_tiers[0] = FeeTier { threshold: 10_000, bps: 20 };
+    vault.fee_tiers[1] = FeeTier { threshold: 1_000, bps: 40 };
+
+    assert_eq!(vault.fee_bps_for(999), 50);
+    assert_eq!(vault.fee_bps_for(1_000), 40);
+    assert_eq!(vault.fee_bps_for(50_000), 20);
+  }
+
+  #[test]
+  fn withdrawals_lock_after_each_deposit() {
+    let user = Pubkey::new_unique();
+    let user_vault = UserVault { user, last_deposit_ts: 1_000, ..UserVault::default() };
+    let mut vault = Vault { lock_duration: 100, ..Vault::default() };
+
+    assert!(vault.is_withdraw_locked(&user_vault, 1_099));
+    assert!(!vault.is_withdraw_locked(&user_vault, 1_100));
+    assert_eq!(vault.unlocks_at(&user_vault), 1_100);
+
+    // The exempt key skips the lock, but not a vesting date of its own
+    vault.cooldown_exempt = user;
+    assert!(!vault.is_withdraw_locked(&user_vault, 1_000));
+    assert!(vault.is_withdraw_locked(&UserVault { locked_until: 2_000, ..user_vault }, 1_500));
+  }
+
+  #[test]
+  fn window_cap_refuses_past_the_allowance_and_rolls_over() {
+    let mut vault = Vault { max_vault_withdraw_per_window: 100, vault_window_start: 0, ..Vault::default() };
+
+    vault.record_window_withdrawal(60, 10).unwrap();
+    assert_eq!(vault.window_withdraw_remaining(10), 40);
+    assert_eq!(vault.record_window_withdrawal(41, 20), Err(VaultError::VaultRateLimited.into()));
+
+    let next_window = VAULT_WITHDRAW_WINDOW_SECS;
+    assert_eq!(vault.window_withdraw_remaining(next_window), 100);
+    vault.record_window_withdrawal(100, next_window).unwrap();
+    assert_eq!(vault.vault_window_start, next_window);
+  }
+
+  #[test]
+  fn in_place_deposit_updates_match_pack() {
+    let vault = sample_vault();
+    let mut data = [0u8; Vault::LEN];
+    Vault::pack(vault, &mut data).unwrap();
+
+    Vault::add_deposits(&mut data, 25).unwrap();
+    Vault::sub_deposits(&mut data, 5).unwrap();
+    assert_eq!(Vault::unpack(&data).unwrap(), Vault { total_deposits: vault.total_deposits + 20, ..vault });
+
+    assert_eq!(Vault::sub_deposits(&mut data, u64::MAX), Err(ProgramError::InsufficientFunds));
+    assert_eq!(Vault::add_deposits(&mut data[..VAULT_V1_LEN], 1), Err(ProgramError::InvalidAccountData));
+  }
+
+  #[cfg(feature = "borsh")]
+  #[test]
+  fn borsh_encoding_matches_pack() {
+    let vault = sample_vault();
+    let mut packed = [0u8; Vault::LEN];
+    Vault::pack(vault, &mut packed).unwrap();
+
+    let mut encoded = [0u8; Vault::LEN];
+    pack_borsh(&vault, &mut encoded).unwrap();
+    assert_eq!(encoded, packed);
+    assert_eq!(unpack_borsh::<Vault>(&packed).unwrap(), vault);
   }
 }
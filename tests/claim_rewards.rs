@@ -0,0 +1,75 @@
+// ClaimRewards pays deposited_amount * rate * elapsed out of the vault's reward token account
+mod common;
+
+use common::{custom, program_id, vault_ix, Env, Setup, User, USER_TOKENS};
+use safe::{error::VaultError, instruction::VaultInstruction, pda, state::REWARD_RATE_PRECISION};
+use solana_program::{clock::Clock, pubkey::Pubkey};
+use solana_sdk::instruction::Instruction;
+
+const RATE: u64 = (REWARD_RATE_PRECISION / 1_000) as u64;           // 0.001 tokens per deposited token per second
+const REWARD_FUND: u64 = 10_000;
+
+fn claim_ix(env: &Env, user: &User, reward_token_account: Pubkey) -> Instruction {
+  vault_ix(
+    VaultInstruction::ClaimRewards,
+    &[
+      user.key(),
+      env.vault_state,
+      env.user_vault_key(user),
+      reward_token_account,
+      user.token_account,
+      pda::reward_authority(&program_id(), &env.vault_state).0,
+      spl_token::id(),
+      env.mint,
+    ],
+  )
+}
+
+async fn advance_clock(env: &Env, seconds: i64) {
+  let mut clock: Clock = env.context.banks_client.clone().get_sysvar().await.unwrap();
+  clock.unix_timestamp += seconds;
+  env.context.set_sysvar(&clock);
+}
+
+// A vault paying RATE out of a reward account funded with REWARD_FUND of the vault's own mint
+async fn start() -> (Env, Pubkey) {
+  let mut setup = Setup::new(2);
+  let reward_token_account = Pubkey::new_unique();
+  let reward_authority = pda::reward_authority(&program_id(), &setup.vault_state).0;
+  common::add_token_account(&mut setup.program_test, reward_token_account, setup.mint, reward_authority, REWARD_FUND);
+  let env = setup.start_with_vault().await;
+
+  let owner = env.owner();
+  let set_rate = vault_ix(
+    VaultInstruction::SetRewardRate { reward_rate_per_second: RATE },
+    &[owner.key(), env.vault_state, reward_token_account],
+  );
+  env.send(&[set_rate], &[&owner.keypair]).await.unwrap();
+  (env, reward_token_account)
+}
+
+#[tokio::test]
+async fn pays_what_accrued_over_the_interval() {
+  let (env, reward_token_account) = start().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+
+  advance_clock(&env, 100).await;
+  env.send(&[claim_ix(&env, user, reward_token_account)], &[&user.keypair]).await.unwrap();
+
+  // 1_000 tokens * 0.001 per second * 100 seconds
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 1_000 + 100);
+  assert_eq!(env.token_balance(reward_token_account).await, REWARD_FUND - 100);
+  assert_eq!(env.user_vault(user).await.reward_debt, 0);
+  assert_eq!(env.user_vault(user).await.deposited_amount, 1_000);       // The principal stays deposited
+}
+
+#[tokio::test]
+async fn nothing_to_claim_without_elapsed_time() {
+  let (env, reward_token_account) = start().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+
+  let err = env.send(&[claim_ix(&env, user, reward_token_account)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::AmountZero));
+}
@@ -0,0 +1,70 @@
+// CloseUserVault hands an empty record's rent back to its user and refuses while the position still holds anything
+mod common;
+
+use common::{custom, program_id, Env, User};
+use safe::{error::VaultError, instruction};
+use solana_sdk::instruction::Instruction;
+
+fn close_user_vault_ix(env: &Env, user: &User) -> Instruction {
+  instruction::close_user_vault(&program_id(), &user.key(), &env.vault_state, None)
+}
+
+#[tokio::test]
+async fn refuses_a_position_with_a_balance() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  let err = env.send(&[close_user_vault_ix(&env, user)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::PositionNotEmpty));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+  assert_eq!(env.vault().await.user_count, 1);
+}
+
+#[tokio::test]
+async fn reclaims_the_rent_of_an_empty_position() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  let user_vault = env.user_vault_key(user);
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  let rent = env.account(user_vault).await.unwrap().lamports;
+  let before = env.account(user.key()).await.unwrap().lamports;
+  env.send(&[close_user_vault_ix(&env, user)], &[&user.keypair]).await.unwrap();
+
+  // The context payer covers the fee, so the user gets the rent back in full
+  assert_eq!(env.account(user.key()).await.unwrap().lamports, before + rent);
+  assert!(env.account(user_vault).await.is_none());
+  assert_eq!(env.vault().await.user_count, 0);
+
+  // Depositing again opens a fresh record
+  env.send(&[env.deposit_ix(user, 50)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 50);
+  assert_eq!(env.vault().await.user_count, 1);
+}
+
+#[tokio::test]
+async fn owner_closes_an_empty_record_for_the_user() {
+  let env = common::Setup::new(3).start_with_vault().await;
+  let (owner, user, stranger) = (env.owner(), &env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  // Neither a stranger nor the owner can close a record that still holds a balance
+  let ix = instruction::close_user_vault(&program_id(), &user.key(), &env.vault_state, Some(&stranger.key()));
+  let err = env.send(&[ix], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  let ix = instruction::close_user_vault(&program_id(), &user.key(), &env.vault_state, Some(&owner.key()));
+  let err = env.send(std::slice::from_ref(&ix), &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::PositionNotEmpty));
+
+  // Emptied by the user and closed by the owner, in one transaction so it isn't a replay of the refused one
+  let rent = env.account(env.user_vault_key(user)).await.unwrap().lamports;
+  let before = env.account(user.key()).await.unwrap().lamports;
+  env.send(&[env.withdraw_ix(user, 100), ix], &[&user.keypair, &owner.keypair]).await.unwrap();
+
+  // The rent goes to the user, not the owner who signed
+  assert_eq!(env.account(user.key()).await.unwrap().lamports, before + rent);
+  assert!(env.account(env.user_vault_key(user)).await.is_none());
+  assert_eq!(env.vault().await.user_count, 0);
+}
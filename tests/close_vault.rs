@@ -0,0 +1,79 @@
+// CloseVault reclaims an empty vault's rent for its owner, and refuses while anything is still recorded against it
+mod common;
+
+use common::{custom, instruction_error, program_id, vault_ix, Env, User};
+use safe::{error::VaultError, instruction::{self, VaultInstruction}};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::instruction::{Instruction, InstructionError};
+
+fn close_vault_ix(env: &Env, signer: &User, destination: Pubkey) -> Instruction {
+  vault_ix(VaultInstruction::CloseVault, &[signer.key(), env.vault_state, destination])
+}
+
+#[tokio::test]
+async fn closes_an_empty_vault() {
+  let env = common::Setup::new(1).start_with_vault().await;
+  let owner = env.owner();
+  let destination = Pubkey::new_unique();
+  let rent = env.account(env.vault_state).await.unwrap().lamports;
+
+  env.send(&[close_vault_ix(&env, owner, destination)], &[&owner.keypair]).await.unwrap();
+
+  assert!(env.account(env.vault_state).await.is_none());
+  assert_eq!(env.account(destination).await.unwrap().lamports, rent);
+}
+
+#[tokio::test]
+async fn refuses_while_deposits_or_records_remain() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  let refused = instruction_error(InstructionError::InvalidAccountData);
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  // Each attempt pays out to a fresh destination, so a retry is never a replay of the refused transaction
+  let err = env.send(&[close_vault_ix(&env, owner, Pubkey::new_unique())], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, refused);
+
+  // Withdrawing everything leaves the empty record open, which still holds the vault
+  env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.total_deposits, 0);
+  let err = env.send(&[close_vault_ix(&env, owner, Pubkey::new_unique())], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, refused);
+
+  let close_record = instruction::close_user_vault(&program_id(), &user.key(), &env.vault_state, None);
+  env.send(&[close_record], &[&user.keypair]).await.unwrap();
+  env.send(&[close_vault_ix(&env, owner, owner.key())], &[&owner.keypair]).await.unwrap();
+  assert!(env.account(env.vault_state).await.is_none());
+}
+
+#[tokio::test]
+async fn refuses_a_non_owner() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let stranger = &env.users[1];
+
+  let err = env.send(&[close_vault_ix(&env, stranger, stranger.key())], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  assert!(env.account(env.vault_state).await.is_some());
+}
+
+#[tokio::test]
+async fn owner_clears_a_record_a_stranger_opened_then_closes() {
+  let env = common::Setup::new(3).start_with_vault().await;
+  let (owner, user, stranger) = (env.owner(), &env.users[1], &env.users[2]);
+
+  // A third party opens a record for someone who never deposited, at their own expense
+  let ensure = vault_ix(
+    VaultInstruction::EnsureUserVault,
+    &[stranger.key(), user.key(), env.user_vault_key(user), env.vault_state, solana_program::system_program::id()],
+  );
+  env.send(&[ensure], &[&stranger.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.user_count, 1);
+
+  let err = env.send(&[close_vault_ix(&env, owner, owner.key())], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+
+  // The owner doesn't need the user to clear the empty record
+  let close_record = instruction::close_user_vault(&program_id(), &user.key(), &env.vault_state, Some(&owner.key()));
+  env.send(&[close_record, close_vault_ix(&env, owner, owner.key())], &[&owner.keypair]).await.unwrap();
+  assert!(env.account(env.vault_state).await.is_none());
+}
@@ -0,0 +1,274 @@
+// Shared setup for the solana-program-test integration tests: a bank with the vault program, one mint, a vault over it
+// and funded users. Token accounts are written straight into genesis instead of going through the token program.
+#![allow(dead_code)]                                      // Each test binary uses its own subset of the helpers
+
+use safe::{
+  error::VaultError,
+  instruction::{self, VaultInstruction},
+  pda,
+  state::{UserVault, Vault},
+};
+use solana_program::{program_option::COption, program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+  account::Account,
+  instruction::{Instruction, InstructionError},
+  signature::{Keypair, Signer},
+  transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+pub const DECIMALS: u8 = 6;
+pub const USER_TOKENS: u64 = 1_000_000;                     // Starting token balance of every funded user
+const USER_LAMPORTS: u64 = 10_000_000_000;                  // Enough SOL for fees and user vault rent
+
+pub fn program_id() -> Pubkey {
+  Pubkey::new_from_array([7; 32])
+}
+
+// The error a transaction fails with when its first instruction returns `error`
+pub fn custom(error: VaultError) -> TransactionError {
+  TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+// The error a transaction fails with when its first instruction returns a builtin `error`
+pub fn instruction_error(error: InstructionError) -> TransactionError {
+  TransactionError::InstructionError(0, error)
+}
+
+// An instruction for `ix` with its account metas taken from account_flags, keys in documented order
+pub fn vault_ix(ix: VaultInstruction, keys: &[Pubkey]) -> Instruction {
+  Instruction { program_id: program_id(), accounts: instruction::required_accounts(&ix, keys), data: ix.pack() }
+}
+
+// A user with SOL and a token account holding USER_TOKENS of the test mint
+pub struct User {
+  pub keypair: Keypair,
+  pub token_account: Pubkey,
+}
+
+impl User {
+  pub fn key(&self) -> Pubkey {
+    self.keypair.pubkey()
+  }
+}
+
+// Everything a test needs before the vault is initialized; the owner is users[0]
+pub struct Setup {
+  pub program_test: ProgramTest,
+  pub mint: Pubkey,
+  pub vault_token_account: Pubkey,
+  pub vault_state: Pubkey,
+  pub users: Vec<User>,
+}
+
+impl Setup {
+  // A bank with the program loaded natively (or from SBF_OUT_DIR when set), the mint, an empty vault token account owned
+  // by the vault authority PDA and `user_count` funded users
+  pub fn new(user_count: usize) -> Self {
+    Setup::with_mint(user_count, Pubkey::new_unique())
+  }
+
+  // Like new, over the given mint; with the native mint every token account is a funded wrapped-SOL account
+  pub fn with_mint(user_count: usize, mint: Pubkey) -> Self {
+    let mut program_test = ProgramTest::new("safe", program_id(), processor!(safe::processor::process_instruction));
+    let vault_token_account = Pubkey::new_unique();
+    let (vault_authority, _bump) = pda::vault_authority(&program_id());
+
+    add_mint(&mut program_test, mint, USER_TOKENS * user_count as u64);
+    add_token_account(&mut program_test, vault_token_account, mint, vault_authority, 0);
+
+    let users: Vec<User> = (0..user_count)
+      .map(|_| {
+        let keypair = Keypair::new();
+        let token_account = Pubkey::new_unique();
+        program_test.add_account(keypair.pubkey(), Account::new(USER_LAMPORTS, 0, &solana_program::system_program::id()));
+        add_token_account(&mut program_test, token_account, mint, keypair.pubkey(), USER_TOKENS);
+        User { keypair, token_account }
+      })
+      .collect();
+
+    let (vault_state, _bump) = pda::vault_state(&program_id(), &users[0].key(), &mint);
+
+    Setup { program_test, mint, vault_token_account, vault_state, users }
+  }
+
+  // Start the bank without initializing the vault, for tests that create it themselves
+  pub async fn start(self) -> Env {
+    let Setup { program_test, mint, vault_token_account, vault_state, users } = self;
+    Env { context: program_test.start_with_context().await, mint, vault_token_account, vault_state, users }
+  }
+
+  // Start the bank and initialize the vault with no name, cap, fee or lock
+  pub async fn start_with_vault(self) -> Env {
+    self.start_with_fee(0, Pubkey::default()).await
+  }
+
+  // Like start_with_vault, but withdrawals by anyone other than the owner pay `withdrawal_fee_bps` into `fee_treasury`
+  pub async fn start_with_fee(self, withdrawal_fee_bps: u16, fee_treasury: Pubkey) -> Env {
+    self.start_with_config(0, withdrawal_fee_bps, fee_treasury, 0).await
+  }
+
+  // Like start_with_fee, with total deposits capped at `deposit_cap` (0 for none) and deposits locked for
+  // `lock_duration` seconds
+  pub async fn start_with_config(
+    self,
+    deposit_cap: u64,
+    withdrawal_fee_bps: u16,
+    fee_treasury: Pubkey,
+    lock_duration: i64,
+  ) -> Env {
+    let env = self.start().await;
+
+    let init = instruction::init_vault(
+      &program_id(),
+      &env.users[0].key(),
+      &env.mint,
+      &env.vault_token_account,
+      &spl_token::id(),
+      [0; 32],
+      deposit_cap,
+      withdrawal_fee_bps,
+      &fee_treasury,
+      lock_duration,
+    );
+    env.send(&[init], &[&env.users[0].keypair]).await.unwrap();
+    env
+  }
+}
+
+// A running bank with an initialized vault
+pub struct Env {
+  pub context: ProgramTestContext,
+  pub mint: Pubkey,
+  pub vault_token_account: Pubkey,
+  pub vault_state: Pubkey,
+  pub users: Vec<User>,
+}
+
+impl Env {
+  pub fn owner(&self) -> &User {
+    &self.users[0]
+  }
+
+  // Run an owner-only instruction whose accounts are just the owner and the vault state
+  pub async fn configure(&self, ix: VaultInstruction) {
+    let owner = self.owner();
+    self.send(&[vault_ix(ix, &[owner.key(), self.vault_state])], &[&owner.keypair]).await.unwrap();
+  }
+
+  // Sign with the context payer plus `signers` and process the transaction
+  pub async fn send(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), BanksClientError> {
+    let transaction = self.transaction(instructions, signers).await;
+    self.context.banks_client.clone().process_transaction(transaction).await
+  }
+
+  pub async fn transaction(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Transaction {
+    let blockhash = self.context.banks_client.clone().get_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&self.context.payer];
+    all_signers.extend_from_slice(signers);
+    Transaction::new_signed_with_payer(instructions, Some(&self.context.payer.pubkey()), &all_signers, blockhash)
+  }
+
+  pub fn deposit_ix(&self, user: &User, amount: u64) -> Instruction {
+    instruction::deposit(
+      &program_id(),
+      amount,
+      &user.key(),
+      &user.token_account,
+      &self.vault_token_account,
+      &self.vault_state,
+      &spl_token::id(),
+      &self.mint,
+      false,
+      None,
+    )
+  }
+
+  pub fn withdraw_ix(&self, user: &User, amount: u64) -> Instruction {
+    instruction::withdraw(
+      &program_id(),
+      amount,
+      &user.key(),
+      &self.vault_token_account,
+      &user.token_account,
+      &self.vault_state,
+      &spl_token::id(),
+      &self.mint,
+      None,
+      None,
+    )
+  }
+
+  // Simulate `ix` and return what it handed back, read from the return data or, without the return-data feature,
+  // from the hex-encoded log line
+  pub async fn return_data(&self, ix: Instruction) -> Vec<u8> {
+    self.signed_return_data(ix, &[]).await
+  }
+
+  // Like return_data, for an instruction that needs `signers`
+  pub async fn signed_return_data(&self, ix: Instruction, signers: &[&Keypair]) -> Vec<u8> {
+    let transaction = self.transaction(&[ix], signers).await;
+    let simulation = self.context.banks_client.clone().simulate_transaction(transaction).await.unwrap();
+    simulation.result.unwrap().unwrap();
+    let details = simulation.simulation_details.unwrap();
+    if let Some(return_data) = details.return_data {
+      return return_data.data;
+    }
+    let hex = details.logs.iter().find_map(|log| log.strip_prefix("Program log: return-data: ")).unwrap();
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+  }
+
+  pub async fn account(&self, address: Pubkey) -> Option<Account> {
+    self.context.banks_client.clone().get_account(address).await.unwrap()
+  }
+
+  pub async fn vault(&self) -> Vault {
+    Vault::unpack(&self.account(self.vault_state).await.unwrap().data).unwrap()
+  }
+
+  pub fn user_vault_key(&self, user: &User) -> Pubkey {
+    pda::user_vault(&program_id(), &user.key(), &self.vault_state).0
+  }
+
+  pub async fn user_vault(&self, user: &User) -> UserVault {
+    UserVault::unpack(&self.account(self.user_vault_key(user)).await.unwrap().data).unwrap()
+  }
+
+  pub async fn token_balance(&self, token_account: Pubkey) -> u64 {
+    TokenAccount::unpack(&self.account(token_account).await.unwrap().data).unwrap().amount
+  }
+}
+
+fn add_packed<T: Pack>(program_test: &mut ProgramTest, address: Pubkey, owner: Pubkey, value: T) {
+  let mut data = vec![0u8; T::LEN];
+  T::pack(value, &mut data).unwrap();
+  program_test.add_account(
+    address,
+    Account { lamports: 1_000_000_000, data, owner, executable: false, rent_epoch: 0 },
+  );
+}
+
+pub fn add_mint(program_test: &mut ProgramTest, address: Pubkey, supply: u64) {
+  add_packed(
+    program_test,
+    address,
+    spl_token::id(),
+    Mint { mint_authority: COption::None, supply, decimals: DECIMALS, is_initialized: true, freeze_authority: COption::None },
+  );
+}
+
+pub fn add_token_account(program_test: &mut ProgramTest, address: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) {
+  let mut token = TokenAccount { mint, owner, amount, state: AccountState::Initialized, ..TokenAccount::default() };
+  if mint != spl_token::native_mint::id() {
+    add_packed(program_test, address, spl_token::id(), token);
+    return;
+  }
+
+  // A wrapped-SOL account holds its tokens as lamports on top of its rent reserve
+  let reserve = Rent::default().minimum_balance(TokenAccount::LEN);
+  token.is_native = COption::Some(reserve);
+  let mut data = vec![0u8; TokenAccount::LEN];
+  TokenAccount::pack(token, &mut data).unwrap();
+  program_test.add_account(address, Account { lamports: reserve + amount, data, owner: spl_token::id(), ..Account::default() });
+}
@@ -0,0 +1,36 @@
+// CompressedDeposit stays unavailable until compressed balances can be withdrawn, whatever the owner enables
+mod common;
+
+use common::{custom, vault_ix};
+use safe::{compression, error::VaultError, instruction::VaultInstruction, state};
+use solana_program::pubkey::Pubkey;
+
+#[tokio::test]
+async fn is_refused_even_with_every_bit_enabled() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  assert_eq!(env.vault().await.enabled_instructions & state::UNAVAILABLE_INSTRUCTIONS, 0);
+
+  let enable_all = VaultInstruction::SetEnabledInstructions { enabled_instructions: u64::MAX };
+  let enable_all = vault_ix(enable_all, &[owner.key(), env.vault_state]);
+  env.send(&[enable_all], &[&owner.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.enabled_instructions, !state::UNAVAILABLE_INSTRUCTIONS);
+
+  let ix = vault_ix(
+    VaultInstruction::CompressedDeposit { amount: 100, previous_balance: 0, index: 0, root: [0; 32] },
+    &[
+      user.key(),
+      user.token_account,
+      env.vault_token_account,
+      env.vault_state,
+      Pubkey::new_unique(),                                       // Merkle tree, never reached
+      compression::find_tree_authority(&env.vault_state, &common::program_id()).0,
+      compression::NOOP_ID,
+      compression::ACCOUNT_COMPRESSION_ID,
+      spl_token::id(),
+      env.mint,
+    ],
+  );
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::InstructionDisabled));
+}
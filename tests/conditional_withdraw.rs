@@ -0,0 +1,155 @@
+// A position with an arbiter only pays out through RequestConditionalWithdraw followed by the arbiter's release. The
+// release pays the same fee as a plain Withdraw, so naming one's own key as arbiter is no way around it
+mod common;
+
+use common::{custom, instruction_error, program_id, vault_ix, Env, User, USER_TOKENS};
+use safe::{error::VaultError, instruction::VaultInstruction, pda};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+
+const FEE_BPS: u16 = 100;                                     // 1%
+
+fn set_arbiter_ix(env: &Env, user: &User, arbiter: Pubkey) -> Instruction {
+  vault_ix(VaultInstruction::SetArbiter { arbiter }, &[user.key(), env.user_vault_key(user), env.vault_state])
+}
+
+fn request_ix(env: &Env, user: &User, amount: u64) -> Instruction {
+  vault_ix(
+    VaultInstruction::RequestConditionalWithdraw { amount },
+    &[user.key(), env.user_vault_key(user), env.vault_state],
+  )
+}
+
+fn release_ix(env: &Env, arbiter: &User, user: &User, fee_treasury: Option<Pubkey>) -> Instruction {
+  let mut keys = vec![
+    arbiter.key(),
+    env.vault_token_account,
+    user.token_account,
+    env.vault_state,
+    env.user_vault_key(user),
+    spl_token::id(),
+    env.mint,
+    pda::vault_authority(&program_id()).0,
+  ];
+  keys.extend(fee_treasury);
+  vault_ix(VaultInstruction::ConditionalWithdraw, &keys)
+}
+
+async fn start() -> (Env, Pubkey) {
+  let mut setup = common::Setup::new(3);
+  let fee_treasury = Pubkey::new_unique();
+  common::add_token_account(&mut setup.program_test, fee_treasury, setup.mint, Pubkey::new_unique(), 0);
+  (setup.start_with_fee(FEE_BPS, fee_treasury).await, fee_treasury)
+}
+
+#[tokio::test]
+async fn release_charges_the_withdrawal_fee() {
+  let (env, fee_treasury) = start().await;
+  let user = &env.users[1];
+  let arbiter = &env.users[2];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  env.send(&[set_arbiter_ix(&env, user, arbiter.key()), request_ix(&env, user, 500)], &[&user.keypair]).await.unwrap();
+
+  // Without the treasury the instruction runs out of accounts instead of skipping the fee
+  let err = env.send(&[release_ix(&env, arbiter, user, None)], &[&arbiter.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::NotEnoughAccountKeys));
+  assert_eq!(env.user_vault(user).await.pending_withdrawal, 500);
+
+  env.send(&[release_ix(&env, arbiter, user, Some(fee_treasury))], &[&arbiter.keypair]).await.unwrap();
+
+  assert_eq!(env.token_balance(fee_treasury).await, 5);
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 1_000 + 495);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 500);
+  assert_eq!(env.vault().await.total_deposits, 500);
+  let user_vault = env.user_vault(user).await;
+  assert_eq!(user_vault.deposited_amount, 500);
+  assert_eq!(user_vault.pending_withdrawal, 0);
+}
+
+#[tokio::test]
+async fn release_refuses_a_foreign_fee_treasury() {
+  let (env, _fee_treasury) = start().await;
+  let user = &env.users[1];
+  let arbiter = &env.users[2];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  env.send(&[set_arbiter_ix(&env, user, arbiter.key()), request_ix(&env, user, 500)], &[&user.keypair]).await.unwrap();
+
+  // The arbiter's own token account in place of the vault's treasury
+  let ix = release_ix(&env, arbiter, user, Some(arbiter.token_account));
+  let err = env.send(&[ix], &[&arbiter.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+
+  assert_eq!(env.token_balance(arbiter.token_account).await, USER_TOKENS);
+  assert_eq!(env.user_vault(user).await.pending_withdrawal, 500);
+  assert_eq!(env.vault().await.total_deposits, 1_000);
+}
+
+#[tokio::test]
+async fn request_then_release() {
+  let (env, _fee_treasury) = start().await;
+  let (owner, arbiter) = (env.owner(), &env.users[2]);             // The owner pays no fee, so no treasury is needed
+  env.send(&[env.deposit_ix(owner, 1_000)], &[&owner.keypair]).await.unwrap();
+  env.send(&[set_arbiter_ix(&env, owner, arbiter.key()), request_ix(&env, owner, 400)], &[&owner.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(owner).await.pending_withdrawal, 400);
+
+  env.send(&[release_ix(&env, arbiter, owner, None)], &[&arbiter.keypair]).await.unwrap();
+
+  assert_eq!(env.token_balance(owner.token_account).await, USER_TOKENS - 600);
+  assert_eq!(env.user_vault(owner).await.deposited_amount, 600);
+  assert_eq!(env.user_vault(owner).await.pending_withdrawal, 0);
+}
+
+#[tokio::test]
+async fn release_needs_a_pending_request() {
+  let (env, fee_treasury) = start().await;
+  let (user, arbiter) = (&env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  env.send(&[set_arbiter_ix(&env, user, arbiter.key())], &[&user.keypair]).await.unwrap();
+
+  let err = env.send(&[release_ix(&env, arbiter, user, Some(fee_treasury))], &[&arbiter.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::NoPendingWithdrawal));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 1_000);
+}
+
+#[tokio::test]
+async fn only_the_arbiter_can_release() {
+  let (env, fee_treasury) = start().await;
+  let (owner, user, arbiter) = (env.owner(), &env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  env.send(&[set_arbiter_ix(&env, user, arbiter.key()), request_ix(&env, user, 500)], &[&user.keypair]).await.unwrap();
+
+  // Neither the user themselves nor the vault owner stands in for the arbiter
+  for signer in [user, owner] {
+    let err = env.send(&[release_ix(&env, signer, user, Some(fee_treasury))], &[&signer.keypair]).await.unwrap_err().unwrap();
+    assert_eq!(err, custom(VaultError::Unauthorized));
+  }
+  assert_eq!(env.user_vault(user).await.pending_withdrawal, 500);
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 1_000);
+}
+
+#[tokio::test]
+async fn plain_withdraw_is_refused_on_an_arbiter_held_position() {
+  let (env, _fee_treasury) = start().await;
+  let (user, arbiter) = (&env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  env.send(&[set_arbiter_ix(&env, user, arbiter.key())], &[&user.keypair]).await.unwrap();
+
+  let err = env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::ArbiterRequired));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 1_000);
+}
+
+#[tokio::test]
+async fn one_key_cannot_sign_as_both_user_and_arbiter() {
+  let (env, _) = start().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  env.send(&[set_arbiter_ix(&env, user, user.key())], &[&user.keypair]).await.unwrap();
+
+  // The user is their own arbiter, but the two signer roles of SetArbiter still need two different keys
+  let mut ix = set_arbiter_ix(&env, user, Pubkey::default());
+  ix.accounts.push(AccountMeta::new_readonly(user.key(), true));
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::DuplicateSigner));
+  assert_eq!(env.user_vault(user).await.arbiter, user.key());
+}
@@ -0,0 +1,237 @@
+// Deposit creates the depositor's user vault on first use and credits it, within the vault's limits, logging any attached memo
+mod common;
+
+use common::{custom, instruction_error, program_id, Setup, USER_TOKENS};
+use safe::{error::VaultError, instruction::{self, VaultInstruction}, state::UserVault};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{
+  account::{Account, AccountSharedData},
+  instruction::{AccountMeta, InstructionError},
+};
+use spl_token::state::Account as TokenAccount;
+
+#[tokio::test]
+async fn first_deposit_creates_the_user_vault() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  assert!(env.account(env.user_vault_key(user)).await.is_none());
+
+  env.send(&[env.deposit_ix(user, 250)], &[&user.keypair]).await.unwrap();
+
+  let account = env.account(env.user_vault_key(user)).await.unwrap();
+  assert_eq!(account.owner, program_id());
+  assert_eq!(account.data.len(), UserVault::LEN);
+  let rent = env.context.banks_client.clone().get_rent().await.unwrap();
+  assert!(rent.is_exempt(account.lamports, account.data.len()));
+
+  let user_vault = env.user_vault(user).await;
+  assert!(user_vault.is_initialized);
+  assert_eq!(user_vault.user, user.key());
+  assert_eq!(user_vault.vault, env.vault_state);
+  assert_eq!(user_vault.deposited_amount, 250);
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 250);
+}
+
+#[tokio::test]
+async fn deposit_cap_boundary() {
+  let env = Setup::new(3).start_with_config(1_000, 0, Pubkey::default(), 0).await;
+  let (user, other) = (&env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(user, 600)], &[&user.keypair]).await.unwrap();
+
+  // The cap is on the vault's total, whoever deposits
+  let err = env.send(&[env.deposit_ix(other, 401)], &[&other.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::CapExceeded));
+  env.send(&[env.deposit_ix(other, 400)], &[&other.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.total_deposits, 1_000);
+
+  let err = env.send(&[env.deposit_ix(user, 1)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::CapExceeded));
+}
+
+#[tokio::test]
+async fn read_only_vault_token_account_is_refused_up_front() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  // account_flags marks the vault token account writable, so the spec check fails before any transfer is attempted
+  let mut ix = env.deposit_ix(user, 100);
+  let meta = ix.accounts.iter_mut().find(|meta| meta.pubkey == env.vault_token_account).unwrap();
+  meta.is_writable = false;
+
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::AccountNotWritable));
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+  assert_eq!(env.vault().await.total_deposits, 0);
+}
+
+#[tokio::test]
+async fn failed_transfer_after_creation_leaves_no_user_vault() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  // The user vault is created and packed before the token transfer, which then fails on an overdrawn balance;
+  // the whole instruction reverts, so neither a half-written record nor the user count survives
+  assert!(env.send(&[env.deposit_ix(user, USER_TOKENS + 1)], &[&user.keypair]).await.is_err());
+  assert!(env.account(env.user_vault_key(user)).await.is_none());
+  assert_eq!(env.vault().await.user_count, 0);
+
+  env.send(&[env.deposit_ix(user, 10)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 10);
+}
+
+#[tokio::test]
+async fn created_but_unpacked_user_vault_is_treated_as_fresh() {
+  let mut setup = Setup::new(2);
+  let user_key = setup.users[1].key();
+  let (user_vault, _) = safe::pda::user_vault(&program_id(), &user_key, &setup.vault_state);
+  // Allocated and owned by the program, but the data is still the zeroes the system program left
+  setup.program_test.add_account(
+    user_vault,
+    Account { lamports: 1_000_000_000, data: vec![0; UserVault::LEN], owner: program_id(), ..Account::default() },
+  );
+  let env = setup.start_with_vault().await;
+  let user = &env.users[1];
+
+  env.send(&[env.deposit_ix(user, 10)], &[&user.keypair]).await.unwrap();
+  let record = env.user_vault(user).await;
+  assert!(record.is_initialized);
+  assert_eq!((record.user, record.vault, record.deposited_amount), (user_key, env.vault_state, 10));
+}
+
+#[tokio::test]
+async fn deposit_with_a_memo_logs_it_through_the_memo_program() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  let mut ix = env.deposit_ix(user, 100);
+  ix.data = VaultInstruction::Deposit { amount: 100, memo: Some("invoice 42".to_string()) }.pack();
+  ix.accounts.push(AccountMeta::new_readonly(spl_memo::id(), false));
+
+  let transaction = env.transaction(&[ix.clone()], &[&user.keypair]).await;
+  let simulation = env.context.banks_client.clone().simulate_transaction(transaction).await.unwrap();
+  assert!(simulation.result.unwrap().is_ok());
+  let logs = simulation.simulation_details.unwrap().logs;
+  assert!(logs.contains(&format!("Program {} invoke [2]", spl_memo::id())));
+  assert!(logs.iter().any(|log| log.contains("invoice 42")));
+
+  env.send(&[ix], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
+
+#[tokio::test]
+async fn memo_needs_the_memo_program() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  let mut ix = env.deposit_ix(user, 100);
+  ix.data = VaultInstruction::Deposit { amount: 100, memo: Some("invoice 42".to_string()) }.pack();
+  ix.accounts.push(AccountMeta::new_readonly(Pubkey::new_unique(), false));
+
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IncorrectProgramId));
+  assert!(env.account(env.user_vault_key(user)).await.is_none());
+}
+
+#[tokio::test]
+async fn zero_amount_is_refused() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  let err = env.send(&[env.deposit_ix(user, 0)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::AmountZero));
+  // Not even an empty user vault record is left behind
+  assert!(env.account(env.user_vault_key(user)).await.is_none());
+}
+
+#[tokio::test]
+async fn vault_token_account_moved_off_the_vault_authority_is_refused() {
+  let mut env = Setup::new(2).start_with_vault().await;
+  // Re-checked on every deposit, in case the token authority was ever handed to another key after init
+  let mut account = env.account(env.vault_token_account).await.unwrap();
+  let mut token = TokenAccount::unpack(&account.data).unwrap();
+  token.owner = Pubkey::new_unique();
+  TokenAccount::pack(token, &mut account.data).unwrap();
+  env.context.set_account(&env.vault_token_account, &AccountSharedData::from(account));
+
+  let user = &env.users[1];
+  let err = env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+}
+
+#[tokio::test]
+async fn user_count_counts_distinct_depositors() {
+  let env = Setup::new(3).start_with_vault().await;
+  let (alice, bob) = (&env.users[1], &env.users[2]);
+
+  env.send(&[env.deposit_ix(alice, 100)], &[&alice.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.user_count, 1);
+  env.send(&[env.deposit_ix(bob, 100)], &[&bob.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.user_count, 2);
+
+  // A returning depositor already has a user vault
+  env.send(&[env.deposit_ix(alice, 50)], &[&alice.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.user_count, 2);
+
+  // Emptying a position leaves its record open, so it still counts
+  env.send(&[env.withdraw_ix(bob, 100)], &[&bob.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.user_count, 2);
+}
+
+#[tokio::test]
+async fn user_vault_of_one_vault_cannot_be_reused_with_another() {
+  let mut setup = Setup::new(3);
+  // A second vault over the same mint, owned by users[2], with its own token account
+  let other_vault_token_account = Pubkey::new_unique();
+  let (vault_authority, _) = safe::pda::vault_authority(&program_id());
+  common::add_token_account(&mut setup.program_test, other_vault_token_account, setup.mint, vault_authority, 0);
+  let mut env = setup.start_with_vault().await;
+  let other_owner = &env.users[2];
+  let (other_vault, _) = safe::pda::vault_state(&program_id(), &other_owner.key(), &env.mint);
+  let init = instruction::init_vault(
+    &program_id(),
+    &other_owner.key(),
+    &env.mint,
+    &other_vault_token_account,
+    &spl_token::id(),
+    [0; 32],
+    0,
+    0,
+    &Pubkey::default(),
+    0,
+  );
+  env.send(&[init], &[&other_owner.keypair]).await.unwrap();
+
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  let other_deposit = |amount| {
+    instruction::deposit(
+      &program_id(),
+      amount,
+      &user.key(),
+      &user.token_account,
+      &other_vault_token_account,
+      &other_vault,
+      &spl_token::id(),
+      &env.mint,
+      false,
+      None,
+    )
+  };
+
+  // The first vault's record doesn't derive from the second vault's key
+  let mut ix = other_deposit(50);
+  ix.accounts[4].pubkey = env.user_vault_key(user);
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::InvalidPda));
+
+  // And copied to the second vault's PDA, it still names the first vault
+  let record = env.account(env.user_vault_key(user)).await.unwrap();
+  let (other_user_vault, _) = safe::pda::user_vault(&program_id(), &user.key(), &other_vault);
+  env.context.set_account(&other_user_vault, &AccountSharedData::from(record));
+  let err = env.send(&[other_deposit(60)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+  assert_eq!(env.token_balance(other_vault_token_account).await, 0);
+}
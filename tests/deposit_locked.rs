@@ -0,0 +1,75 @@
+// DepositLocked vests the whole position until locked_until; withdrawals are refused before it and allowed after
+mod common;
+
+use common::{custom, program_id, Env, User, USER_TOKENS};
+use safe::{error::VaultError, instruction};
+use solana_program::clock::Clock;
+use solana_sdk::instruction::Instruction;
+
+fn deposit_locked_ix(env: &Env, user: &User, amount: u64, locked_until: i64) -> Instruction {
+  instruction::deposit_locked(
+    &program_id(),
+    amount,
+    locked_until,
+    &user.key(),
+    &user.token_account,
+    &env.vault_token_account,
+    &env.vault_state,
+    &spl_token::id(),
+    &env.mint,
+    false,
+    None,
+  )
+}
+
+async fn now(env: &Env) -> i64 {
+  env.context.banks_client.clone().get_sysvar::<Clock>().await.unwrap().unix_timestamp
+}
+
+async fn set_time(env: &Env, unix_timestamp: i64) {
+  let mut clock: Clock = env.context.banks_client.clone().get_sysvar().await.unwrap();
+  clock.unix_timestamp = unix_timestamp;
+  env.context.set_sysvar(&clock);
+}
+
+#[tokio::test]
+async fn locked_until_expiry_then_withdrawable() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  let unlock = now(&env).await + 1_000;
+  env.send(&[deposit_locked_ix(&env, user, 500, unlock)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.locked_until, unlock);
+
+  let err = env.send(&[env.withdraw_ix(user, 500)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Locked));
+
+  // One second short is still locked
+  set_time(&env, unlock - 1).await;
+  let err = env.send(&[env.withdraw_ix(user, 499)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Locked));
+
+  set_time(&env, unlock).await;
+  env.send(&[env.withdraw_ix(user, 500)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+}
+
+#[tokio::test]
+async fn later_deposits_keep_the_longer_lock() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  let start = now(&env).await;
+  env.send(&[deposit_locked_ix(&env, user, 100, start + 2_000)], &[&user.keypair]).await.unwrap();
+
+  // A shorter lock, or a plain deposit, leaves the existing one in place
+  env.send(&[deposit_locked_ix(&env, user, 100, start + 500)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.locked_until, start + 2_000);
+
+  // A longer one extends it over the whole position
+  env.send(&[deposit_locked_ix(&env, user, 100, start + 3_000)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.locked_until, start + 3_000);
+
+  set_time(&env, start + 2_500).await;
+  let err = env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Locked));
+}
@@ -0,0 +1,52 @@
+// process_instruction dispatch: each tag reaches its handler and leaves the state that handler is meant to write
+mod common;
+
+use common::{program_id, Setup, USER_TOKENS};
+use safe::error::VaultError;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_sdk::{instruction::Instruction, transaction::TransactionError};
+
+#[tokio::test]
+async fn init_records_the_vault() {
+  let env = Setup::new(1).start_with_vault().await;
+
+  let vault = env.vault().await;
+  assert!(vault.is_initialized);
+  assert_eq!(vault.owner, env.owner().key());
+  assert_eq!(vault.token_mint, env.mint);
+  assert_eq!(vault.vault_token_account, env.vault_token_account);
+  assert_eq!(vault.total_deposits, 0);
+}
+
+#[tokio::test]
+async fn deposit_then_withdraw_moves_tokens_and_balances() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  env.send(&[env.deposit_ix(user, 300)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 300);
+  assert_eq!(env.vault().await.total_deposits, 300);
+  assert_eq!(env.vault().await.user_count, 1);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 300);
+
+  env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 200);
+  assert_eq!(env.vault().await.total_deposits, 200);
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 200);
+}
+
+#[tokio::test]
+async fn unknown_tag_is_rejected() {
+  let env = Setup::new(1).start_with_vault().await;
+
+  let ix = Instruction { program_id: program_id(), accounts: vec![], data: vec![255] };
+  let err = env.send(&[ix], &[]).await.unwrap_err().unwrap();
+  assert_eq!(
+    err,
+    TransactionError::InstructionError(0, InstructionError::Custom(VaultError::UnknownInstruction as u32)),
+  );
+
+  // The vault is untouched by the rejected instruction
+  assert_eq!(env.vault().await.owner, env.owner().key());
+  assert_ne!(env.vault_state, Pubkey::default());
+}
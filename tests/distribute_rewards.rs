@@ -0,0 +1,58 @@
+// DistributeRewards grows every depositor's balance in proportion to their share, once per epoch, and each user picks
+// up the rounds since their last interaction
+mod common;
+
+use common::{custom, vault_ix, Env, Setup, USER_TOKENS};
+use safe::{error::VaultError, instruction::VaultInstruction};
+use solana_sdk::instruction::Instruction;
+
+fn distribute_ix(env: &Env, total_reward: u64) -> Instruction {
+  let owner = env.owner();
+  vault_ix(
+    VaultInstruction::DistributeRewards { total_reward },
+    &[owner.key(), owner.token_account, env.vault_token_account, env.vault_state, spl_token::id(), env.mint],
+  )
+}
+
+async fn next_epoch(env: &mut Env) {
+  let epoch = env.context.banks_client.get_sysvar::<solana_program::clock::Clock>().await.unwrap().epoch;
+  env.context.warp_to_epoch(epoch + 1).unwrap();
+}
+
+#[tokio::test]
+async fn two_rounds_over_two_users_of_different_sizes() {
+  let mut env = Setup::new(3).start_with_vault().await;
+  let (small, large) = (&env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(small, 100), env.deposit_ix(large, 300)], &[&small.keypair, &large.keypair]).await.unwrap();
+
+  // 400 over 400 deposited tokens: one reward token each
+  next_epoch(&mut env).await;
+  env.send(&[distribute_ix(&env, 400)], &[&env.owner().keypair]).await.unwrap();
+  assert_eq!(env.vault().await.total_deposits, 800);
+
+  // 200 over the 800 now credited: a quarter token each, so 50 and 150
+  next_epoch(&mut env).await;
+  env.send(&[distribute_ix(&env, 200)], &[&env.owner().keypair]).await.unwrap();
+  assert_eq!(env.vault().await.total_deposits, 1_000);
+
+  // Neither user touched the vault between rounds, so each withdrawal settles both rounds at once
+  let (small, large) = (&env.users[1], &env.users[2]);
+  env.send(&[env.withdraw_ix(small, 250), env.withdraw_ix(large, 750)], &[&small.keypair, &large.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(small.token_account).await, USER_TOKENS - 100 + 250);
+  assert_eq!(env.token_balance(large.token_account).await, USER_TOKENS - 300 + 750);
+  assert_eq!(env.token_balance(env.owner().token_account).await, USER_TOKENS - 600);
+  assert_eq!(env.vault().await.total_deposits, 0);
+}
+
+#[tokio::test]
+async fn one_round_per_epoch() {
+  let mut env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  next_epoch(&mut env).await;
+  env.send(&[distribute_ix(&env, 10)], &[&env.owner().keypair]).await.unwrap();
+  let err = env.send(&[distribute_ix(&env, 20)], &[&env.owner().keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::RewardsAlreadyDistributed));
+  assert_eq!(env.vault().await.total_deposits, 110);
+}
@@ -0,0 +1,33 @@
+// EnsureUserVault lets a third party set up a user's record ahead of time; the first deposit then lands in it
+mod common;
+
+use common::{vault_ix, Env, User};
+use safe::instruction::VaultInstruction;
+use solana_sdk::instruction::Instruction;
+
+fn ensure_user_vault_ix(env: &Env, payer: &User, user: &User) -> Instruction {
+  vault_ix(
+    VaultInstruction::EnsureUserVault,
+    &[payer.key(), user.key(), env.user_vault_key(user), env.vault_state, solana_program::system_program::id()],
+  )
+}
+
+#[tokio::test]
+async fn ensure_then_deposit() {
+  let env = common::Setup::new(3).start_with_vault().await;
+  let (payer, user, other_payer) = (env.owner(), &env.users[1], &env.users[2]);
+
+  env.send(&[ensure_user_vault_ix(&env, payer, user)], &[&payer.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.user_count, 1);
+  assert_eq!(env.user_vault(user).await.deposited_amount, 0);
+
+  // A second call is a no-op rather than an error and doesn't count the user twice. A different payer keeps it from
+  // being an exact duplicate of the first transaction, which the bank could drop as already processed
+  env.send(&[ensure_user_vault_ix(&env, other_payer, user)], &[&other_payer.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.user_count, 1);
+
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+  assert_eq!(env.vault().await.total_deposits, 100);
+  assert_eq!(env.vault().await.user_count, 1);
+}
@@ -0,0 +1,110 @@
+// Deposit and Withdraw log a structured event as a "Program data:" line that decode_event parses back
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{Env, Setup};
+use safe::events::{decode_event, DepositEvent, VaultEvent, WithdrawEvent};
+use solana_program::{
+  account_info::AccountInfo,
+  entrypoint::ProgramResult,
+  instruction::Instruction,
+  program_stubs::{set_syscall_stubs, SyscallStubs},
+  pubkey::Pubkey,
+};
+use std::sync::Once;
+
+// The natively loaded program logs sol_log_data through program-test's syscall stubs, which only print it to stdout.
+// This wraps them so the payload lands in the transaction logs instead, base64-encoded as the runtime does it;
+// everything else goes to program-test's stubs unchanged.
+struct LogDataStubs(Box<dyn SyscallStubs>);
+
+impl SyscallStubs for LogDataStubs {
+  fn sol_log_data(&self, fields: &[&[u8]]) {
+    let fields: Vec<String> = fields.iter().map(|field| STANDARD.encode(field)).collect();
+    self.0.sol_log(&format!("data: {}", fields.join(" ")));
+  }
+
+  fn sol_log(&self, message: &str) {
+    self.0.sol_log(message)
+  }
+  fn sol_invoke_signed(
+    &self,
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+  ) -> ProgramResult {
+    self.0.sol_invoke_signed(instruction, account_infos, signers_seeds)
+  }
+  fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+    self.0.sol_get_clock_sysvar(var_addr)
+  }
+  fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+    self.0.sol_get_epoch_schedule_sysvar(var_addr)
+  }
+  fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+    self.0.sol_get_epoch_rewards_sysvar(var_addr)
+  }
+  fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+    self.0.sol_get_fees_sysvar(var_addr)
+  }
+  fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+    self.0.sol_get_rent_sysvar(var_addr)
+  }
+  fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+    self.0.sol_get_last_restart_slot(var_addr)
+  }
+  fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+    self.0.sol_get_return_data()
+  }
+  fn sol_set_return_data(&self, data: &[u8]) {
+    self.0.sol_set_return_data(data)
+  }
+  fn sol_get_stack_height(&self) -> u64 {
+    self.0.sol_get_stack_height()
+  }
+}
+
+// A started vault; program-test installs its stubs on the first start, so they're wrapped only after that
+async fn start() -> Env {
+  static WRAP: Once = Once::new();
+  let env = Setup::new(2).start_with_vault().await;
+  WRAP.call_once(|| {
+    let program_test_stubs = set_syscall_stubs(Box::new(NoStubs));
+    set_syscall_stubs(Box::new(LogDataStubs(program_test_stubs)));
+  });
+  env
+}
+
+// Placeholder held only for the instant the real stubs are swapped out
+struct NoStubs;
+impl SyscallStubs for NoStubs {}
+
+// Simulate `ix`, signed by users[1], and decode every event it logged: "Program data:" lines from an SBF build,
+// "Program log: data:" lines from the wrapped stubs
+async fn events(env: &Env, ix: Instruction) -> Vec<VaultEvent> {
+  let transaction = env.transaction(&[ix], &[&env.users[1].keypair]).await;
+  let simulation = env.context.banks_client.clone().simulate_transaction(transaction).await.unwrap();
+  simulation.result.unwrap().unwrap();
+  simulation
+    .simulation_details
+    .unwrap()
+    .logs
+    .iter()
+    .filter_map(|log| log.strip_prefix("Program data: ").or_else(|| log.strip_prefix("Program log: data: ")))
+    .filter_map(|data| decode_event(&STANDARD.decode(data).unwrap()))
+    .collect()
+}
+
+#[tokio::test]
+async fn deposit_and_withdraw_events_decode() {
+  let env = start().await;
+  let user = &env.users[1];
+  let (user_key, vault) = (user.key(), env.vault_state);
+
+  let deposit = VaultEvent::Deposit(DepositEvent { user: user_key, vault, amount: 300, new_total: 300 });
+  assert_eq!(events(&env, env.deposit_ix(user, 300)).await, [deposit]);
+  env.send(&[env.deposit_ix(user, 300)], &[&user.keypair]).await.unwrap();
+
+  let withdraw = VaultEvent::Withdraw(WithdrawEvent { user: user_key, vault, amount: 120, fee: 0, new_total: 180 });
+  assert_eq!(events(&env, env.withdraw_ix(user, 120)).await, [withdraw]);
+}
@@ -0,0 +1,25 @@
+// A vault state account with the right bytes but owned by another program is refused before it's unpacked
+mod common;
+
+use common::instruction_error;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_sdk::account::AccountSharedData;
+
+#[tokio::test]
+async fn deposit_and_withdraw_refuse_a_foreign_vault_state_account() {
+  let mut env = common::Setup::new(2).start_with_vault().await;
+  env.send(&[env.deposit_ix(&env.users[1], 500)], &[&env.users[1].keypair]).await.unwrap();
+
+  // Same data as the real vault, only the owner differs
+  let mut account = env.account(env.vault_state).await.unwrap();
+  account.owner = Pubkey::new_unique();
+  let vault_state = env.vault_state;
+  env.context.set_account(&vault_state, &AccountSharedData::from(account));
+
+  let user = &env.users[1];
+  let err = env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IllegalOwner));
+
+  let err = env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IllegalOwner));
+}
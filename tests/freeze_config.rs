@@ -0,0 +1,53 @@
+// FreezeConfig makes a vault's configuration final: every config change fails with ConfigFrozen afterwards, while
+// deposits and withdrawals carry on
+mod common;
+
+use common::{custom, vault_ix, Env, User};
+use safe::{error::VaultError, instruction::VaultInstruction};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::instruction::Instruction;
+
+fn owner_ix(env: &Env, signer: &User, ix: VaultInstruction) -> Instruction {
+  vault_ix(ix, &[signer.key(), env.vault_state])
+}
+
+#[tokio::test]
+async fn config_changes_fail_after_freezing_while_deposits_still_work() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  env.send(&[env.deposit_ix(user, 500)], &[&user.keypair]).await.unwrap();
+
+  env.configure(VaultInstruction::FreezeConfig).await;
+  assert!(env.vault().await.config_frozen);
+
+  // Fees, caps, pausing, ownership, and the freeze itself
+  let no_fee = VaultInstruction::SetInstructionFee { instruction_fee_lamports: 0, instruction_fee_treasury: Pubkey::default() };
+  let changes = [
+    owner_ix(&env, owner, no_fee),
+    owner_ix(&env, owner, VaultInstruction::SetMaxSingleDeposit { max_single_deposit: 100 }),
+    owner_ix(&env, owner, VaultInstruction::SetPaused { paused: true }),
+    vault_ix(VaultInstruction::TransferOwnership, &[owner.key(), env.vault_state, user.key()]),
+    owner_ix(&env, owner, VaultInstruction::FreezeConfig),
+  ];
+  for change in changes {
+    let err = env.send(&[change], &[&owner.keypair]).await.unwrap_err().unwrap();
+    assert_eq!(err, custom(VaultError::ConfigFrozen));
+  }
+  let vault = env.vault().await;
+  assert_eq!((vault.owner, vault.paused, vault.max_single_deposit), (owner.key(), false, 0));
+
+  env.send(&[env.deposit_ix(user, 200)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.withdraw_ix(user, 300)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 400);
+}
+
+#[tokio::test]
+async fn refuses_a_non_owner() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let stranger = &env.users[1];
+
+  let freeze = owner_ix(&env, stranger, VaultInstruction::FreezeConfig);
+  let err = env.send(&[freeze], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  assert!(!env.vault().await.config_frozen);
+}
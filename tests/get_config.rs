@@ -0,0 +1,26 @@
+// GetConfig returns the whole vault in its Pack layout, so the same Vault::unpack decodes it and the account
+mod common;
+
+use common::{vault_ix, Setup};
+use safe::{instruction::VaultInstruction, state::Vault};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+
+#[tokio::test]
+async fn config_decodes_back_into_the_vault() {
+  let fee_treasury = Pubkey::new_unique();
+  let env = Setup::new(2).start_with_config(5_000, 30, fee_treasury, 3_600).await;
+  let user = &env.users[1];
+  env.configure(VaultInstruction::SetMaxSingleDeposit { max_single_deposit: 1_000 }).await;
+  env.send(&[env.deposit_ix(user, 400)], &[&user.keypair]).await.unwrap();
+
+  let data = env.return_data(vault_ix(VaultInstruction::GetConfig, &[env.vault_state])).await;
+  assert_eq!(data.len(), Vault::LEN);
+  let config = Vault::unpack(&data).unwrap();
+
+  // Byte for byte what the account holds, so nothing is left out
+  assert_eq!(data, env.account(env.vault_state).await.unwrap().data);
+  assert_eq!(config, env.vault().await);
+  assert_eq!((config.owner, config.token_mint, config.fee_treasury), (env.owner().key(), env.mint, fee_treasury));
+  assert_eq!((config.deposit_cap, config.withdrawal_fee_bps, config.lock_duration), (5_000, 30, 3_600));
+  assert_eq!((config.max_single_deposit, config.total_deposits, config.user_count), (1_000, 400, 1));
+}
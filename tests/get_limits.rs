@@ -0,0 +1,31 @@
+// GetLimits returns every constraint a deposit/withdraw form needs as one packed VaultLimits
+mod common;
+
+use common::{vault_ix, Setup};
+use safe::{instruction::VaultInstruction, state::VaultLimits};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+
+#[tokio::test]
+async fn limits_match_the_configuration() {
+  let env = Setup::new(1).start_with_config(5_000, 30, Pubkey::new_unique(), 3_600).await;
+  env.configure(VaultInstruction::SetMinWithdraw { min_withdraw: 10 }).await;
+
+  let data = env.return_data(vault_ix(VaultInstruction::GetLimits, &[env.vault_state])).await;
+  assert_eq!(data.len(), VaultLimits::LEN);
+  let limits = VaultLimits::unpack_from_slice(&data).unwrap();
+
+  assert_eq!(limits.deposit_cap, 5_000);
+  assert_eq!(limits.withdraw_fee_bps, 30);
+  assert_eq!(limits.cooldown_secs, 3_600);
+  assert_eq!(limits.min_withdraw, 10);
+  // Not enforced by this program, so always reported as 0
+  assert_eq!((limits.min_deposit, limits.reserve), (0, 0));
+}
+
+#[tokio::test]
+async fn unconfigured_vault_reports_no_limits() {
+  let env = Setup::new(1).start_with_vault().await;
+
+  let data = env.return_data(vault_ix(VaultInstruction::GetLimits, &[env.vault_state])).await;
+  assert_eq!(data, [0; VaultLimits::LEN]);
+}
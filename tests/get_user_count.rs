@@ -0,0 +1,64 @@
+// GetUserCount reads the vault's counter of open user vault records, which tracks every way a record is opened or closed
+mod common;
+
+use common::{program_id, vault_ix, Env, User};
+use safe::instruction::{self, VaultInstruction};
+use solana_sdk::instruction::Instruction;
+
+async fn user_count(env: &Env) -> u64 {
+  let data = env.return_data(vault_ix(VaultInstruction::GetUserCount, &[env.vault_state])).await;
+  u64::from_le_bytes(data.try_into().unwrap())
+}
+
+// The records that actually exist, found by checking every test user's PDA
+async fn open_records(env: &Env) -> u64 {
+  let mut open = 0;
+  for user in &env.users {
+    open += env.account(env.user_vault_key(user)).await.is_some() as u64;
+  }
+  open
+}
+
+async fn assert_counted(env: &Env, expected: u64) {
+  assert_eq!(open_records(env).await, expected);
+  assert_eq!(user_count(env).await, expected);
+}
+
+fn close_ix(env: &Env, user: &User, closer: Option<&User>) -> Instruction {
+  let closer = closer.map(User::key);
+  instruction::close_user_vault(&program_id(), &user.key(), &env.vault_state, closer.as_ref())
+}
+
+#[tokio::test]
+async fn counter_matches_the_records_through_opens_and_closes() {
+  let env = common::Setup::new(5).start_with_vault().await;
+  let owner = env.owner();
+  let (first, second, third, fourth) = (&env.users[1], &env.users[2], &env.users[3], &env.users[4]);
+  assert_counted(&env, 0).await;
+
+  // Opened by first deposits
+  for user in [first, second, third] {
+    env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  }
+  assert_counted(&env, 3).await;
+
+  // Opened ahead of any deposit by a third party
+  let ensure = vault_ix(
+    VaultInstruction::EnsureUserVault,
+    &[owner.key(), fourth.key(), env.user_vault_key(fourth), env.vault_state, solana_program::system_program::id()],
+  );
+  env.send(&[ensure], &[&owner.keypair]).await.unwrap();
+  assert_counted(&env, 4).await;
+
+  // Closed by its user once emptied
+  env.send(&[env.withdraw_ix(second, 100), close_ix(&env, second, None)], &[&second.keypair]).await.unwrap();
+  assert_counted(&env, 3).await;
+
+  // Force-closed by the vault owner, empty and never funded
+  env.send(&[close_ix(&env, fourth, Some(owner))], &[&owner.keypair]).await.unwrap();
+  assert_counted(&env, 2).await;
+
+  // Reopened by a new deposit
+  env.send(&[env.deposit_ix(second, 10)], &[&second.keypair]).await.unwrap();
+  assert_counted(&env, 3).await;
+}
@@ -0,0 +1,87 @@
+// GetWithdrawable reports what a Withdraw would pay out right now, after every limit and the fee
+mod common;
+
+use common::{vault_ix, Env, User};
+use safe::instruction::VaultInstruction;
+use solana_program::{clock::Clock, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::account::AccountSharedData;
+use spl_token::state::Account as TokenAccount;
+
+const FEE_BPS: u16 = 100;
+
+async fn withdrawable(env: &Env, user: &User) -> u64 {
+  let ix = vault_ix(
+    VaultInstruction::GetWithdrawable,
+    &[user.key(), env.vault_state, env.user_vault_key(user), env.vault_token_account],
+  );
+  u64::from_le_bytes(env.return_data(ix).await.try_into().unwrap())
+}
+
+#[tokio::test]
+async fn reports_the_payout_net_of_the_fee() {
+  let env = common::Setup::new(2).start_with_fee(FEE_BPS, Pubkey::new_unique()).await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  env.send(&[env.deposit_ix(user, 1_000), env.deposit_ix(owner, 1_000)], &[&user.keypair, &owner.keypair]).await.unwrap();
+
+  assert_eq!(withdrawable(&env, user).await, 990);
+  assert_eq!(withdrawable(&env, owner).await, 1_000);                 // The owner pays no fee
+}
+
+#[tokio::test]
+async fn zero_while_paused_or_withdraw_is_disabled() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+
+  env.configure(VaultInstruction::SetPaused { paused: true }).await;
+  assert_eq!(withdrawable(&env, user).await, 0);
+  env.configure(VaultInstruction::SetPaused { paused: false }).await;
+  assert_eq!(withdrawable(&env, user).await, 1_000);
+
+  let withdraw_bit = 1u64 << VaultInstruction::Withdraw { amount: 0, authority_bump: None }.tag();
+  env.configure(VaultInstruction::SetEnabledInstructions { enabled_instructions: !withdraw_bit }).await;
+  assert_eq!(withdrawable(&env, user).await, 0);
+}
+
+#[tokio::test]
+async fn zero_during_the_cooldown() {
+  let env = common::Setup::new(2).start_with_config(0, 0, Pubkey::default(), 3_600).await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  assert_eq!(withdrawable(&env, user).await, 0);
+
+  let mut clock: Clock = env.context.banks_client.clone().get_sysvar().await.unwrap();
+  clock.unix_timestamp += 3_600;
+  env.context.set_sysvar(&clock);
+  assert_eq!(withdrawable(&env, user).await, 1_000);
+}
+
+#[tokio::test]
+async fn capped_by_vault_liquidity() {
+  let mut env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+
+  // Part of the deposits are out of the token account, e.g. deployed elsewhere
+  let mut account = env.account(env.vault_token_account).await.unwrap();
+  let mut token = TokenAccount::unpack(&account.data).unwrap();
+  token.amount = 400;
+  TokenAccount::pack(token, &mut account.data).unwrap();
+  let vault_token_account = env.vault_token_account;
+  env.context.set_account(&vault_token_account, &AccountSharedData::from(account));
+
+  assert_eq!(withdrawable(&env, &env.users[1]).await, 400);
+}
+
+#[tokio::test]
+async fn capped_by_the_window_allowance() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+
+  env.configure(VaultInstruction::SetMaxVaultWithdrawPerWindow { max_vault_withdraw_per_window: 300 }).await;
+  assert_eq!(withdrawable(&env, user).await, 300);
+
+  env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  assert_eq!(withdrawable(&env, user).await, 200);
+}
@@ -0,0 +1,169 @@
+// InitVault creates the vault state account itself, owned by the program and funded to rent exemption, and records the
+// vault authority's bump for later withdrawals
+mod common;
+
+use common::{custom, instruction_error, program_id, vault_ix, Env, Setup};
+use safe::{error::VaultError, instruction::{self, VaultInstruction}, pda, state::Vault};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use solana_sdk::{
+  account::{Account, AccountSharedData},
+  instruction::{Instruction, InstructionError},
+};
+use spl_token::state::Account as TokenAccount;
+
+// InitVault for the owner with no name, cap, fee or lock
+fn init_ix(env: &Env) -> Instruction {
+  instruction::init_vault(
+    &program_id(),
+    &env.owner().key(),
+    &env.mint,
+    &env.vault_token_account,
+    &spl_token::id(),
+    [0; 32],
+    0,
+    0,
+    &Pubkey::default(),
+    0,
+  )
+}
+
+#[tokio::test]
+async fn creates_a_rent_exempt_program_owned_account() {
+  let env = Setup::new(1).start_with_vault().await;
+
+  let account = env.account(env.vault_state).await.unwrap();
+  assert_eq!(account.owner, program_id());
+  assert_eq!(account.data.len(), Vault::LEN);
+  let rent = env.context.banks_client.clone().get_rent().await.unwrap();
+  assert!(rent.is_exempt(account.lamports, account.data.len()));
+  assert_eq!(env.vault().await.owner, env.owner().key());
+}
+
+#[tokio::test]
+async fn records_the_vault_authority_bump() {
+  let env = Setup::new(1).start_with_vault().await;
+  let vault = env.vault().await;
+
+  // The stored bump rebuilds, with the cheap create_program_address, the same authority a full search finds
+  let (authority, bump) = pda::vault_authority(&program_id());
+  assert_eq!(vault.vault_bump, bump);
+  let rebuilt = Pubkey::create_program_address(&[pda::VAULT_AUTHORITY_SEED, &[vault.vault_bump]], &program_id()).unwrap();
+  assert_eq!(rebuilt, authority);
+
+  // And it is that authority which owns the vault token account
+  let vault_token = TokenAccount::unpack(&env.account(env.vault_token_account).await.unwrap().data).unwrap();
+  assert_eq!(vault_token.owner, rebuilt);
+}
+
+#[tokio::test]
+async fn returns_the_vault_authority_and_its_bump() {
+  let env = Setup::new(1).start().await;
+  let returned = env.signed_return_data(init_ix(&env), &[&env.owner().keypair]).await;
+  assert_eq!(returned.len(), 33);
+
+  // The returned bump alone reproduces the returned authority, no search needed
+  let (authority, bump) = (Pubkey::try_from(&returned[..32]).unwrap(), returned[32]);
+  assert_eq!(Pubkey::create_program_address(&[pda::VAULT_AUTHORITY_SEED, &[bump]], &program_id()).unwrap(), authority);
+  assert_eq!((authority, bump), pda::vault_authority(&program_id()));
+}
+
+#[tokio::test]
+async fn underfunded_pre_allocated_vault_account_is_rejected() {
+  let mut setup = Setup::new(1);
+  // Allocated for the program ahead of time, one lamport short of rent exemption
+  let lamports = Rent::default().minimum_balance(Vault::LEN) - 1;
+  let account = Account { lamports, data: vec![0; Vault::LEN], owner: program_id(), ..Account::default() };
+  setup.program_test.add_account(setup.vault_state, account);
+  let env = setup.start().await;
+
+  let err = env.send(&[init_ix(&env)], &[&env.owner().keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::AccountNotRentExempt));
+  assert!(Vault::unpack(&env.account(env.vault_state).await.unwrap().data).is_err());
+}
+
+#[tokio::test]
+async fn pre_funded_vault_token_account_is_rejected() {
+  let mut env = Setup::new(1).start().await;
+  // Tokens already sitting in the vault token account would never show up in total_deposits
+  let mut account = env.account(env.vault_token_account).await.unwrap();
+  let mut token = TokenAccount::unpack(&account.data).unwrap();
+  token.amount = 1;
+  TokenAccount::pack(token, &mut account.data).unwrap();
+  env.context.set_account(&env.vault_token_account, &AccountSharedData::from(account));
+
+  let err = env.send(&[init_ix(&env)], &[&env.owner().keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::PreFundedAccount));
+  assert!(env.account(env.vault_state).await.is_none());
+}
+
+#[tokio::test]
+async fn vault_token_account_owned_by_another_key_is_rejected() {
+  let mut env = Setup::new(1).start().await;
+  // Whoever holds the token authority could drain every deposit, so it has to be the vault authority PDA
+  let mut account = env.account(env.vault_token_account).await.unwrap();
+  let mut token = TokenAccount::unpack(&account.data).unwrap();
+  token.owner = Pubkey::new_unique();
+  TokenAccount::pack(token, &mut account.data).unwrap();
+  env.context.set_account(&env.vault_token_account, &AccountSharedData::from(account));
+
+  let err = env.send(&[init_ix(&env)], &[&env.owner().keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+  assert!(env.account(env.vault_state).await.is_none());
+}
+
+#[tokio::test]
+async fn a_live_vault_cannot_be_initialized_again() {
+  let env = Setup::new(1).start_with_vault().await;
+  let owner = env.owner();
+  env.configure(VaultInstruction::SetPaused { paused: true }).await;
+
+  // A different cap keeps this from being a replay of the setup's InitVault
+  let init = instruction::init_vault(
+    &program_id(),
+    &owner.key(),
+    &env.mint,
+    &env.vault_token_account,
+    &spl_token::id(),
+    [0; 32],
+    1,
+    0,
+    &Pubkey::default(),
+    0,
+  );
+  let err = env.send(&[init], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::AccountAlreadyInitialized));
+  assert!(env.vault().await.paused);
+}
+
+#[tokio::test]
+async fn a_closed_vault_is_reopened_afresh() {
+  let env = Setup::new(2).start_with_vault().await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  let close_record = instruction::close_user_vault(&program_id(), &user.key(), &env.vault_state, None);
+  let close_vault = vault_ix(VaultInstruction::CloseVault, &[owner.key(), env.vault_state, owner.key()]);
+  env.send(&[close_record, close_vault], &[&user.keypair, &owner.keypair]).await.unwrap();
+  assert!(env.account(env.vault_state).await.is_none());
+
+  // Reuse is the intended policy: the address is the owner's own PDA and nothing recorded against it outlived the close
+  env.send(&[init_ix(&env)], &[&owner.keypair]).await.unwrap();
+  let vault = env.vault().await;
+  assert_eq!((vault.total_deposits, vault.user_count, vault.owner), (0, 0, owner.key()));
+
+  env.send(&[env.deposit_ix(user, 50)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.total_deposits, 50);
+}
+
+#[tokio::test]
+async fn a_zeroed_program_owned_vault_account_is_initialized_in_place() {
+  let mut setup = Setup::new(1);
+  // What a closed vault's account looks like if it were funded again before being purged: program owned, all zeroes
+  let lamports = Rent::default().minimum_balance(Vault::LEN);
+  let account = Account { lamports, data: vec![0; Vault::LEN], owner: program_id(), ..Account::default() };
+  setup.program_test.add_account(setup.vault_state, account);
+  let env = setup.start().await;
+
+  env.send(&[init_ix(&env)], &[&env.owner().keypair]).await.unwrap();
+  assert_eq!(env.vault().await.owner, env.owner().key());
+}
@@ -0,0 +1,39 @@
+// InitVaultAndDeposit leaves the vault and the owner's first position set up by a single instruction
+mod common;
+
+use common::{program_id, Setup, USER_TOKENS};
+use safe::instruction;
+use solana_program::pubkey::Pubkey;
+
+#[tokio::test]
+async fn initializes_the_vault_and_deposits() {
+  let env = Setup::new(1).start().await;
+  let owner = env.owner();
+
+  let ix = instruction::init_vault_and_deposit(
+    &program_id(),
+    &owner.key(),
+    &env.mint,
+    &env.vault_token_account,
+    &owner.token_account,
+    &spl_token::id(),
+    [0; 32],
+    0,
+    0,
+    &Pubkey::default(),
+    0,
+    400,
+  );
+  env.send(&[ix], &[&owner.keypair]).await.unwrap();
+
+  let vault = env.vault().await;
+  assert!(vault.is_initialized);
+  assert_eq!(vault.owner, owner.key());
+  assert_eq!(vault.total_deposits, 400);
+  assert_eq!(vault.user_count, 1);
+  let user_vault = env.user_vault(owner).await;
+  assert_eq!(user_vault.deposited_amount, 400);
+  assert_eq!(user_vault.vault, env.vault_state);
+  assert_eq!(env.token_balance(owner.token_account).await, USER_TOKENS - 400);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 400);
+}
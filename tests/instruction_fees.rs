@@ -0,0 +1,91 @@
+// With the instruction-fees feature, a vault's SOL fee has to be paid to its treasury for a deposit or withdrawal to go
+// through; without the feature SetInstructionFee refuses to set one
+mod common;
+
+use common::instruction_error;
+use safe::instruction::VaultInstruction;
+use solana_sdk::instruction::InstructionError;
+#[cfg(feature = "instruction-fees")]
+use {
+  common::{program_id, Env, User},
+  safe::instruction,
+  solana_program::pubkey::Pubkey,
+  solana_sdk::instruction::Instruction,
+};
+
+const FEE: u64 = 1_000_000;                // Above the rent-exempt minimum, so a fresh treasury can receive it
+
+#[cfg(feature = "instruction-fees")]
+fn deposit_ix(env: &Env, user: &User, amount: u64, treasury: Option<&Pubkey>) -> Instruction {
+  instruction::deposit(
+    &program_id(),
+    amount,
+    &user.key(),
+    &user.token_account,
+    &env.vault_token_account,
+    &env.vault_state,
+    &spl_token::id(),
+    &env.mint,
+    false,
+    treasury,
+  )
+}
+
+#[cfg(feature = "instruction-fees")]
+fn withdraw_ix(env: &Env, user: &User, amount: u64, treasury: Option<&Pubkey>) -> Instruction {
+  instruction::withdraw(
+    &program_id(),
+    amount,
+    &user.key(),
+    &env.vault_token_account,
+    &user.token_account,
+    &env.vault_state,
+    &spl_token::id(),
+    &env.mint,
+    None,
+    treasury,
+  )
+}
+
+#[cfg(feature = "instruction-fees")]
+#[tokio::test]
+async fn unpaid_fees_fail_and_paid_ones_succeed() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  let treasury = Pubkey::new_unique();
+  let set_fee = VaultInstruction::SetInstructionFee { instruction_fee_lamports: FEE, instruction_fee_treasury: treasury };
+  env.configure(set_fee).await;
+
+  // No treasury passed, so nothing pays the fee
+  let err = env.send(&[deposit_ix(&env, user, 100, None)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::NotEnoughAccountKeys));
+
+  // Paying somewhere other than the vault's treasury doesn't count
+  let elsewhere = Pubkey::new_unique();
+  let err = env.send(&[deposit_ix(&env, user, 100, Some(&elsewhere))], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+
+  env.send(&[deposit_ix(&env, user, 100, Some(&treasury))], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.account(treasury).await.unwrap().lamports, FEE);
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+
+  let err = env.send(&[withdraw_ix(&env, user, 40, None)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::NotEnoughAccountKeys));
+
+  env.send(&[withdraw_ix(&env, user, 40, Some(&treasury))], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.account(treasury).await.unwrap().lamports, 2 * FEE);
+  assert_eq!(env.user_vault(user).await.deposited_amount, 60);
+}
+
+#[cfg(not(feature = "instruction-fees"))]
+#[tokio::test]
+async fn a_fee_this_build_would_not_charge_is_refused() {
+  let env = common::Setup::new(1).start_with_vault().await;
+  let owner = env.owner();
+  let set_fee = VaultInstruction::SetInstructionFee { instruction_fee_lamports: FEE, instruction_fee_treasury: owner.key() };
+  let set_fee = common::vault_ix(set_fee, &[owner.key(), env.vault_state]);
+
+  let err = env.send(&[set_fee], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidArgument));
+  assert_eq!(env.vault().await.instruction_fee_lamports, 0);
+}
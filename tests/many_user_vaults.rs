@@ -0,0 +1,46 @@
+// Scaling check for the PDA-per-user model: 100 distinct users each open a user vault with a first deposit, batched
+// into as many deposits per transaction as fit the packet size. Prints the compute units consumed.
+// Loaded natively, only the token program CPIs are metered; point SBF_OUT_DIR at the `cargo build-sbf` output to load
+// the program as SBF and get on-chain figures.
+mod common;
+
+use common::Setup;
+use solana_sdk::signature::Keypair;
+
+const USERS: usize = 100;
+const DEPOSITS_PER_TRANSACTION: usize = 4;               // Each adds a signature and three accounts; a fifth overflows 1232 bytes
+const DEPOSIT: u64 = 1_000;
+const MAX_UNITS_PER_INSTRUCTION: u64 = 200_000;          // Default compute budget of an instruction
+
+#[tokio::test]
+async fn one_hundred_user_vaults() {
+  let env = Setup::new(USERS + 1).start_with_vault().await;   // users[0] is the owner and doesn't deposit
+  let mut total_units = 0;
+
+  for batch in env.users[1..].chunks(DEPOSITS_PER_TRANSACTION) {
+    let instructions: Vec<_> = batch.iter().map(|user| env.deposit_ix(user, DEPOSIT)).collect();
+    let signers: Vec<&Keypair> = batch.iter().map(|user| &user.keypair).collect();
+    let transaction = env.transaction(&instructions, &signers).await;
+
+    let outcome = env.context.banks_client.clone().process_transaction_with_metadata(transaction).await.unwrap();
+    outcome.result.unwrap();
+    let units = outcome.metadata.unwrap().compute_units_consumed;
+    assert!(units <= MAX_UNITS_PER_INSTRUCTION * batch.len() as u64);
+    total_units += units;
+  }
+
+  let vault = env.vault().await;
+  assert_eq!(vault.user_count, USERS as u64);
+  assert_eq!(vault.total_deposits, DEPOSIT * USERS as u64);
+  for user in &env.users[1..] {
+    assert_eq!(env.user_vault(user).await.deposited_amount, DEPOSIT);
+  }
+
+  println!(
+    "{} user vaults opened in {} transactions: {} CU total, {} CU per first deposit",
+    USERS,
+    USERS.div_ceil(DEPOSITS_PER_TRANSACTION),
+    total_units,
+    total_units / USERS as u64,
+  );
+}
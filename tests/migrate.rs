@@ -0,0 +1,55 @@
+// Migrate grows a vault written in the original layout to the current one in place, keeping its address and data
+mod common;
+
+use common::{vault_ix, Setup};
+use safe::{
+  instruction::VaultInstruction,
+  state::{self, Vault},
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, rent::Rent, system_program};
+use solana_sdk::account::Account;
+
+#[tokio::test]
+async fn migrates_a_legacy_vault() {
+  let mut setup = Setup::new(2);
+  let owner = setup.users[0].key();
+
+  // The original layout: is_initialized, owner, token_mint, vault_token_account, at whatever address it was created
+  let mut data = vec![1u8];
+  data.extend_from_slice(owner.as_ref());
+  data.extend_from_slice(setup.mint.as_ref());
+  data.extend_from_slice(setup.vault_token_account.as_ref());
+  assert_eq!(data.len(), state::VAULT_V1_LEN);
+  setup.vault_state = Pubkey::new_unique();
+  setup.program_test.add_account(
+    setup.vault_state,
+    Account { lamports: Rent::default().minimum_balance(data.len()), data, owner: common::program_id(), ..Account::default() },
+  );
+  let env = setup.start().await;
+  let owner = env.owner();
+
+  let migrate = vault_ix(VaultInstruction::Migrate, &[owner.key(), env.vault_state, system_program::id()]);
+  env.send(&[migrate], &[&owner.keypair]).await.unwrap();
+
+  let account = env.account(env.vault_state).await.unwrap();
+  assert_eq!(account.data.len(), Vault::LEN);
+  let rent = env.context.banks_client.clone().get_rent().await.unwrap();
+  assert!(rent.is_exempt(account.lamports, account.data.len()));
+
+  // The original fields survive and the new ones read as their defaults
+  let vault = Vault::unpack(&account.data).unwrap();
+  assert_eq!(vault.version, state::VAULT_VERSION);
+  assert_eq!(vault.owner, owner.key());
+  assert_eq!(vault.token_mint, env.mint);
+  assert_eq!(vault.vault_token_account, env.vault_token_account);
+  assert_eq!(vault.allowed_token_program, spl_token::id());
+  assert_eq!(vault.total_deposits, 0);
+  assert_eq!(vault.user_count, 0);
+  assert_ne!(vault.vault_bump, 0);
+
+  // And the migrated vault works as any other
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.withdraw_ix(user, 40)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.total_deposits, 60);
+}
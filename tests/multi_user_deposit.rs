@@ -0,0 +1,26 @@
+// Two users depositing into the same vault within one transaction each get their own user vault, and the vault counts both
+mod common;
+
+use common::Setup;
+
+#[tokio::test]
+async fn two_signers_deposit_in_one_transaction() {
+  let env = Setup::new(3).start_with_vault().await;
+  let (alice, bob) = (&env.users[1], &env.users[2]);
+
+  env
+    .send(&[env.deposit_ix(alice, 250), env.deposit_ix(bob, 400)], &[&alice.keypair, &bob.keypair])
+    .await
+    .unwrap();
+
+  let (alice_vault, bob_vault) = (env.user_vault(alice).await, env.user_vault(bob).await);
+  assert_eq!((alice_vault.user, alice_vault.deposited_amount), (alice.key(), 250));
+  assert_eq!((bob_vault.user, bob_vault.deposited_amount), (bob.key(), 400));
+  assert_eq!(alice_vault.vault, env.vault_state);
+  assert_eq!(bob_vault.vault, env.vault_state);
+
+  let vault = env.vault().await;
+  assert_eq!(vault.total_deposits, 650);
+  assert_eq!(vault.user_count, 2);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 650);
+}
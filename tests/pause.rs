@@ -0,0 +1,42 @@
+// SetPaused is owner-only, and a paused vault refuses deposits and withdrawals until it's unpaused
+mod common;
+
+use common::{custom, vault_ix, Env, User};
+use safe::{error::VaultError, instruction::VaultInstruction};
+use solana_sdk::instruction::Instruction;
+
+fn set_paused_ix(env: &Env, signer: &User, paused: bool) -> Instruction {
+  vault_ix(VaultInstruction::SetPaused { paused }, &[signer.key(), env.vault_state])
+}
+
+#[tokio::test]
+async fn only_the_owner_can_pause() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let stranger = &env.users[1];
+
+  let err = env.send(&[set_paused_ix(&env, stranger, true)], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  assert!(!env.vault().await.paused);
+
+  env.send(&[set_paused_ix(&env, env.owner(), true)], &[&env.owner().keypair]).await.unwrap();
+  assert!(env.vault().await.paused);
+}
+
+#[tokio::test]
+async fn paused_vault_refuses_deposits_and_withdrawals() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  env.send(&[set_paused_ix(&env, env.owner(), true)], &[&env.owner().keypair]).await.unwrap();
+
+  let err = env.send(&[env.deposit_ix(user, 50)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::VaultPaused));
+  let err = env.send(&[env.withdraw_ix(user, 50)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::VaultPaused));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+
+  env.send(&[set_paused_ix(&env, env.owner(), false)], &[&env.owner().keypair]).await.unwrap();
+  env.send(&[env.deposit_ix(user, 50)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 150);
+}
@@ -0,0 +1,96 @@
+// A depositor can be a PDA of another program: that program signs for it with invoke_signed when it CPIs into Deposit,
+// and the signature carries through to the token transfer and the user vault's creation
+mod common;
+
+use common::{instruction_error, program_id, Setup};
+use safe::{instruction, state::UserVault};
+use solana_program::{
+  account_info::AccountInfo,
+  entrypoint::ProgramResult,
+  program::invoke_signed,
+  program_pack::Pack,
+  pubkey::Pubkey,
+  system_program,
+};
+use solana_program_test::processor;
+use solana_sdk::{
+  account::Account,
+  instruction::{AccountMeta, Instruction, InstructionError},
+};
+
+const MOCK_PROGRAM_ID: Pubkey = Pubkey::new_from_array([9; 32]);
+const DEPOSITOR_SEED: &[u8] = b"depositor";
+const TREASURY_TOKENS: u64 = 5_000;
+
+// Deposits the little-endian u64 in `data` from its ["depositor"] PDA
+// Accounts: depositor PDA, source token account, vault token account, vault state, user vault, token program, system
+// program, token mint, vault program
+fn mock_process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+  let amount = u64::from_le_bytes(data.try_into().unwrap());
+  let (depositor, bump) = Pubkey::find_program_address(&[DEPOSITOR_SEED], program_id);
+  let deposit = instruction::deposit(
+    accounts[8].key,
+    amount,
+    &depositor,
+    accounts[1].key,
+    accounts[2].key,
+    accounts[3].key,
+    accounts[5].key,
+    accounts[7].key,
+    false,
+    None,
+  );
+  invoke_signed(&deposit, accounts, &[&[DEPOSITOR_SEED, &[bump]]])
+}
+
+#[tokio::test]
+async fn program_deposits_from_its_pda() {
+  let mut setup = Setup::new(1);
+  setup.program_test.add_program("mock_depositor", MOCK_PROGRAM_ID, processor!(mock_process_instruction));
+  let (depositor, _) = Pubkey::find_program_address(&[DEPOSITOR_SEED], &MOCK_PROGRAM_ID);
+  let source = Pubkey::new_unique();
+  // A data-less PDA with SOL can pay for its own user vault
+  setup.program_test.add_account(depositor, Account::new(1_000_000_000, 0, &system_program::id()));
+  common::add_token_account(&mut setup.program_test, source, setup.mint, depositor, TREASURY_TOKENS);
+  let env = setup.start_with_vault().await;
+
+  let (user_vault, _) = safe::pda::user_vault(&program_id(), &depositor, &env.vault_state);
+  let mock_deposit = Instruction {
+    program_id: MOCK_PROGRAM_ID,
+    accounts: vec![
+      AccountMeta::new(depositor, false),
+      AccountMeta::new(source, false),
+      AccountMeta::new(env.vault_token_account, false),
+      AccountMeta::new(env.vault_state, false),
+      AccountMeta::new(user_vault, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(system_program::id(), false),
+      AccountMeta::new_readonly(env.mint, false),
+      AccountMeta::new_readonly(program_id(), false),
+    ],
+    data: 1_200u64.to_le_bytes().to_vec(),
+  };
+  env.send(&[mock_deposit], &[]).await.unwrap();
+
+  let record = UserVault::unpack(&env.account(user_vault).await.unwrap().data).unwrap();
+  assert_eq!((record.user, record.deposited_amount), (depositor, 1_200));
+  assert_eq!(env.token_balance(source).await, TREASURY_TOKENS - 1_200);
+  assert_eq!(env.vault().await.total_deposits, 1_200);
+
+  // Nobody but the mock program can sign for its PDA
+  let mut direct = instruction::deposit(
+    &program_id(),
+    100,
+    &depositor,
+    &source,
+    &env.vault_token_account,
+    &env.vault_state,
+    &spl_token::id(),
+    &env.mint,
+    false,
+    None,
+  );
+  direct.accounts[0].is_signer = false;
+  let err = env.send(&[direct], &[]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::MissingRequiredSignature));
+}
@@ -0,0 +1,66 @@
+// PreflightCheck reports every check a deposit or withdrawal would fail at once, using the same checks as the real thing
+mod common;
+
+use common::{program_id, vault_ix, Env, User};
+use safe::{instruction::VaultInstruction, pda, state};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::account::AccountSharedData;
+use spl_token::state::Account as TokenAccount;
+
+async fn preflight(env: &Env, user: &User, op: u8, amount: u64, whitelist_entry: Option<Pubkey>) -> u32 {
+  let mut keys = vec![user.key(), env.vault_state, env.user_vault_key(user), env.vault_token_account];
+  keys.extend(whitelist_entry);
+  let ix = vault_ix(VaultInstruction::PreflightCheck { op, amount }, &keys);
+  u32::from_le_bytes(env.return_data(ix).await.try_into().unwrap())
+}
+
+#[tokio::test]
+async fn deposit_reports_every_failing_check() {
+  let env = common::Setup::new(2).start_with_vault().await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  let whitelist_entry = pda::whitelist(&program_id(), &env.vault_state, &user.key()).0;
+  assert_eq!(preflight(&env, user, state::PREFLIGHT_OP_DEPOSIT, 500, None).await, 0);
+
+  env.configure(VaultInstruction::SetPaused { paused: true }).await;
+  env.configure(VaultInstruction::SetMaxSingleDeposit { max_single_deposit: 100 }).await;
+  env.configure(VaultInstruction::SetRequireWhitelisted { require_whitelisted: true }).await;
+
+  let expected = state::PREFLIGHT_PAUSED | state::PREFLIGHT_SINGLE_DEPOSIT_LIMIT | state::PREFLIGHT_NOT_WHITELISTED;
+  assert_eq!(preflight(&env, user, state::PREFLIGHT_OP_DEPOSIT, 500, None).await, expected);
+  assert_eq!(preflight(&env, user, state::PREFLIGHT_OP_DEPOSIT, 500, Some(whitelist_entry)).await, expected);
+
+  let add = vault_ix(
+    VaultInstruction::AddToWhitelist,
+    &[owner.key(), env.vault_state, user.key(), whitelist_entry, solana_program::system_program::id()],
+  );
+  env.send(&[add], &[&owner.keypair]).await.unwrap();
+  let expected = state::PREFLIGHT_PAUSED | state::PREFLIGHT_SINGLE_DEPOSIT_LIMIT;
+  assert_eq!(preflight(&env, user, state::PREFLIGHT_OP_DEPOSIT, 500, Some(whitelist_entry)).await, expected);
+}
+
+#[tokio::test]
+async fn withdraw_reports_every_failing_check() {
+  let mut env = common::Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+  assert_eq!(preflight(&env, user, state::PREFLIGHT_OP_WITHDRAW, 1_000, None).await, 0);
+
+  env.configure(VaultInstruction::SetMaxVaultWithdrawPerWindow { max_vault_withdraw_per_window: 300 }).await;
+  env.configure(VaultInstruction::SetMinWithdraw { min_withdraw: 600 }).await;
+
+  // Most of the deposits are out of the token account
+  let mut account = env.account(env.vault_token_account).await.unwrap();
+  let mut token = TokenAccount::unpack(&account.data).unwrap();
+  token.amount = 400;
+  TokenAccount::pack(token, &mut account.data).unwrap();
+  let vault_token_account = env.vault_token_account;
+  env.context.set_account(&vault_token_account, &AccountSharedData::from(account));
+
+  let user = &env.users[1];
+  let expected =
+    state::PREFLIGHT_BELOW_MIN_WITHDRAW | state::PREFLIGHT_VAULT_RATE_LIMITED | state::PREFLIGHT_INSUFFICIENT_LIQUIDITY;
+  assert_eq!(preflight(&env, user, state::PREFLIGHT_OP_WITHDRAW, 500, None).await, expected);
+
+  // A real withdrawal of the same amount is refused too
+  assert!(env.send(&[env.withdraw_ix(user, 500)], &[&user.keypair]).await.is_err());
+}
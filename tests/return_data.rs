@@ -0,0 +1,46 @@
+// Read-style instructions hand their result back through set_return_data, or with the return-data feature off through
+// a hex-encoded "return-data:" log line; each build is tested for the mechanism it uses
+mod common;
+
+use common::{vault_ix, Env, Setup};
+use safe::instruction::VaultInstruction;
+use solana_sdk::transaction_context::TransactionReturnData;
+
+// users[1] has deposited, so GetUserCount returns 1
+async fn start() -> Env {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 10)], &[&user.keypair]).await.unwrap();
+  env
+}
+
+// The simulated GetUserCount's return data and logs
+async fn simulate_get_user_count(env: &Env) -> (Option<TransactionReturnData>, Vec<String>) {
+  let transaction = env.transaction(&[vault_ix(VaultInstruction::GetUserCount, &[env.vault_state])], &[]).await;
+  let simulation = env.context.banks_client.clone().simulate_transaction(transaction).await.unwrap();
+  simulation.result.unwrap().unwrap();
+  let details = simulation.simulation_details.unwrap();
+  (details.return_data, details.logs)
+}
+
+#[cfg(feature = "return-data")]
+#[tokio::test]
+async fn read_comes_back_as_return_data() {
+  let env = start().await;
+  let (return_data, logs) = simulate_get_user_count(&env).await;
+
+  let return_data = return_data.unwrap();
+  assert_eq!(return_data.program_id, common::program_id());
+  assert_eq!(return_data.data, 1u64.to_le_bytes());
+  assert!(!logs.iter().any(|log| log.contains("return-data:")));
+}
+
+#[cfg(not(feature = "return-data"))]
+#[tokio::test]
+async fn read_comes_back_as_a_log_line() {
+  let env = start().await;
+  let (return_data, logs) = simulate_get_user_count(&env).await;
+
+  assert!(return_data.is_none());
+  assert!(logs.contains(&"Program log: return-data: 0100000000000000".to_string()));
+}
@@ -0,0 +1,68 @@
+// Once SetAdmins stores an admin set with a threshold, owner-gated instructions also need that many distinct admins
+// to sign, passed after the instruction's own accounts
+mod common;
+
+use common::{custom, vault_ix, Env, User};
+use safe::{error::VaultError, instruction::VaultInstruction};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+
+// Pause the vault as the owner, with `admins` appended as extra signers
+fn pause_ix(env: &Env, paused: bool, admins: &[&User]) -> Instruction {
+  let mut ix = vault_ix(VaultInstruction::SetPaused { paused }, &[env.owner().key(), env.vault_state]);
+  ix.accounts.extend(admins.iter().map(|admin| AccountMeta::new_readonly(admin.key(), true)));
+  ix
+}
+
+// users[1] and users[2] are admins and two of them must sign; users[3] is not an admin
+async fn start() -> Env {
+  let env = common::Setup::new(4).start_with_vault().await;
+  env
+    .configure(VaultInstruction::SetAdmins {
+      admins: [env.users[1].key(), env.users[2].key(), Pubkey::default()],
+      admin_threshold: 2,
+    })
+    .await;
+  env
+}
+
+#[tokio::test]
+async fn threshold_met() {
+  let env = start().await;
+  let (owner, first, second) = (env.owner(), &env.users[1], &env.users[2]);
+
+  env.send(&[pause_ix(&env, true, &[first, second])], &[&owner.keypair, &first.keypair, &second.keypair]).await.unwrap();
+  assert!(env.vault().await.paused);
+}
+
+#[tokio::test]
+async fn threshold_not_met() {
+  let env = start().await;
+  let (owner, first, outsider) = (env.owner(), &env.users[1], &env.users[3]);
+
+  // The owner alone, now that the threshold is set
+  let err = env.send(&[pause_ix(&env, true, &[])], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::ThresholdNotMet));
+
+  // One admin short
+  let err = env.send(&[pause_ix(&env, true, &[first])], &[&owner.keypair, &first.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::ThresholdNotMet));
+
+  // A signer outside the admin set doesn't count
+  let err = env
+    .send(&[pause_ix(&env, true, &[first, outsider])], &[&owner.keypair, &first.keypair, &outsider.keypair])
+    .await
+    .unwrap_err()
+    .unwrap();
+  assert_eq!(err, custom(VaultError::ThresholdNotMet));
+
+  // Nor does listing the same admin twice
+  let err = env
+    .send(&[pause_ix(&env, true, &[first, first])], &[&owner.keypair, &first.keypair])
+    .await
+    .unwrap_err()
+    .unwrap();
+  assert_eq!(err, custom(VaultError::ThresholdNotMet));
+
+  assert!(!env.vault().await.paused);
+}
@@ -0,0 +1,21 @@
+// SetCooldownExempt lets one account withdraw during the lock after a deposit, while everyone else waits it out
+mod common;
+
+use common::{custom, Setup, USER_TOKENS};
+use safe::{error::VaultError, instruction::VaultInstruction};
+use solana_program::pubkey::Pubkey;
+
+#[tokio::test]
+async fn exempt_user_withdraws_during_the_cooldown() {
+  let env = Setup::new(3).start_with_config(0, 0, Pubkey::default(), 3_600).await;
+  let (rebalancer, user) = (&env.users[1], &env.users[2]);
+  env.configure(VaultInstruction::SetCooldownExempt { exempt: rebalancer.key() }).await;
+  env.send(&[env.deposit_ix(rebalancer, 500), env.deposit_ix(user, 500)], &[&rebalancer.keypair, &user.keypair]).await.unwrap();
+
+  env.send(&[env.withdraw_ix(rebalancer, 500)], &[&rebalancer.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(rebalancer.token_account).await, USER_TOKENS);
+
+  let err = env.send(&[env.withdraw_ix(user, 500)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Locked));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 500);
+}
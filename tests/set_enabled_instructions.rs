@@ -0,0 +1,35 @@
+// SetEnabledInstructions switches individual instructions off for one vault, leaving the rest of it working
+mod common;
+
+use common::{custom, vault_ix, Setup, USER_TOKENS};
+use safe::{error::VaultError, instruction::VaultInstruction};
+
+#[tokio::test]
+async fn disabling_deposit_keeps_withdraw_working() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 300)], &[&user.keypair]).await.unwrap();
+
+  let deposit_bit = 1u64 << VaultInstruction::Deposit { amount: 0, memo: None }.tag();
+  env.configure(VaultInstruction::SetEnabledInstructions { enabled_instructions: !deposit_bit }).await;
+
+  let err = env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::InstructionDisabled));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 300);
+
+  // A deprecated vault still lets everyone out
+  env.send(&[env.withdraw_ix(user, 300)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+  assert_eq!(env.vault().await.total_deposits, 0);
+}
+
+#[tokio::test]
+async fn only_the_owner_sets_the_mask() {
+  let env = Setup::new(2).start_with_vault().await;
+  let stranger = &env.users[1];
+
+  let ix = vault_ix(VaultInstruction::SetEnabledInstructions { enabled_instructions: 0 }, &[stranger.key(), env.vault_state]);
+  let err = env.send(&[ix], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  env.send(&[env.deposit_ix(stranger, 100)], &[&stranger.keypair]).await.unwrap();
+}
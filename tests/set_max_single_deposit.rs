@@ -0,0 +1,34 @@
+// SetMaxSingleDeposit caps each deposit on its own, independently of the vault's total cap
+mod common;
+
+use common::{custom, vault_ix, Setup};
+use safe::{error::VaultError, instruction::VaultInstruction};
+
+#[tokio::test]
+async fn deposits_at_below_and_above_the_limit() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.configure(VaultInstruction::SetMaxSingleDeposit { max_single_deposit: 100 }).await;
+
+  env.send(&[env.deposit_ix(user, 99)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  let err = env.send(&[env.deposit_ix(user, 101)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::SingleDepositTooLarge));
+
+  // The limit is per deposit: the position itself can keep growing past it
+  assert_eq!(env.user_vault(user).await.deposited_amount, 199);
+
+  env.configure(VaultInstruction::SetMaxSingleDeposit { max_single_deposit: 0 }).await;
+  env.send(&[env.deposit_ix(user, 1_000)], &[&user.keypair]).await.unwrap();
+}
+
+#[tokio::test]
+async fn only_the_owner_sets_the_limit() {
+  let env = Setup::new(2).start_with_vault().await;
+  let stranger = &env.users[1];
+
+  let ix = vault_ix(VaultInstruction::SetMaxSingleDeposit { max_single_deposit: 1 }, &[stranger.key(), env.vault_state]);
+  let err = env.send(&[ix], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  assert_eq!(env.vault().await.max_single_deposit, 0);
+}
@@ -0,0 +1,62 @@
+// A vault's name is set by InitVault and changed with SetName, stored as UTF-8 padded with zero bytes to 32
+mod common;
+
+use common::{custom, instruction_error, program_id, vault_ix, Setup};
+use safe::{error::VaultError, instruction::{self, VaultInstruction}, state::trim_name};
+use solana_sdk::instruction::InstructionError;
+
+fn padded(name: &str) -> [u8; 32] {
+  let mut padded = [0; 32];
+  padded[..name.len()].copy_from_slice(name.as_bytes());
+  padded
+}
+
+#[tokio::test]
+async fn name_from_init_then_renamed() {
+  let env = Setup::new(2).start().await;
+  let owner = env.owner();
+  let init = instruction::init_vault(
+    &program_id(),
+    &owner.key(),
+    &env.mint,
+    &env.vault_token_account,
+    &spl_token::id(),
+    padded("Main USDC vault"),
+    0,
+    0,
+    &Default::default(),
+    0,
+  );
+  env.send(&[init], &[&owner.keypair]).await.unwrap();
+  assert_eq!(trim_name(&env.vault().await.name), b"Main USDC vault");
+
+  // A name using all 32 bytes has no padding to trim
+  let full = "Vault name that fills every byte";
+  assert_eq!(full.len(), 32);
+  env.configure(VaultInstruction::SetName { name: padded(full) }).await;
+  assert_eq!(trim_name(&env.vault().await.name), full.as_bytes());
+
+  // A shorter name replaces every byte of the longer one
+  env.configure(VaultInstruction::SetName { name: padded("Treasury") }).await;
+  let name = env.vault().await.name;
+  assert_eq!(name, padded("Treasury"));
+  assert_eq!(std::str::from_utf8(trim_name(&name)).unwrap(), "Treasury");
+}
+
+#[tokio::test]
+async fn only_the_owner_renames_and_only_to_utf8() {
+  let env = Setup::new(2).start_with_vault().await;
+  let stranger = &env.users[1];
+
+  let rename = vault_ix(VaultInstruction::SetName { name: padded("Mine now") }, &[stranger.key(), env.vault_state]);
+  let err = env.send(&[rename], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+
+  let mut invalid = padded("Name");
+  invalid[4] = 0xff;
+  let owner = env.owner();
+  let rename = vault_ix(VaultInstruction::SetName { name: invalid }, &[owner.key(), env.vault_state]);
+  let err = env.send(&[rename], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidInstructionData));
+  assert_eq!(env.vault().await.name, [0; 32]);
+}
@@ -0,0 +1,50 @@
+// Trailing accounts an instruction never reads are ignored by default, so callers can append accounts freely; with the
+// strict-accounts feature they're rejected with TooManyAccounts. Each build is tested for the mode it uses
+mod common;
+
+use common::{vault_ix, Env, Setup};
+use safe::instruction::{self, VaultInstruction};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+
+// A deposit carrying every optional account slot plus one more, and a GetUserCount with one account too many
+fn with_extra_accounts(env: &Env) -> [Instruction; 2] {
+  let user = &env.users[1];
+  let mut deposit = env.deposit_ix(user, 100);
+  let max = instruction::max_accounts(&VaultInstruction::Deposit { amount: 100, memo: None }).unwrap();
+  while deposit.accounts.len() <= max {
+    deposit.accounts.push(AccountMeta::new_readonly(Pubkey::new_unique(), false));
+  }
+
+  let mut get_user_count = vault_ix(VaultInstruction::GetUserCount, &[env.vault_state]);
+  get_user_count.accounts.push(AccountMeta::new_readonly(Pubkey::new_unique(), false));
+
+  [deposit, get_user_count]
+}
+
+#[cfg(feature = "strict-accounts")]
+#[tokio::test]
+async fn trailing_accounts_are_rejected() {
+  use common::custom;
+  use safe::error::VaultError;
+
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  let [deposit, get_user_count] = with_extra_accounts(&env);
+  let err = env.send(&[deposit], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::TooManyAccounts));
+  let err = env.send(&[get_user_count], &[]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::TooManyAccounts));
+  assert_eq!(env.vault().await.total_deposits, 0);
+}
+
+#[cfg(not(feature = "strict-accounts"))]
+#[tokio::test]
+async fn trailing_accounts_are_ignored() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  env.send(&with_extra_accounts(&env), &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
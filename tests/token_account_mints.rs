@@ -0,0 +1,79 @@
+// Every token account Deposit and Withdraw move tokens through has to hold the vault's mint, so a token of another
+// mint can never be sneaked into the vault's accounting
+mod common;
+
+use common::{custom, instruction_error, Env, Setup, USER_TOKENS};
+use safe::error::VaultError;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{account::AccountSharedData, instruction::InstructionError};
+use spl_token::state::Account as TokenAccount;
+
+// users[1] also holds USER_TOKENS of another mint, in the returned token account
+async fn start() -> (Env, Pubkey) {
+  let mut setup = Setup::new(2);
+  let (other_mint, other_token_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+  common::add_mint(&mut setup.program_test, other_mint, USER_TOKENS);
+  let owner = setup.users[1].key();
+  common::add_token_account(&mut setup.program_test, other_token_account, other_mint, owner, USER_TOKENS);
+  (setup.start_with_vault().await, other_token_account)
+}
+
+// Point the vault's own token account at another mint, as if it had been set up wrong
+async fn switch_vault_token_mint(env: &mut Env) {
+  let mut account = env.account(env.vault_token_account).await.unwrap();
+  let mut token = TokenAccount::unpack(&account.data).unwrap();
+  token.mint = Pubkey::new_unique();
+  TokenAccount::pack(token, &mut account.data).unwrap();
+  env.context.set_account(&env.vault_token_account, &AccountSharedData::from(account));
+}
+
+#[tokio::test]
+async fn deposit_from_a_source_of_another_mint() {
+  let (env, other_token_account) = start().await;
+  let user = &env.users[1];
+
+  let mut ix = env.deposit_ix(user, 100);
+  ix.accounts[1].pubkey = other_token_account;
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+  assert_eq!(env.token_balance(other_token_account).await, USER_TOKENS);
+  assert_eq!(env.vault().await.total_deposits, 0);
+}
+
+#[tokio::test]
+async fn deposit_into_a_vault_token_account_of_another_mint() {
+  let (mut env, _) = start().await;
+  switch_vault_token_mint(&mut env).await;
+  let user = &env.users[1];
+
+  let err = env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+  assert_eq!(env.vault().await.total_deposits, 0);
+}
+
+#[tokio::test]
+async fn withdraw_from_a_vault_token_account_of_another_mint() {
+  let (mut env, _) = start().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  switch_vault_token_mint(&mut env).await;
+
+  let user = &env.users[1];
+  let err = env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
+
+#[tokio::test]
+async fn withdraw_to_a_destination_of_another_mint() {
+  let (env, other_token_account) = start().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  // The destination gets its own, more specific error than the vault-side accounts
+  let mut ix = env.withdraw_ix(user, 100);
+  ix.accounts[2].pubkey = other_token_account;
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::MintMismatch));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
@@ -0,0 +1,67 @@
+// A transfer the token program rejects fails the whole instruction with the token program's own error, and none of
+// the vault's accounting survives it
+mod common;
+
+use common::{Env, Setup, USER_TOKENS};
+use solana_program::{instruction::InstructionError, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{account::AccountSharedData, transaction::TransactionError};
+use spl_token::{
+  error::TokenError,
+  state::{Account as TokenAccount, AccountState},
+};
+
+fn token_error(error: TokenError) -> TransactionError {
+  TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+async fn freeze(env: &mut Env, token_account: Pubkey) {
+  let mut account = env.account(token_account).await.unwrap();
+  let mut state = TokenAccount::unpack(&account.data).unwrap();
+  state.state = AccountState::Frozen;
+  TokenAccount::pack(state, &mut account.data).unwrap();
+  env.context.set_account(&token_account, &AccountSharedData::from(account));
+}
+
+#[tokio::test]
+async fn deposit_over_the_source_balance() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  let err = env.send(&[env.deposit_ix(user, USER_TOKENS + 1)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, token_error(TokenError::InsufficientFunds));
+
+  assert_eq!(env.vault().await.total_deposits, 0);
+  assert_eq!(env.vault().await.user_count, 0);
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+}
+
+#[tokio::test]
+async fn deposit_from_a_frozen_account() {
+  let mut env = Setup::new(2).start_with_vault().await;
+  env.send(&[env.deposit_ix(&env.users[1], 100)], &[&env.users[1].keypair]).await.unwrap();
+  let source = env.users[1].token_account;
+  freeze(&mut env, source).await;
+
+  let user = &env.users[1];
+  let err = env.send(&[env.deposit_ix(user, 50)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, token_error(TokenError::AccountFrozen));
+
+  assert_eq!(env.vault().await.total_deposits, 100);
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
+
+#[tokio::test]
+async fn withdraw_to_a_frozen_account() {
+  let mut env = Setup::new(2).start_with_vault().await;
+  env.send(&[env.deposit_ix(&env.users[1], 100)], &[&env.users[1].keypair]).await.unwrap();
+  let destination = env.users[1].token_account;
+  freeze(&mut env, destination).await;
+
+  let user = &env.users[1];
+  let err = env.send(&[env.withdraw_ix(user, 40)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, token_error(TokenError::AccountFrozen));
+
+  assert_eq!(env.vault().await.total_deposits, 100);
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 100);
+}
@@ -0,0 +1,73 @@
+// Deposit and Withdraw only ever CPI into the token program the vault was initialized with, which must be SPL Token,
+// so a look-alike passed in its place is refused before any accounting is written
+mod common;
+
+use common::{instruction_error, Setup, USER_TOKENS};
+use safe::state::Vault;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{account::AccountSharedData, instruction::InstructionError};
+
+const TOKEN_PROGRAM_INDEX: usize = 5;                       // Same position in the Deposit and Withdraw account lists
+
+#[tokio::test]
+async fn deposit_with_a_substituted_token_program() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  let mut ix = env.deposit_ix(user, 100);
+  ix.accounts[TOKEN_PROGRAM_INDEX].pubkey = Pubkey::new_unique();
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IncorrectProgramId));
+
+  assert_eq!(env.vault().await.total_deposits, 0);
+  assert!(env.account(env.user_vault_key(user)).await.is_none());
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+}
+
+#[tokio::test]
+async fn withdraw_with_a_substituted_token_program() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  let mut ix = env.withdraw_ix(user, 100);
+  ix.accounts[TOKEN_PROGRAM_INDEX].pubkey = Pubkey::new_unique();
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IncorrectProgramId));
+
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+  assert_eq!(env.vault().await.total_deposits, 100);
+}
+
+#[tokio::test]
+async fn vault_is_pinned_to_the_token_program_it_was_initialized_with() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  assert_eq!(env.vault().await.allowed_token_program, spl_token::id());
+
+  // The allowed program round trips
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.withdraw_ix(user, 40)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 60);
+}
+
+#[tokio::test]
+async fn token_program_other_than_the_allowed_one_is_refused() {
+  let mut env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  // Pin the vault to some other program: even the real SPL Token program no longer matches the allow-list
+  let mut account = env.account(env.vault_state).await.unwrap();
+  let mut vault = Vault::unpack(&account.data).unwrap();
+  vault.allowed_token_program = Pubkey::new_unique();
+  Vault::pack(vault, &mut account.data).unwrap();
+  env.context.set_account(&env.vault_state, &AccountSharedData::from(account));
+
+  let user = &env.users[1];
+  let err = env.send(&[env.deposit_ix(user, 50)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IncorrectProgramId));
+  let err = env.send(&[env.withdraw_ix(user, 50)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IncorrectProgramId));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
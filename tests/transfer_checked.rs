@@ -0,0 +1,63 @@
+// Deposit and Withdraw move tokens with transfer_checked, passing the decimals of the vault's own mint account, so the
+// token program checks every amount against the mint it belongs to
+mod common;
+
+use common::{custom, Setup, DECIMALS, USER_TOKENS};
+use safe::error::VaultError;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{account::Account, instruction::InstructionError, transaction::TransactionError};
+use spl_token::{error::TokenError, state::Mint};
+
+#[tokio::test]
+async fn token_program_rejects_mismatched_decimals() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  // The vault's own transfer with any other decimals than the mint's is refused by the token program itself
+  let transfer = spl_token::instruction::transfer_checked(
+    &spl_token::id(),
+    &user.token_account,
+    &env.mint,
+    &env.vault_token_account,
+    &user.key(),
+    &[],
+    100,
+    DECIMALS + 1,
+  )
+  .unwrap();
+  let err = env.send(&[transfer], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, TransactionError::InstructionError(0, InstructionError::Custom(TokenError::MintDecimalsMismatch as u32)));
+
+  // While the vault, reading the decimals off the mint, gets them right
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  env.send(&[env.withdraw_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+}
+
+#[tokio::test]
+async fn mint_with_other_decimals_is_refused() {
+  let mut setup = Setup::new(2);
+  // A mint whose decimals would make the vault pass a different scale than its own mint's
+  let other_mint = Pubkey::new_unique();
+  let mut data = vec![0; Mint::LEN];
+  let mint = Mint { decimals: DECIMALS + 3, is_initialized: true, ..Mint::default() };
+  Mint::pack(mint, &mut data).unwrap();
+  let account = Account { lamports: 1_000_000_000, data, owner: spl_token::id(), ..Account::default() };
+  setup.program_test.add_account(other_mint, account);
+  let env = setup.start_with_vault().await;
+  let user = &env.users[1];
+
+  let mut ix = env.deposit_ix(user, 100);
+  let mint_meta = ix.accounts.iter_mut().find(|meta| meta.pubkey == env.mint).unwrap();
+  mint_meta.pubkey = other_mint;
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::MintMismatch));
+
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  let mut ix = env.withdraw_ix(user, 100);
+  let mint_meta = ix.accounts.iter_mut().find(|meta| meta.pubkey == env.mint).unwrap();
+  mint_meta.pubkey = other_mint;
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::MintMismatch));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
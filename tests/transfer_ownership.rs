@@ -0,0 +1,46 @@
+// TransferOwnership hands every owner-only power to the new key and takes it from the old one
+mod common;
+
+use common::{custom, vault_ix, Env, Setup, User};
+use safe::{error::VaultError, instruction::VaultInstruction};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::instruction::Instruction;
+
+fn transfer_ownership_ix(env: &Env, signer: &User, new_owner: Pubkey) -> Instruction {
+  vault_ix(VaultInstruction::TransferOwnership, &[signer.key(), env.vault_state, new_owner])
+}
+
+#[tokio::test]
+async fn new_owner_takes_over() {
+  let env = Setup::new(2).start_with_vault().await;
+  let (old_owner, new_owner) = (env.owner(), &env.users[1]);
+
+  env.send(&[transfer_ownership_ix(&env, old_owner, new_owner.key())], &[&old_owner.keypair]).await.unwrap();
+  assert_eq!(env.vault().await.owner, new_owner.key());
+
+  // Owner-only instructions now answer to the new key, and no longer to the old one
+  let pause = |signer: &User| vault_ix(VaultInstruction::SetPaused { paused: true }, &[signer.key(), env.vault_state]);
+  let err = env.send(&[pause(old_owner)], &[&old_owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  env.send(&[pause(new_owner)], &[&new_owner.keypair]).await.unwrap();
+  assert!(env.vault().await.paused);
+}
+
+#[tokio::test]
+async fn refuses_a_non_owner() {
+  let env = Setup::new(2).start_with_vault().await;
+  let stranger = &env.users[1];
+
+  let err = env.send(&[transfer_ownership_ix(&env, stranger, stranger.key())], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  assert_eq!(env.vault().await.owner, env.owner().key());
+}
+
+#[tokio::test]
+async fn refuses_the_current_owner_as_the_new_one() {
+  let env = Setup::new(1).start_with_vault().await;
+  let owner = env.owner();
+
+  let err = env.send(&[transfer_ownership_ix(&env, owner, owner.key())], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::OwnerUnchanged));
+}
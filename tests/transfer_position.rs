@@ -0,0 +1,39 @@
+// TransferPosition moves deposited balance between two users' records without any token leaving the vault
+mod common;
+
+use common::{vault_ix, Env, Setup, User, USER_TOKENS};
+use safe::instruction::VaultInstruction;
+use solana_sdk::instruction::Instruction;
+
+fn transfer_position_ix(env: &Env, from: &User, to: &User, amount: u64) -> Instruction {
+  vault_ix(
+    VaultInstruction::TransferPosition { amount, to: to.key() },
+    &[from.key(), env.user_vault_key(from), env.user_vault_key(to), env.vault_state, solana_program::system_program::id()],
+  )
+}
+
+#[tokio::test]
+async fn partial_then_full_transfer() {
+  let env = Setup::new(3).start_with_vault().await;
+  let (from, to) = (&env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(from, 1_000)], &[&from.keypair]).await.unwrap();
+
+  // The recipient never deposited, so their record is created by the transfer
+  env.send(&[transfer_position_ix(&env, from, to, 300)], &[&from.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(from).await.deposited_amount, 700);
+  assert_eq!(env.user_vault(to).await.deposited_amount, 300);
+  assert_eq!(env.vault().await.user_count, 2);
+
+  env.send(&[transfer_position_ix(&env, from, to, 700)], &[&from.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(from).await.deposited_amount, 0);
+  assert_eq!(env.user_vault(to).await.deposited_amount, 1_000);
+
+  // Nothing moved in tokens: the vault still holds and records the same total
+  assert_eq!(env.vault().await.total_deposits, 1_000);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 1_000);
+  assert_eq!(env.token_balance(to.token_account).await, USER_TOKENS);
+
+  // The balance is now the recipient's to withdraw
+  env.send(&[env.withdraw_ix(to, 1_000)], &[&to.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(to.token_account).await, USER_TOKENS + 1_000);
+}
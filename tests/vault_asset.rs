@@ -0,0 +1,101 @@
+// An extra asset added with AddAsset is deposited and withdrawn on its own books, apart from the vault's own mint
+mod common;
+
+use common::{program_id, vault_ix, Env, Setup, User, USER_TOKENS};
+use safe::{instruction::VaultInstruction, pda, state::UserVault};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, system_program};
+use solana_sdk::instruction::Instruction;
+
+struct Asset {
+  mint: Pubkey,
+  token_account: Pubkey,                                      // The vault's, owned by the vault authority
+  user_token_account: Pubkey,                                 // The user's, funded with USER_TOKENS of the asset
+}
+
+impl Asset {
+  fn account(&self, env: &Env) -> Pubkey {
+    pda::vault_asset(&program_id(), &env.vault_state, &self.mint).0
+  }
+
+  fn position(&self, env: &Env, user: &User) -> Pubkey {
+    pda::user_vault(&program_id(), &user.key(), &self.account(env)).0
+  }
+
+  fn deposit_ix(&self, env: &Env, user: &User, amount: u64) -> Instruction {
+    vault_ix(
+      VaultInstruction::DepositAsset { amount },
+      &[
+        user.key(),
+        self.user_token_account,
+        self.token_account,
+        env.vault_state,
+        self.account(env),
+        self.position(env, user),
+        spl_token::id(),
+        system_program::id(),
+        self.mint,
+      ],
+    )
+  }
+
+  fn withdraw_ix(&self, env: &Env, user: &User, amount: u64) -> Instruction {
+    vault_ix(
+      VaultInstruction::WithdrawAsset { amount },
+      &[
+        user.key(),
+        self.token_account,
+        self.user_token_account,
+        env.vault_state,
+        self.account(env),
+        self.position(env, user),
+        spl_token::id(),
+        self.mint,
+        pda::vault_authority(&program_id()).0,
+      ],
+    )
+  }
+}
+
+// A vault over its own mint, plus a second mint registered as an asset, with users[1] holding some of both
+async fn start() -> (Env, Asset) {
+  let mut setup = Setup::new(2);
+  let asset = Asset { mint: Pubkey::new_unique(), token_account: Pubkey::new_unique(), user_token_account: Pubkey::new_unique() };
+  common::add_mint(&mut setup.program_test, asset.mint, USER_TOKENS);
+  let vault_authority = pda::vault_authority(&program_id()).0;
+  common::add_token_account(&mut setup.program_test, asset.token_account, asset.mint, vault_authority, 0);
+  let user = setup.users[1].key();
+  common::add_token_account(&mut setup.program_test, asset.user_token_account, asset.mint, user, USER_TOKENS);
+  let env = setup.start_with_vault().await;
+
+  let owner = env.owner();
+  let add = vault_ix(
+    VaultInstruction::AddAsset,
+    &[owner.key(), env.vault_state, asset.mint, asset.token_account, asset.account(&env), system_program::id()],
+  );
+  env.send(&[add], &[&owner.keypair]).await.unwrap();
+  (env, asset)
+}
+
+#[tokio::test]
+async fn two_mints_are_kept_apart() {
+  let (env, asset) = start().await;
+  let user = &env.users[1];
+
+  env.send(&[env.deposit_ix(user, 300), asset.deposit_ix(&env, user, 200)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 300);
+  assert_eq!(env.vault().await.total_deposits, 300);
+  assert_eq!(env.token_balance(asset.token_account).await, 200);
+
+  // Withdrawing the asset leaves the vault's own mint untouched, and the other way round
+  env.send(&[asset.withdraw_ix(&env, user, 150)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(asset.user_token_account).await, USER_TOKENS - 50);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 300);
+  assert_eq!(env.vault().await.total_deposits, 300);
+
+  env.send(&[env.withdraw_ix(user, 300)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+  assert_eq!(env.token_balance(asset.token_account).await, 50);
+
+  let position = UserVault::unpack(&env.account(asset.position(&env, user)).await.unwrap().data).unwrap();
+  assert_eq!(position.deposited_amount, 50);
+}
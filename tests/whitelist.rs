@@ -0,0 +1,62 @@
+// A vault with require_whitelisted only takes deposits from users the owner added with AddToWhitelist
+mod common;
+
+use common::{custom, program_id, vault_ix, Env, Setup, User};
+use safe::{error::VaultError, instruction::{self, VaultInstruction}, pda};
+use solana_program::system_program;
+use solana_sdk::instruction::Instruction;
+
+fn whitelisted_deposit_ix(env: &Env, user: &User, amount: u64) -> Instruction {
+  instruction::deposit(
+    &program_id(),
+    amount,
+    &user.key(),
+    &user.token_account,
+    &env.vault_token_account,
+    &env.vault_state,
+    &spl_token::id(),
+    &env.mint,
+    true,
+    None,
+  )
+}
+
+fn whitelist_ix(env: &Env, ix: VaultInstruction, user: &User) -> Instruction {
+  let owner = env.owner();
+  let entry = pda::whitelist(&program_id(), &env.vault_state, &user.key()).0;
+  let mut keys = vec![owner.key(), env.vault_state, user.key(), entry];
+  if ix == VaultInstruction::AddToWhitelist {
+    keys.push(system_program::id());
+  }
+  vault_ix(ix, &keys)
+}
+
+#[tokio::test]
+async fn allows_listed_users_and_denies_the_rest() {
+  let env = Setup::new(3).start_with_vault().await;
+  let (owner, listed, unlisted) = (env.owner(), &env.users[1], &env.users[2]);
+  env.configure(VaultInstruction::SetRequireWhitelisted { require_whitelisted: true }).await;
+  env.send(&[whitelist_ix(&env, VaultInstruction::AddToWhitelist, listed)], &[&owner.keypair]).await.unwrap();
+
+  env.send(&[whitelisted_deposit_ix(&env, listed, 100)], &[&listed.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(listed).await.deposited_amount, 100);
+
+  let err = env.send(&[whitelisted_deposit_ix(&env, unlisted, 100)], &[&unlisted.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  assert!(env.account(env.user_vault_key(unlisted)).await.is_none());
+
+  // Removing the entry stops further deposits, but the existing position can still leave
+  env.send(&[whitelist_ix(&env, VaultInstruction::RemoveFromWhitelist, listed)], &[&owner.keypair]).await.unwrap();
+  let err = env.send(&[whitelisted_deposit_ix(&env, listed, 50)], &[&listed.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::Unauthorized));
+  env.send(&[env.withdraw_ix(listed, 100)], &[&listed.keypair]).await.unwrap();
+}
+
+#[tokio::test]
+async fn anyone_deposits_while_the_flag_is_off() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
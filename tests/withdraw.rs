@@ -0,0 +1,247 @@
+// Withdraw only ever pays out the signer's own position, whoever else signs, every withdrawal from a fee-charging
+// vault pays at least the minimum fee, and an authority bump hint can save a search but never name another authority
+mod common;
+
+use common::{custom, instruction_error, program_id, Env, Setup, User, USER_TOKENS};
+use safe::{error::VaultError, instruction::{self, VaultInstruction}, pda, state::{UserVault, Vault}};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{
+  account::{Account, AccountSharedData},
+  instruction::{Instruction, InstructionError},
+};
+use spl_token::state::Account as TokenAccount;
+
+#[tokio::test]
+async fn owner_cannot_withdraw_a_users_position() {
+  let env = Setup::new(2).start_with_vault().await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  env.send(&[env.deposit_ix(user, 500)], &[&user.keypair]).await.unwrap();
+
+  // The owner signs for the user's record, paying out to their own token account
+  let mut ix = env.withdraw_ix(owner, 500);
+  ix.accounts[4].pubkey = env.user_vault_key(user);
+  let err = env.send(&[ix], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::InvalidPda));
+
+  assert_eq!(env.user_vault(user).await.deposited_amount, 500);
+  assert_eq!(env.token_balance(owner.token_account).await, USER_TOKENS);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 500);
+}
+
+fn withdraw_with_fee_ix(env: &Env, user: &User, amount: u64, fee_treasury: &Pubkey) -> Instruction {
+  instruction::withdraw(
+    &program_id(),
+    amount,
+    &user.key(),
+    &env.vault_token_account,
+    &user.token_account,
+    &env.vault_state,
+    &spl_token::id(),
+    &env.mint,
+    Some(fee_treasury),
+    None,
+  )
+}
+
+#[tokio::test]
+async fn small_withdrawals_and_dust_exits_pay_the_minimum_fee() {
+  let mut setup = Setup::new(3);
+  let fee_treasury = Pubkey::new_unique();
+  common::add_token_account(&mut setup.program_test, fee_treasury, setup.mint, Pubkey::new_unique(), 0);
+  let env = setup.start_with_fee(100, fee_treasury).await;                 // 1%, so anything under 100 rounds to 0
+  let (user, dust_user) = (&env.users[1], &env.users[2]);
+  env.configure(VaultInstruction::SetMinWithdraw { min_withdraw: 50 }).await;
+  env.send(&[env.deposit_ix(user, 1_000), env.deposit_ix(dust_user, 30)], &[&user.keypair, &dust_user.keypair]).await.unwrap();
+
+  let err = env.send(&[withdraw_with_fee_ix(&env, user, 49, &fee_treasury)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::WithdrawBelowMinimum));
+
+  // At the floor and up to the first whole unit of fee, the fee is the one-unit minimum
+  for (amount, treasury) in [(50, 1), (199, 2), (200, 4)] {
+    env.send(&[withdraw_with_fee_ix(&env, user, amount, &fee_treasury)], &[&user.keypair]).await.unwrap();
+    assert_eq!(env.token_balance(fee_treasury).await, treasury);
+  }
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 1_000 + 449 - 4);
+
+  // A full exit below the floor is allowed, and still pays the minimum fee
+  env.send(&[withdraw_with_fee_ix(&env, dust_user, 30, &fee_treasury)], &[&dust_user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(fee_treasury).await, 5);
+  assert_eq!(env.token_balance(dust_user.token_account).await, USER_TOKENS - 1);
+  assert_eq!(env.user_vault(dust_user).await.deposited_amount, 0);
+}
+
+#[tokio::test]
+async fn owner_withdraws_without_the_fee_but_only_from_their_own_position() {
+  let mut setup = Setup::new(2);
+  let fee_treasury = Pubkey::new_unique();
+  common::add_token_account(&mut setup.program_test, fee_treasury, setup.mint, Pubkey::new_unique(), 0);
+  let env = setup.start_with_fee(100, fee_treasury).await;
+  let (owner, user) = (env.owner(), &env.users[1]);
+  env.send(&[env.deposit_ix(owner, 1_000), env.deposit_ix(user, 1_000)], &[&owner.keypair, &user.keypair]).await.unwrap();
+
+  // The same 500 costs a user 1% and the owner nothing
+  env.send(&[withdraw_with_fee_ix(&env, user, 500, &fee_treasury)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS - 1_000 + 495);
+  assert_eq!(env.token_balance(fee_treasury).await, 5);
+
+  env.send(&[withdraw_with_fee_ix(&env, owner, 500, &fee_treasury)], &[&owner.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(owner.token_account).await, USER_TOKENS - 1_000 + 500);
+  assert_eq!(env.token_balance(fee_treasury).await, 5);
+
+  // Skipping the fee doesn't skip the accounting: the owner's record is debited, and can't go past what they deposited
+  assert_eq!(env.user_vault(owner).await.deposited_amount, 500);
+  let err = env.send(&[withdraw_with_fee_ix(&env, owner, 501, &fee_treasury)], &[&owner.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InsufficientFunds));
+  assert_eq!(env.vault().await.total_deposits, 1_000);
+}
+
+// A vault from before the bump was recorded at init, whose withdrawals derive the authority from the client's hint
+async fn start_without_recorded_bump() -> Env {
+  let mut env = Setup::new(2).start_with_vault().await;
+  let mut account = env.account(env.vault_state).await.unwrap();
+  let mut vault = Vault::unpack(&account.data).unwrap();
+  vault.vault_bump = 0;
+  Vault::pack(vault, &mut account.data).unwrap();
+  env.context.set_account(&env.vault_state, &AccountSharedData::from(account));
+  env
+}
+
+fn withdraw_with_bump(env: &Env, user: &User, amount: u64, authority_bump: u8) -> Instruction {
+  let mut ix = env.withdraw_ix(user, amount);
+  ix.data = VaultInstruction::Withdraw { amount, authority_bump: Some(authority_bump) }.pack();
+  ix
+}
+
+#[tokio::test]
+async fn correct_authority_bump_hint() {
+  let env = start_without_recorded_bump().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  let (_, bump) = pda::vault_authority(&program_id());
+  env.send(&[withdraw_with_bump(&env, user, 100, bump)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+}
+
+#[tokio::test]
+async fn incorrect_authority_bump_hint() {
+  let env = start_without_recorded_bump().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+  let (_, canonical) = pda::vault_authority(&program_id());
+  let derives = |bump: u8| Pubkey::create_program_address(&[pda::VAULT_AUTHORITY_SEED, &[bump]], &program_id()).is_ok();
+
+  // A bump that derives some other PDA is taken at its word, and that PDA isn't the vault authority passed in
+  let other = (0..canonical).rev().find(|&bump| derives(bump)).unwrap();
+  let err = env.send(&[withdraw_with_bump(&env, user, 100, other)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::InvalidPda));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+
+  // A bump that derives nothing falls back to the full search
+  let invalid = (0..=u8::MAX).find(|&bump| !derives(bump)).unwrap();
+  env.send(&[withdraw_with_bump(&env, user, 60, invalid)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 40);
+}
+
+#[tokio::test]
+async fn uninitialized_destination_is_refused_with_a_clear_error() {
+  let mut setup = Setup::new(2);
+  // Allocated for the token program, but never initialized
+  let destination = Pubkey::new_unique();
+  setup.program_test.add_account(
+    destination,
+    Account { lamports: 1_000_000_000, data: vec![0; TokenAccount::LEN], owner: spl_token::id(), ..Account::default() },
+  );
+  let env = setup.start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  let mut ix = env.withdraw_ix(user, 100);
+  ix.accounts[2].pubkey = destination;
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::DestinationNotInitialized));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
+
+#[tokio::test]
+async fn stranger_cannot_withdraw_another_users_balance() {
+  let mut env = Setup::new(3).start_with_vault().await;
+  let (user, stranger) = (&env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(user, 500)], &[&user.keypair]).await.unwrap();
+
+  // Passing the user's record fails the PDA derivation from the stranger's key
+  let mut ix = env.withdraw_ix(stranger, 500);
+  ix.accounts[4].pubkey = env.user_vault_key(user);
+  let err = env.send(&[ix], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::InvalidPda));
+
+  // A copy of the user's record planted at the stranger's own PDA still names the user, which the record check catches
+  let record = env.account(env.user_vault_key(user)).await.unwrap();
+  let stranger_vault = env.user_vault_key(&env.users[2]);
+  env.context.set_account(&stranger_vault, &AccountSharedData::from(record));
+  let stranger = &env.users[2];
+  let err = env.send(&[env.withdraw_ix(stranger, 500)], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::IllegalOwner));
+
+  // A record naming the stranger but another vault is refused too
+  let mut record = env.account(stranger_vault).await.unwrap();
+  let mut user_vault = UserVault::unpack(&record.data).unwrap();
+  (user_vault.user, user_vault.vault) = (stranger.key(), Pubkey::new_unique());
+  UserVault::pack(user_vault, &mut record.data).unwrap();
+  env.context.set_account(&stranger_vault, &AccountSharedData::from(record));
+  let err = env.send(&[env.withdraw_ix(stranger, 400)], &[&stranger.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+
+  assert_eq!(env.user_vault(&env.users[1]).await.deposited_amount, 500);
+  assert_eq!(env.token_balance(stranger.token_account).await, USER_TOKENS);
+}
+
+#[tokio::test]
+async fn zero_amount_is_refused() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  let err = env.send(&[env.withdraw_ix(user, 0)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::AmountZero));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+}
+
+#[tokio::test]
+async fn short_vault_token_account_is_a_liquidity_error() {
+  let mut env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 500)], &[&user.keypair]).await.unwrap();
+
+  // Funds deployed elsewhere: the position still records 500, but only 300 sit in the vault token account
+  let mut account = env.account(env.vault_token_account).await.unwrap();
+  let mut token = TokenAccount::unpack(&account.data).unwrap();
+  token.amount = 300;
+  TokenAccount::pack(token, &mut account.data).unwrap();
+  env.context.set_account(&env.vault_token_account, &AccountSharedData::from(account));
+
+  let user = &env.users[1];
+  let err = env.send(&[env.withdraw_ix(user, 400)], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, custom(VaultError::InsufficientVaultLiquidity));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 500);
+
+  // What the account does hold can still come out
+  env.send(&[env.withdraw_ix(user, 300)], &[&user.keypair]).await.unwrap();
+  assert_eq!(env.user_vault(user).await.deposited_amount, 200);
+}
+
+#[tokio::test]
+async fn vault_token_account_as_destination_is_refused() {
+  let env = Setup::new(2).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 100)], &[&user.keypair]).await.unwrap();
+
+  // A self-transfer would move nothing, yet still debit the position
+  let mut ix = env.withdraw_ix(user, 100);
+  ix.accounts[2].pubkey = env.vault_token_account;
+  let err = env.send(&[ix], &[&user.keypair]).await.unwrap_err().unwrap();
+  assert_eq!(err, instruction_error(InstructionError::InvalidAccountData));
+  assert_eq!(env.user_vault(user).await.deposited_amount, 100);
+  assert_eq!(env.vault().await.total_deposits, 100);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 100);
+}
@@ -0,0 +1,29 @@
+// WithdrawAll reads the position's balance on-chain and pays all of it out
+mod common;
+
+use common::{Env, Setup, User, USER_TOKENS};
+use safe::instruction::VaultInstruction;
+use solana_sdk::instruction::Instruction;
+
+// Same accounts as Withdraw; only the data differs
+fn withdraw_all_ix(env: &Env, user: &User) -> Instruction {
+  let mut ix = env.withdraw_ix(user, 0);
+  ix.data = VaultInstruction::WithdrawAll.pack();
+  ix
+}
+
+#[tokio::test]
+async fn drains_the_position() {
+  let env = Setup::new(3).start_with_vault().await;
+  let (user, other) = (&env.users[1], &env.users[2]);
+  env.send(&[env.deposit_ix(user, 300), env.deposit_ix(user, 450), env.deposit_ix(other, 200)], &[&user.keypair, &other.keypair])
+    .await
+    .unwrap();
+
+  env.send(&[withdraw_all_ix(&env, user)], &[&user.keypair]).await.unwrap();
+
+  assert_eq!(env.user_vault(user).await.deposited_amount, 0);
+  assert_eq!(env.token_balance(user.token_account).await, USER_TOKENS);
+  assert_eq!(env.vault().await.total_deposits, 200);                 // Decremented by exactly what the user held
+  assert_eq!(env.token_balance(env.vault_token_account).await, 200);
+}
@@ -0,0 +1,33 @@
+// WithdrawUnwrapped pays a wrapped-SOL vault out as native lamports by closing the user's wrapped-SOL account
+mod common;
+
+use common::{Env, Setup, User, USER_TOKENS};
+use safe::instruction::VaultInstruction;
+use solana_program::{program_pack::Pack, rent::Rent};
+use solana_sdk::instruction::Instruction;
+use spl_token::{native_mint, state::Account as TokenAccount};
+
+// Same accounts as Withdraw, paying into the user's own wrapped-SOL account
+fn withdraw_unwrapped_ix(env: &Env, user: &User, amount: u64) -> Instruction {
+  let mut ix = env.withdraw_ix(user, amount);
+  ix.accounts[0].is_writable = true;                                 // Receives the unwrapped lamports
+  ix.data = VaultInstruction::WithdrawUnwrapped { amount, authority_bump: None }.pack();
+  ix
+}
+
+#[tokio::test]
+async fn lands_as_lamports_in_the_wallet() {
+  let env = Setup::with_mint(2, native_mint::id()).start_with_vault().await;
+  let user = &env.users[1];
+  env.send(&[env.deposit_ix(user, 400_000)], &[&user.keypair]).await.unwrap();
+
+  let wallet_before = env.account(user.key()).await.unwrap().lamports;
+  env.send(&[withdraw_unwrapped_ix(&env, user, 400_000)], &[&user.keypair]).await.unwrap();
+
+  // The wrapped account is gone: its remaining balance, the withdrawal and its rent reserve all arrived as lamports
+  assert!(env.account(user.token_account).await.is_none());
+  let reserve = Rent::default().minimum_balance(TokenAccount::LEN);
+  assert_eq!(env.account(user.key()).await.unwrap().lamports, wallet_before + USER_TOKENS + reserve);
+  assert_eq!(env.user_vault(user).await.deposited_amount, 0);
+  assert_eq!(env.token_balance(env.vault_token_account).await, 0);
+}